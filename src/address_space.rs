@@ -0,0 +1,473 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{
+    CommOrExecRecord, CommonData, CpuMode, EventRecord, ForkOrExitRecord, Mmap2FileId, Mmap2Record,
+    MmapRecord,
+};
+
+/// A memory mapping, from an MMAP or MMAP2 record.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Mapping {
+    /// The start address of the mapping.
+    pub start: u64,
+    /// The end address of the mapping (exclusive).
+    pub end: u64,
+    /// The file offset which corresponds to `start`.
+    pub page_offset: u64,
+    /// The path of the mapped file, or a name like `[vdso]` or `//anon`.
+    pub path: Vec<u8>,
+    /// The inode or build ID of the mapped file, for MMAP2 records.
+    pub file_id: Option<Mmap2FileId>,
+    /// Whether the mapping is executable.
+    pub is_executable: bool,
+    /// The time at which the mapping was created, or at which it was cut down
+    /// to its current size because a part of it was replaced.
+    pub start_time: u64,
+    /// The time at which the mapping was replaced or unmapped, or `None` if
+    /// it is still mapped.
+    pub end_time: Option<u64>,
+}
+
+impl Mapping {
+    /// Whether the mapping contains `address` at `timestamp`.
+    fn contains(&self, address: u64, timestamp: u64) -> bool {
+        self.start <= address
+            && address < self.end
+            && self.start_time <= timestamp
+            && self.end_time.is_none_or(|end| timestamp < end)
+    }
+}
+
+/// The result of [`AddressSpaceTracker::resolve`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ResolvedAddress<'a> {
+    /// The mapping which contains the address.
+    pub mapping: &'a Mapping,
+    /// The offset of the address in the mapped file.
+    pub file_offset: u64,
+    /// The build ID of the mapped file, from the MMAP2 record or from
+    /// [`AddressSpaceTracker::add_build_id`].
+    pub build_id: Option<&'a [u8]>,
+}
+
+/// The mappings of one process, or of the kernel.
+#[derive(Debug, Clone, Default)]
+struct AddressSpace {
+    /// The current mappings, keyed by start address. They never overlap.
+    live: BTreeMap<u64, Mapping>,
+    /// Mappings which have been replaced or unmapped, keyed by start address.
+    /// Mappings with the same start address are in the order in which they
+    /// were removed.
+    retired: BTreeMap<u64, Vec<Mapping>>,
+    /// The size of the largest retired mapping. Only retired mappings which
+    /// start at most this far below an address can contain it.
+    max_retired_size: u64,
+    /// The latest timestamp at which this address space was changed.
+    last_timestamp: u64,
+}
+
+impl AddressSpace {
+    fn insert(&mut self, mapping: Mapping) {
+        self.remove_range(mapping.start, mapping.end, mapping.start_time);
+        self.live.insert(mapping.start, mapping);
+    }
+
+    /// Whether any live mapping overlaps `start..end`.
+    fn overlaps(&self, start: u64, end: u64) -> bool {
+        self.live
+            .range(..end)
+            .next_back()
+            .is_some_and(|(_, mapping)| mapping.end > start)
+    }
+
+    fn retire(&mut self, mut mapping: Mapping, timestamp: u64) {
+        mapping.end_time = Some(timestamp);
+        self.max_retired_size = self.max_retired_size.max(mapping.end - mapping.start);
+        self.retired.entry(mapping.start).or_default().push(mapping);
+    }
+
+    /// Unmap `start..end` at `timestamp`. Mappings which are only partially
+    /// covered are split, and the parts outside the range stay mapped.
+    fn remove_range(&mut self, start: u64, end: u64, timestamp: u64) {
+        self.last_timestamp = self.last_timestamp.max(timestamp);
+        // The live mappings don't overlap, so their end addresses are sorted
+        // just like their start addresses.
+        let overlapping: Vec<u64> = self
+            .live
+            .range(..end)
+            .rev()
+            .take_while(|(_, mapping)| mapping.end > start)
+            .map(|(&key, _)| key)
+            .collect();
+        for key in overlapping {
+            let Some(mapping) = self.live.remove(&key) else {
+                continue;
+            };
+            if mapping.start < start {
+                let mut before = mapping.clone();
+                before.end = start;
+                before.start_time = timestamp;
+                self.live.insert(before.start, before);
+            }
+            if end < mapping.end {
+                let mut after = mapping.clone();
+                after.page_offset += end - mapping.start;
+                after.start = end;
+                after.start_time = timestamp;
+                self.live.insert(after.start, after);
+            }
+            self.retire(mapping, timestamp);
+        }
+    }
+
+    fn clear(&mut self, timestamp: u64) {
+        self.last_timestamp = self.last_timestamp.max(timestamp);
+        for (_, mapping) in std::mem::take(&mut self.live) {
+            self.retire(mapping, timestamp);
+        }
+    }
+
+    fn find(&self, address: u64, timestamp: u64) -> Option<&Mapping> {
+        if let Some((_, mapping)) = self.live.range(..=address).next_back() {
+            if mapping.contains(address, timestamp) {
+                return Some(mapping);
+            }
+        }
+        let min_start = address.saturating_sub(self.max_retired_size);
+        self.retired
+            .range(min_start..=address)
+            .rev()
+            .flat_map(|(_, mappings)| mappings.iter().rev())
+            .find(|mapping| mapping.contains(address, timestamp))
+    }
+}
+
+/// Tracks the memory mappings of each process over time, from MMAP and MMAP2
+/// records, so that instruction pointers from samples can be resolved to
+/// files.
+///
+/// A new mapping replaces the parts of older mappings that it overlaps. An
+/// exec clears the address space of the process, and a FORK of a new process
+/// copies the mappings of its parent, except where the new process already
+/// has mappings, e.g. from MMAP records which perf synthesized before the
+/// FORK. Mappings with pid -1 describe the kernel and its modules; they are
+/// used for addresses in kernel mode.
+///
+/// Mappings are kept after they are replaced, so addresses can be resolved
+/// at any time in the recording, and records don't need to be fed in
+/// strict timestamp order as long as each address space sees its own
+/// records in order.
+#[derive(Debug, Clone, Default)]
+pub struct AddressSpaceTracker {
+    processes: HashMap<i32, AddressSpace>,
+    kernel: AddressSpace,
+    build_ids: HashMap<Vec<u8>, Vec<u8>>,
+}
+
+impl AddressSpaceTracker {
+    /// Create an empty tracker.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Register the build ID of the file at `path`, e.g. from the `BUILD_ID`
+    /// feature section. Used for mappings whose MMAP2 record doesn't carry a
+    /// build ID.
+    pub fn add_build_id(&mut self, path: &[u8], build_id: Vec<u8>) {
+        self.build_ids.insert(path.to_owned(), build_id);
+    }
+
+    /// Apply an MMAP, MMAP2, FORK, EXIT or exec COMM record. Other records
+    /// are ignored. The timestamp of MMAP and COMM records is taken from
+    /// `common_data`. If it's not available, MMAP records count as mapped
+    /// from time 0, and an exec ends the mappings of the process at the
+    /// latest timestamp which was seen for the process, so that the earlier
+    /// mappings can still be resolved up to that point.
+    pub fn handle_record(&mut self, record: &EventRecord, common_data: &CommonData) {
+        match record {
+            EventRecord::Mmap(record) => {
+                self.handle_mmap(record, common_data.timestamp.unwrap_or(0))
+            }
+            EventRecord::Mmap2(record) => {
+                self.handle_mmap2(record, common_data.timestamp.unwrap_or(0))
+            }
+            EventRecord::Fork(record) => self.handle_fork(record),
+            EventRecord::Exit(record) => self.handle_exit(record),
+            EventRecord::Comm(record) => {
+                let timestamp = common_data.timestamp.unwrap_or_else(|| {
+                    self.processes
+                        .get(&record.pid)
+                        .map_or(0, |address_space| address_space.last_timestamp)
+                });
+                self.handle_comm(record, timestamp)
+            }
+            _ => {}
+        }
+    }
+
+    /// Apply an MMAP record.
+    pub fn handle_mmap(&mut self, record: &MmapRecord, timestamp: u64) {
+        let mapping = Mapping {
+            start: record.address,
+            end: record.address.saturating_add(record.length),
+            page_offset: record.page_offset,
+            path: record.path.as_slice().into_owned(),
+            file_id: None,
+            is_executable: record.is_executable,
+            start_time: timestamp,
+            end_time: None,
+        };
+        self.address_space_mut(record.pid).insert(mapping);
+    }
+
+    /// Apply an MMAP2 record.
+    pub fn handle_mmap2(&mut self, record: &Mmap2Record, timestamp: u64) {
+        let mapping = Mapping {
+            start: record.address,
+            end: record.address.saturating_add(record.length),
+            page_offset: record.page_offset,
+            path: record.path.as_slice().into_owned(),
+            file_id: Some(record.file_id.clone()),
+            is_executable: record.protection & PROT_EXEC != 0,
+            start_time: timestamp,
+            end_time: None,
+        };
+        self.address_space_mut(record.pid).insert(mapping);
+    }
+
+    /// Apply a FORK record. If it creates a new process, the process gets
+    /// a copy of the mappings of its parent. Mappings which the new process
+    /// already has take precedence, and the mappings of an earlier process
+    /// with the same pid are kept for resolving earlier addresses.
+    pub fn handle_fork(&mut self, record: &ForkOrExitRecord) {
+        if record.pid == record.ppid {
+            // A new thread; it shares the address space of its process.
+            return;
+        }
+        let parent_mappings: Vec<Mapping> = match self.processes.get(&record.ppid) {
+            Some(parent) => parent.live.values().cloned().collect(),
+            None => Vec::new(),
+        };
+        let address_space = self.processes.entry(record.pid).or_default();
+        address_space.last_timestamp = address_space.last_timestamp.max(record.timestamp);
+        for mut mapping in parent_mappings {
+            if address_space.overlaps(mapping.start, mapping.end) {
+                continue;
+            }
+            mapping.start_time = record.timestamp;
+            address_space.live.insert(mapping.start, mapping);
+        }
+    }
+
+    /// Apply an EXIT record. When the main thread exits, all mappings of the
+    /// process end.
+    pub fn handle_exit(&mut self, record: &ForkOrExitRecord) {
+        if record.pid == record.tid {
+            if let Some(address_space) = self.processes.get_mut(&record.pid) {
+                address_space.clear(record.timestamp);
+            }
+        }
+    }
+
+    /// Apply a COMM record. An exec replaces the address space of the process,
+    /// so all its mappings end.
+    pub fn handle_comm(&mut self, record: &CommOrExecRecord, timestamp: u64) {
+        if record.is_execve {
+            if let Some(address_space) = self.processes.get_mut(&record.pid) {
+                address_space.clear(timestamp);
+            }
+        }
+    }
+
+    /// Remove the mappings in `address..address + length` of process `pid`,
+    /// e.g. for a `munmap` call. Pass -1 for kernel mappings.
+    pub fn unmap(&mut self, pid: i32, address: u64, length: u64, timestamp: u64) {
+        let end = address.saturating_add(length);
+        self.address_space_mut(pid)
+            .remove_range(address, end, timestamp);
+    }
+
+    /// Find the mapping which contained `address` in process `pid` at
+    /// `timestamp`. Addresses in kernel mode are looked up in the kernel
+    /// mappings; if the mode is unknown, the process is checked first.
+    pub fn resolve(
+        &self,
+        pid: i32,
+        address: u64,
+        timestamp: u64,
+        cpu_mode: CpuMode,
+    ) -> Option<ResolvedAddress<'_>> {
+        let process = self.processes.get(&pid);
+        let mapping = match cpu_mode {
+            CpuMode::Kernel => self.kernel.find(address, timestamp),
+            CpuMode::Unknown => process
+                .and_then(|process| process.find(address, timestamp))
+                .or_else(|| self.kernel.find(address, timestamp)),
+            _ => process?.find(address, timestamp),
+        }?;
+        let build_id = match &mapping.file_id {
+            Some(Mmap2FileId::BuildId(build_id)) => Some(&build_id[..]),
+            _ => self.build_ids.get(&mapping.path).map(|id| &id[..]),
+        };
+        Some(ResolvedAddress {
+            mapping,
+            file_offset: address - mapping.start + mapping.page_offset,
+            build_id,
+        })
+    }
+
+    fn address_space_mut(&mut self, pid: i32) -> &mut AddressSpace {
+        if pid == -1 {
+            &mut self.kernel
+        } else {
+            self.processes.entry(pid).or_default()
+        }
+    }
+}
+
+/// `PROT_EXEC` from `<sys/mman.h>`.
+const PROT_EXEC: u32 = 0x4;
+
+#[cfg(test)]
+mod test {
+    use super::AddressSpaceTracker;
+    use crate::{
+        CommOrExecRecord, CommonData, CpuMode, EventRecord, ForkOrExitRecord, MmapRecord, RawData,
+    };
+
+    fn mmap(pid: i32, address: u64, length: u64, page_offset: u64, path: &[u8]) -> MmapRecord<'_> {
+        MmapRecord {
+            pid,
+            tid: pid,
+            address,
+            length,
+            page_offset,
+            is_executable: true,
+            cpu_mode: CpuMode::User,
+            path: RawData::from(path),
+        }
+    }
+
+    fn resolve(
+        tracker: &AddressSpaceTracker,
+        pid: i32,
+        address: u64,
+        timestamp: u64,
+        cpu_mode: CpuMode,
+    ) -> Option<(&[u8], u64)> {
+        let resolved = tracker.resolve(pid, address, timestamp, cpu_mode)?;
+        Some((&resolved.mapping.path[..], resolved.file_offset))
+    }
+
+    #[test]
+    fn overlap_fork_and_kernel() {
+        let mut tracker = AddressSpaceTracker::new();
+        tracker.handle_mmap(&mmap(-1, 0xffff0000, 0x1000, 0, b"[kernel.kallsyms]"), 0);
+        tracker.handle_mmap(&mmap(10, 0x1000, 0x3000, 0, b"libfoo.so"), 0);
+        tracker.add_build_id(b"libfoo.so", vec![0xab; 20]);
+        // Replace the middle page at time 50.
+        tracker.handle_mmap(&mmap(10, 0x2000, 0x1000, 0x8000, b"libbar.so"), 50);
+
+        assert_eq!(
+            resolve(&tracker, 10, 0x2100, 10, CpuMode::User),
+            Some((&b"libfoo.so"[..], 0x1100))
+        );
+        assert_eq!(
+            resolve(&tracker, 10, 0x2100, 60, CpuMode::User),
+            Some((&b"libbar.so"[..], 0x8100))
+        );
+        assert_eq!(
+            resolve(&tracker, 10, 0x3100, 60, CpuMode::User),
+            Some((&b"libfoo.so"[..], 0x2100))
+        );
+        assert_eq!(
+            resolve(&tracker, 10, 0xffff0010, 60, CpuMode::Kernel),
+            Some((&b"[kernel.kallsyms]"[..], 0x10))
+        );
+        assert_eq!(resolve(&tracker, 10, 0xffff0010, 60, CpuMode::User), None);
+        let resolved = tracker.resolve(10, 0x1000, 60, CpuMode::User).unwrap();
+        assert_eq!(resolved.build_id, Some(&[0xab; 20][..]));
+
+        tracker.handle_fork(&ForkOrExitRecord {
+            pid: 20,
+            ppid: 10,
+            tid: 20,
+            ptid: 10,
+            timestamp: 100,
+        });
+        tracker.unmap(10, 0x1000, 0x3000, 110);
+        assert_eq!(resolve(&tracker, 10, 0x3100, 120, CpuMode::User), None);
+        assert_eq!(
+            resolve(&tracker, 20, 0x3100, 120, CpuMode::User),
+            Some((&b"libfoo.so"[..], 0x2100))
+        );
+    }
+
+    fn fork(pid: i32, ppid: i32, timestamp: u64) -> ForkOrExitRecord {
+        ForkOrExitRecord {
+            pid,
+            ppid,
+            tid: pid,
+            ptid: ppid,
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn exec_without_timestamp() {
+        let mut tracker = AddressSpaceTracker::new();
+        tracker.handle_mmap(&mmap(10, 0x1000, 0x1000, 0, b"before-exec"), 10);
+        tracker.handle_mmap(&mmap(10, 0x5000, 0x1000, 0, b"other"), 20);
+        let exec = EventRecord::Comm(CommOrExecRecord {
+            pid: 10,
+            tid: 10,
+            name: RawData::from(&b"new"[..]),
+            is_execve: true,
+        });
+        tracker.handle_record(&exec, &CommonData::default());
+        tracker.handle_mmap(&mmap(10, 0x1000, 0x1000, 0, b"after-exec"), 30);
+
+        assert_eq!(
+            resolve(&tracker, 10, 0x1000, 15, CpuMode::User),
+            Some((&b"before-exec"[..], 0))
+        );
+        assert_eq!(resolve(&tracker, 10, 0x5000, 25, CpuMode::User), None);
+        assert_eq!(
+            resolve(&tracker, 10, 0x1000, 35, CpuMode::User),
+            Some((&b"after-exec"[..], 0))
+        );
+    }
+
+    #[test]
+    fn fork_merges_into_existing_mappings() {
+        let mut tracker = AddressSpaceTracker::new();
+        tracker.handle_mmap(&mmap(10, 0x1000, 0x3000, 0, b"parent.so"), 0);
+        tracker.handle_mmap(&mmap(10, 0x8000, 0x1000, 0, b"libc.so"), 0);
+
+        // perf synthesized an MMAP for the child before its FORK record.
+        tracker.handle_mmap(&mmap(20, 0x2000, 0x1000, 0, b"child.so"), 5);
+        tracker.handle_fork(&fork(20, 10, 100));
+        assert_eq!(
+            resolve(&tracker, 20, 0x2000, 120, CpuMode::User),
+            Some((&b"child.so"[..], 0))
+        );
+        assert_eq!(
+            resolve(&tracker, 20, 0x8000, 120, CpuMode::User),
+            Some((&b"libc.so"[..], 0))
+        );
+
+        // pid 30 exits and is reused; the old mappings stay resolvable.
+        tracker.handle_mmap(&mmap(30, 0x4000, 0x1000, 0, b"old.so"), 10);
+        tracker.handle_exit(&fork(30, 30, 50));
+        tracker.handle_fork(&fork(30, 10, 60));
+        assert_eq!(
+            resolve(&tracker, 30, 0x4000, 20, CpuMode::User),
+            Some((&b"old.so"[..], 0))
+        );
+        assert_eq!(
+            resolve(&tracker, 30, 0x1000, 70, CpuMode::User),
+            Some((&b"parent.so"[..], 0))
+        );
+        assert_eq!(resolve(&tracker, 30, 0x4000, 70, CpuMode::User), None);
+    }
+}
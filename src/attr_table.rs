@@ -0,0 +1,354 @@
+use std::collections::HashMap;
+
+use byteorder::{BigEndian, LittleEndian};
+
+use crate::{
+    get_record_id, Endianness, PerfEventAttr, RawData, RawEventRecord, RecordIdParseInfo,
+    RecordParseInfo, RecordType,
+};
+
+/// An error that can occur when adding attrs to an [`AttrTable`].
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum AttrTableError {
+    /// The same event ID was listed for two different attrs.
+    #[error("ID {id} is claimed by both attr {first_index} and attr {second_index}")]
+    DuplicateId {
+        id: u64,
+        first_index: usize,
+        second_index: usize,
+    },
+}
+
+/// An attr with the event IDs that belong to it.
+#[derive(Debug, Clone)]
+pub struct AttrTableEntry {
+    /// The attr.
+    pub attr: PerfEventAttr,
+    /// The IDs of the events which were opened with this attr. There is usually
+    /// one ID per CPU or per thread.
+    pub ids: Vec<u64>,
+    /// The parse info for records which belong to this attr.
+    pub parse_info: RecordParseInfo,
+}
+
+/// Maps records to the `perf_event_attr` they belong to.
+///
+/// Recordings with multiple events, for example from
+/// `perf record -e cycles,instructions,sched:sched_switch`, have one attr per
+/// event, and each record needs to be parsed with the [`RecordParseInfo`] of
+/// its own attr. The only way to find the right attr is the record's ID,
+/// which can be found with [`get_record_id`] as long as all attrs agree on
+/// where the ID is stored. `AttrTable` checks this agreement when attrs are
+/// added, and then maps IDs to attrs.
+///
+/// If the table only has a single attr, every record belongs to that attr and
+/// no ID lookup is needed ("single-attr mode"). This is also the case for
+/// recordings which don't have IDs at all.
+///
+/// If the attrs disagree on where the ID is stored, the IDs can't be read
+/// reliably, and the table falls back to single-attr mode: every record is
+/// assigned to the first attr, which is what `perf` does for records it
+/// can't map to an event.
+#[derive(Debug, Clone)]
+pub struct AttrTable {
+    endian: Endianness,
+    entries: Vec<AttrTableEntry>,
+    id_parse_info: Option<RecordIdParseInfo>,
+    /// Set once an attr with a different [`RecordIdParseInfo`] is added.
+    has_incompatible_id_parse_info: bool,
+    index_for_id: HashMap<u64, usize>,
+}
+
+impl AttrTable {
+    /// Create an empty table. Attrs can be added with [`AttrTable::add_attr`].
+    pub fn new(endian: Endianness) -> Self {
+        Self {
+            endian,
+            entries: Vec::new(),
+            id_parse_info: None,
+            has_incompatible_id_parse_info: false,
+            index_for_id: HashMap::new(),
+        }
+    }
+
+    /// Create a table from a list of attrs and their IDs.
+    pub fn from_attrs(
+        attrs: impl IntoIterator<Item = (PerfEventAttr, Vec<u64>)>,
+        endian: Endianness,
+    ) -> Result<Self, AttrTableError> {
+        let mut table = Self::new(endian);
+        for (attr, ids) in attrs {
+            table.add_attr(attr, ids)?;
+        }
+        Ok(table)
+    }
+
+    /// Add an attr with its IDs. Returns the index of the new attr.
+    ///
+    /// Fails if one of the IDs is already used by a different attr. If the
+    /// attr's [`RecordIdParseInfo`] doesn't match the one of the attrs which
+    /// are already in the table, the attr is added, and the table falls back
+    /// to single-attr mode.
+    pub fn add_attr(
+        &mut self,
+        attr: PerfEventAttr,
+        ids: Vec<u64>,
+    ) -> Result<usize, AttrTableError> {
+        let index = self.entries.len();
+        let id_parse_info = RecordIdParseInfo::new(&attr);
+        self.check_ids(index, &ids)?;
+        match self.id_parse_info {
+            None => self.id_parse_info = Some(id_parse_info),
+            Some(existing) if existing != id_parse_info => {
+                self.has_incompatible_id_parse_info = true;
+            }
+            Some(_) => {}
+        }
+        for &id in &ids {
+            self.index_for_id.insert(id, index);
+        }
+        let parse_info = RecordParseInfo::new(&attr, self.endian);
+        self.entries.push(AttrTableEntry {
+            attr,
+            ids,
+            parse_info,
+        });
+        Ok(index)
+    }
+
+    /// Associate additional IDs with the attr at `index`.
+    ///
+    /// This is needed when IDs become known after the attr, for example from
+    /// `PERF_RECORD_ID_INDEX` records.
+    ///
+    /// Panics if `index` is out of range.
+    pub fn add_ids(&mut self, index: usize, ids: &[u64]) -> Result<(), AttrTableError> {
+        assert!(index < self.entries.len());
+        self.check_ids(index, ids)?;
+        for &id in ids {
+            if self.index_for_id.insert(id, index).is_none() {
+                self.entries[index].ids.push(id);
+            }
+        }
+        Ok(())
+    }
+
+    fn check_ids(&self, index: usize, ids: &[u64]) -> Result<(), AttrTableError> {
+        for &id in ids {
+            match self.index_for_id.get(&id) {
+                Some(&first_index) if first_index != index => {
+                    return Err(AttrTableError::DuplicateId {
+                        id,
+                        first_index,
+                        second_index: index,
+                    });
+                }
+                _ => {}
+            }
+        }
+        Ok(())
+    }
+
+    /// The endianness of the records in this table.
+    pub fn endian(&self) -> Endianness {
+        self.endian
+    }
+
+    /// The number of attrs.
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Whether there are no attrs in this table.
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Whether all records are assigned to the first attr without an ID
+    /// lookup, either because there is only one attr or because the attrs
+    /// have incompatible [`RecordIdParseInfo`]s.
+    pub fn is_single_attr_mode(&self) -> bool {
+        self.entries.len() == 1 || self.has_incompatible_id_parse_info
+    }
+
+    /// The [`RecordIdParseInfo`] shared by all attrs. `None` if there are no
+    /// attrs, or if the attrs don't agree on it.
+    pub fn id_parse_info(&self) -> Option<&RecordIdParseInfo> {
+        if self.has_incompatible_id_parse_info {
+            return None;
+        }
+        self.id_parse_info.as_ref()
+    }
+
+    /// The entries in this table, in the order in which they were added.
+    pub fn entries(&self) -> &[AttrTableEntry] {
+        &self.entries
+    }
+
+    /// The entry at `index`.
+    pub fn get(&self, index: usize) -> Option<&AttrTableEntry> {
+        self.entries.get(index)
+    }
+
+    /// The index of the attr which has the ID `id`.
+    pub fn index_for_id(&self, id: u64) -> Option<usize> {
+        self.index_for_id.get(&id).copied()
+    }
+
+    /// Find the index of the attr which the record belongs to.
+    ///
+    /// In single-attr mode, this is always 0. Otherwise, the record's ID is
+    /// read and looked up. Records which don't carry an ID, for example
+    /// non-sample records if `AttrFlags::SAMPLE_ID_ALL` is not set, are
+    /// assigned to the first attr, matching what `perf` does. Records with an
+    /// unknown ID return `None`.
+    pub fn attr_index_for_record(&self, record_type: RecordType, data: RawData) -> Option<usize> {
+        if self.is_single_attr_mode() {
+            return Some(0);
+        }
+        let id_parse_info = self.id_parse_info.as_ref()?;
+        let id = match self.endian {
+            Endianness::LittleEndian => {
+                get_record_id::<LittleEndian>(record_type, data, id_parse_info)
+            }
+            Endianness::BigEndian => get_record_id::<BigEndian>(record_type, data, id_parse_info),
+        };
+        match id {
+            Some(id) => self.index_for_id(id),
+            None => Some(0),
+        }
+    }
+
+    /// Find the [`RecordParseInfo`] for the record with this type and body.
+    ///
+    /// See [`AttrTable::attr_index_for_record`] for how the attr is chosen.
+    pub fn parse_info_for_record(
+        &self,
+        record_type: RecordType,
+        data: RawData,
+    ) -> Option<RecordParseInfo> {
+        let index = self.attr_index_for_record(record_type, data)?;
+        Some(self.entries[index].parse_info)
+    }
+
+    /// Create a [`RawEventRecord`] with the parse info of the matching attr.
+    pub fn raw_record<'a>(
+        &self,
+        record_type: RecordType,
+        misc: u16,
+        data: RawData<'a>,
+    ) -> Option<RawEventRecord<'a>> {
+        let parse_info = self.parse_info_for_record(record_type, data)?;
+        Some(RawEventRecord::new(record_type, misc, data, parse_info))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{AttrTable, AttrTableError};
+    use crate::{
+        AttrFlags, BranchSampleFormat, Endianness, HardwareEventId, PerfClock, PerfEventAttr,
+        PerfEventType, PmuTypeId, RawData, ReadFormat, RecordType, SampleFormat, SamplingPolicy,
+        WakeupPolicy,
+    };
+
+    fn attr(sample_format: SampleFormat) -> PerfEventAttr {
+        PerfEventAttr {
+            type_: PerfEventType::Hardware(HardwareEventId::CpuCycles, PmuTypeId(0)),
+            sampling_policy: SamplingPolicy::Frequency(1000),
+            sample_format,
+            read_format: ReadFormat::empty(),
+            flags: AttrFlags::SAMPLE_ID_ALL | AttrFlags::FREQ,
+            wakeup_policy: WakeupPolicy::EventCount(0),
+            branch_sample_format: BranchSampleFormat::empty(),
+            sample_regs_user: 0,
+            sample_stack_user: 0,
+            clock: PerfClock::Default,
+            sample_regs_intr: 0,
+            aux_watermark: 0,
+            sample_max_stack: 0,
+            aux_sample_size: 0,
+            sig_data: 0,
+        }
+    }
+
+    #[test]
+    fn routes_records_by_id() {
+        let format = SampleFormat::IDENTIFIER | SampleFormat::IP | SampleFormat::TIME;
+        let table = AttrTable::from_attrs(
+            [
+                (attr(format), vec![10, 11]),
+                (attr(format | SampleFormat::PERIOD), vec![20, 21]),
+            ],
+            Endianness::LittleEndian,
+        )
+        .unwrap();
+        assert!(!table.is_single_attr_mode());
+
+        let mut sample = Vec::new();
+        sample.extend_from_slice(&21u64.to_le_bytes());
+        sample.extend_from_slice(&0x1234u64.to_le_bytes());
+        sample.extend_from_slice(&5u64.to_le_bytes());
+        sample.extend_from_slice(&1000u64.to_le_bytes());
+        let data = RawData::from(&sample[..]);
+        assert_eq!(
+            table.attr_index_for_record(RecordType::SAMPLE, data),
+            Some(1)
+        );
+        let record = table.raw_record(RecordType::SAMPLE, 0, data).unwrap();
+        assert_eq!(
+            record.parse_info.sample_format,
+            format | SampleFormat::PERIOD
+        );
+
+        // Non-sample records have the identifier at the very end.
+        let mut exit = vec![0; 24];
+        exit.extend_from_slice(&5u64.to_le_bytes());
+        exit.extend_from_slice(&10u64.to_le_bytes());
+        let data = RawData::from(&exit[..]);
+        assert_eq!(table.attr_index_for_record(RecordType::EXIT, data), Some(0));
+
+        let mut unknown = vec![0; 24];
+        unknown.extend_from_slice(&99u64.to_le_bytes());
+        let data = RawData::from(&unknown[..]);
+        assert_eq!(table.attr_index_for_record(RecordType::EXIT, data), None);
+    }
+
+    #[test]
+    fn falls_back_for_incompatible_attrs() {
+        let mut table = AttrTable::new(Endianness::LittleEndian);
+        table
+            .add_attr(attr(SampleFormat::IP | SampleFormat::ID), vec![1])
+            .unwrap();
+        assert!(table.is_single_attr_mode());
+        assert_eq!(
+            table.add_attr(attr(SampleFormat::IP | SampleFormat::ID), vec![1]),
+            Err(AttrTableError::DuplicateId {
+                id: 1,
+                first_index: 0,
+                second_index: 1
+            })
+        );
+        assert_eq!(
+            table.add_attr(
+                attr(SampleFormat::IP | SampleFormat::TID | SampleFormat::ID),
+                vec![2]
+            ),
+            Ok(1)
+        );
+        assert!(table.is_single_attr_mode());
+        assert_eq!(table.id_parse_info(), None);
+
+        // The ID is not where the first attr expects it, so it isn't read.
+        let mut sample = Vec::new();
+        sample.extend_from_slice(&0x1234u64.to_le_bytes());
+        sample.extend_from_slice(&5u64.to_le_bytes());
+        sample.extend_from_slice(&2u64.to_le_bytes());
+        let data = RawData::from(&sample[..]);
+        assert_eq!(
+            table.attr_index_for_record(RecordType::SAMPLE, data),
+            Some(0)
+        );
+    }
+}
@@ -0,0 +1,175 @@
+use crate::ring_buffer::ring_slice;
+use crate::{AuxFlags, AuxRecord, RawData};
+use std::ptr::NonNull;
+use std::{io, slice};
+
+/// The AUX area of a perf event mmap.
+///
+/// Hardware tracers like Intel PT or ARM CoreSight write their trace data into
+/// a second ring buffer, the AUX area. It is mapped from the same fd as the
+/// regular ring buffer, at the offset and with the size that are given by
+/// `aux_offset` and `aux_size` in the [`PerfEventMmapPage`](crate::PerfEventMmapPage).
+/// The kernel announces new AUX data by emitting [`AuxRecord`]s into the
+/// regular ring buffer, and `AuxRingBuffer` finds the trace data for each
+/// such record.
+///
+/// If the AUX area was mapped writable, the consumer releases the data by
+/// advancing `aux_tail`, see [`MmapRingBuffer::set_aux_tail`](crate::MmapRingBuffer::set_aux_tail).
+/// If it was mapped read-only, the AUX area is in overwrite ("snapshot") mode:
+/// The hardware keeps overwriting the oldest data, and the records carry
+/// [`AuxFlags::OVERWRITE`].
+#[derive(Debug, Clone, Copy)]
+pub struct AuxRingBuffer<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> AuxRingBuffer<'a> {
+    /// Create an `AuxRingBuffer` for the AUX area `data`.
+    ///
+    /// Fails if the size of `data` is not a power of two.
+    pub fn new(data: &'a [u8]) -> Result<Self, io::Error> {
+        if !data.len().is_power_of_two() {
+            return Err(io::ErrorKind::InvalidInput.into());
+        }
+        Ok(Self { data })
+    }
+
+    /// Create an `AuxRingBuffer` for the mmap'd AUX area of a perf event fd.
+    ///
+    /// # Safety
+    ///
+    /// `base` must point to `len` bytes which stay mapped for `'a`, and the
+    /// AUX area must not be written to while the `AuxRingBuffer` and the data
+    /// it returns are in use. The hardware keeps writing into the AUX area,
+    /// so in overwrite mode, output needs to be paused first with
+    /// `PERF_EVENT_IOC_PAUSE_OUTPUT` (`PerfEventFd::pause_output` with the
+    /// `open` feature), and only resumed once the data has been consumed. In
+    /// regular mode, `aux_tail` must not be advanced past data which is still
+    /// in use.
+    pub unsafe fn from_raw_parts(base: NonNull<u8>, len: usize) -> Result<Self, io::Error> {
+        Self::new(slice::from_raw_parts(base.as_ptr(), len))
+    }
+
+    /// The size of the AUX area, in bytes.
+    pub fn size(&self) -> u64 {
+        self.data.len() as u64
+    }
+
+    /// The `len` bytes at the AUX position `position`, which may wrap around
+    /// the end of the AUX area. Returns `None` if `len` is larger than the AUX area.
+    pub fn get(&self, position: u64, len: u64) -> Option<RawData<'a>> {
+        if len > self.size() {
+            return None;
+        }
+        Some(ring_slice(self.data, position, len as usize))
+    }
+
+    /// The trace data which is announced by the AUX record.
+    ///
+    /// For regular records, this is the range given by `aux_offset` and `aux_size`.
+    /// For records with [`AuxFlags::OVERWRITE`], this is the snapshot of the
+    /// `aux_size` bytes which end at `aux_offset`, see [`AuxRingBuffer::snapshot`].
+    pub fn data_for_record(&self, record: &AuxRecord) -> Option<RawData<'a>> {
+        if record.flags.contains(AuxFlags::OVERWRITE) {
+            Some(self.snapshot(record.aux_offset, record.aux_size))
+        } else {
+            self.get(record.aux_offset, record.aux_size)
+        }
+    }
+
+    /// The last `len` bytes of trace data of an AUX area in overwrite mode,
+    /// which end at `head`.
+    ///
+    /// `head` is the `aux_offset` of the [`AuxRecord`]. Depending on the
+    /// tracer, this is either a free-running counter or a position inside the
+    /// AUX area (e.g. Intel PT in snapshot mode), so only `head` modulo the
+    /// size of the AUX area is used, and the snapshot can cover the whole
+    /// AUX area even if `head` is smaller than its size.
+    ///
+    /// Like perf, this assumes that the AUX area hasn't wrapped yet if its
+    /// end is still zeroed, and then limits the result to the data from the
+    /// start of the AUX area.
+    pub fn snapshot(&self, head: u64, len: u64) -> RawData<'a> {
+        let size = self.data.len();
+        let head_index = (head % self.size()) as usize;
+        let mut len = len.min(self.size()) as usize;
+        let check_start = head_index.max(size.saturating_sub(FIRST_WRAP_CHECK_LEN));
+        if self.data[check_start..].iter().all(|&b| b == 0) {
+            len = len.min(head_index);
+        }
+        ring_slice(self.data, head.wrapping_sub(len as u64), len)
+    }
+}
+
+/// The number of bytes at the end of the AUX area which need to be zero for
+/// a snapshot to be treated as not wrapped yet, like in perf's
+/// `intel_pt_first_wrap`.
+const FIRST_WRAP_CHECK_LEN: usize = 512;
+
+#[cfg(test)]
+mod test {
+    use super::AuxRingBuffer;
+    use crate::{AuxFlags, AuxRecord, RawData};
+
+    #[test]
+    fn records_and_snapshots() {
+        let mut data = vec![0u8; 16];
+        data[..6].copy_from_slice(b"ABCDEF");
+        let aux = AuxRingBuffer::new(&data).unwrap();
+
+        let record = AuxRecord {
+            aux_offset: 4,
+            aux_size: 2,
+            flags: AuxFlags::empty(),
+        };
+        assert_eq!(aux.data_for_record(&record), Some(RawData::Single(b"EF")));
+
+        // Before wrapping, the snapshot only covers the data from the start.
+        let record = AuxRecord {
+            aux_offset: 6,
+            aux_size: 6,
+            flags: AuxFlags::OVERWRITE,
+        };
+        assert_eq!(
+            aux.data_for_record(&record),
+            Some(RawData::Single(b"ABCDEF"))
+        );
+
+        data.copy_from_slice(b"QRSTEFGHIJKLMNOP");
+        let aux = AuxRingBuffer::new(&data).unwrap();
+        let record = AuxRecord {
+            aux_offset: 16 * 3 + 4,
+            aux_size: 16,
+            flags: AuxFlags::OVERWRITE,
+        };
+        let snapshot = aux.data_for_record(&record).unwrap();
+        assert_eq!(&snapshot.as_slice()[..], b"EFGHIJKLMNOPQRST");
+
+        // With a head inside the AUX area, as with Intel PT, a wrapped
+        // snapshot still covers the whole AUX area.
+        assert_eq!(
+            aux.snapshot(3, 16),
+            RawData::Split(b"TEFGHIJKLMNOP", b"QRS")
+        );
+        assert_eq!(aux.snapshot(20, 2), RawData::Single(b"ST"));
+        assert_eq!(aux.snapshot(20, 64).len(), 16);
+
+        // A zeroed end means that the AUX area hasn't wrapped yet.
+        let zeroed = vec![0u8; 16];
+        let aux = AuxRingBuffer::new(&zeroed).unwrap();
+        assert_eq!(aux.snapshot(3, 16), RawData::Single(&[0; 3]));
+        assert_eq!(aux.snapshot(0, 16), RawData::Single(&[]));
+
+        let aux = AuxRingBuffer::new(&data).unwrap();
+        // Records in non-overwrite mode wrap around as well.
+        let record = AuxRecord {
+            aux_offset: 14,
+            aux_size: 4,
+            flags: AuxFlags::empty(),
+        };
+        assert_eq!(
+            aux.data_for_record(&record),
+            Some(RawData::Split(b"OP", b"QR"))
+        );
+    }
+}
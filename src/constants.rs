@@ -90,6 +90,31 @@ pub const HW_BREAKPOINT_RW: u8 = HW_BREAKPOINT_R | HW_BREAKPOINT_W;
 pub const HW_BREAKPOINT_X: u8 = 4;
 pub const HW_BREAKPOINT_INVALID: u8 = HW_BREAKPOINT_RW | HW_BREAKPOINT_X;
 
+pub const HW_BREAKPOINT_LEN_1: u64 = 1;
+pub const HW_BREAKPOINT_LEN_2: u64 = 2;
+pub const HW_BREAKPOINT_LEN_3: u64 = 3;
+pub const HW_BREAKPOINT_LEN_4: u64 = 4;
+pub const HW_BREAKPOINT_LEN_5: u64 = 5;
+pub const HW_BREAKPOINT_LEN_6: u64 = 6;
+pub const HW_BREAKPOINT_LEN_7: u64 = 7;
+pub const HW_BREAKPOINT_LEN_8: u64 = 8;
+
+// The config of the kprobe and uprobe PMUs, from kernel/events/core.c.
+/// The `retprobe` format bit, `config:0`.
+pub const PERF_PROBE_CONFIG_IS_RETPROBE: u64 = 1 << 0;
+/// The shift of the uprobe `ref_ctr_offset` format field, `config:32-63`.
+pub const PERF_UPROBE_REF_CTR_OFFSET_SHIFT: u32 = 32;
+
+// Flags for the perf_event_open syscall.
+/// Ignore the group_fd argument (except with `PERF_FLAG_FD_OUTPUT`).
+pub const PERF_FLAG_FD_NO_GROUP: u64 = 1 << 0;
+/// Reroute the output to the group_fd's ring buffer. Broken since Linux 2.6.35.
+pub const PERF_FLAG_FD_OUTPUT: u64 = 1 << 1;
+/// The pid argument is a cgroup fd; per-container monitoring.
+pub const PERF_FLAG_PID_CGROUP: u64 = 1 << 2;
+/// Set the close-on-exec flag on the new fd.
+pub const PERF_FLAG_FD_CLOEXEC: u64 = 1 << 3;
+
 /// sizeof first published struct
 pub const PERF_ATTR_SIZE_VER0: u32 = 64;
 /// add: config2
@@ -466,6 +491,18 @@ pub const PERF_RECORD_AUX: u32 = 11;
  */
 pub const PERF_RECORD_ITRACE_START: u32 = 12;
 
+// Flags in PERF_RECORD_AUX::flags.
+/// Record was truncated to fit.
+pub const PERF_AUX_FLAG_TRUNCATED: u64 = 0x01;
+/// Snapshot from overwrite mode.
+pub const PERF_AUX_FLAG_OVERWRITE: u64 = 0x02;
+/// Record contains gaps.
+pub const PERF_AUX_FLAG_PARTIAL: u64 = 0x04;
+/// Sample collided with another.
+pub const PERF_AUX_FLAG_COLLISION: u64 = 0x08;
+/// PMU specific trace format type.
+pub const PERF_AUX_FLAG_PMU_FORMAT_TYPE_MASK: u64 = 0xff00;
+
 /*
  * Records the dropped/lost sample number.
  *
@@ -595,6 +632,29 @@ pub const PERF_RECORD_AUX_OUTPUT_HW_ID: u32 = 21;
 
 pub const PERF_RECORD_USER_TYPE_START: u32 = 64;
 
+// Record types which are synthesized by the perf tool, see perf's `util/event.h`.
+pub const PERF_RECORD_HEADER_ATTR: u32 = 64;
+pub const PERF_RECORD_HEADER_EVENT_TYPE: u32 = 65;
+/// Followed by `size` bytes of tracing data which are not included in the record size.
+pub const PERF_RECORD_HEADER_TRACING_DATA: u32 = 66;
+pub const PERF_RECORD_HEADER_BUILD_ID: u32 = 67;
+pub const PERF_RECORD_FINISHED_ROUND: u32 = 68;
+pub const PERF_RECORD_ID_INDEX: u32 = 69;
+pub const PERF_RECORD_AUXTRACE_INFO: u32 = 70;
+/// Followed by `size` bytes of AUX data which are not included in the record size.
+pub const PERF_RECORD_AUXTRACE: u32 = 71;
+pub const PERF_RECORD_AUXTRACE_ERROR: u32 = 72;
+pub const PERF_RECORD_THREAD_MAP: u32 = 73;
+pub const PERF_RECORD_CPU_MAP: u32 = 74;
+pub const PERF_RECORD_STAT_CONFIG: u32 = 75;
+pub const PERF_RECORD_STAT: u32 = 76;
+pub const PERF_RECORD_STAT_ROUND: u32 = 77;
+pub const PERF_RECORD_EVENT_UPDATE: u32 = 78;
+pub const PERF_RECORD_TIME_CONV: u32 = 79;
+pub const PERF_RECORD_HEADER_FEATURE: u32 = 80;
+pub const PERF_RECORD_COMPRESSED: u32 = 81;
+pub const PERF_RECORD_FINISHED_INIT: u32 = 82;
+
 pub const PERF_SAMPLE_IP: u64 = 1 << 0;
 pub const PERF_SAMPLE_TID: u64 = 1 << 1;
 pub const PERF_SAMPLE_TIME: u64 = 1 << 2;
@@ -741,6 +801,7 @@ pub const PERF_FORMAT_TOTAL_TIME_ENABLED: u64 = 1 << 0;
 pub const PERF_FORMAT_TOTAL_TIME_RUNNING: u64 = 1 << 1;
 pub const PERF_FORMAT_ID: u64 = 1 << 2;
 pub const PERF_FORMAT_GROUP: u64 = 1 << 3;
+pub const PERF_FORMAT_LOST: u64 = 1 << 4;
 
 /*
  * values to program into branch_sample_type when PERF_SAMPLE_BRANCH is set
@@ -877,3 +938,17 @@ pub const PERF_CONTEXT_GUEST_USER: u64 = -2560i64 as u64;
 /// Any callchain frames which are >= PERF_CONTEXT_MAX are not real addresses;
 /// instead, they mark the context of the subsequent callchain frames.
 pub const PERF_CONTEXT_MAX: u64 = -4095i64 as u64;
+
+// Bits in perf_event_mmap_page::capabilities.
+/// Deprecated, see `PERF_MMAP_CAP_BIT0_IS_DEPRECATED`.
+pub const PERF_MMAP_CAP_BIT0: u64 = 1 << 0;
+/// Set if `PERF_MMAP_CAP_BIT0` is deprecated and should be ignored.
+pub const PERF_MMAP_CAP_BIT0_IS_DEPRECATED: u64 = 1 << 1;
+/// The `rdpmc` instruction can be used from user space to read the counter.
+pub const PERF_MMAP_CAP_USER_RDPMC: u64 = 1 << 2;
+/// `time_shift`, `time_mult` and `time_offset` are valid.
+pub const PERF_MMAP_CAP_USER_TIME: u64 = 1 << 3;
+/// `time_zero` is valid.
+pub const PERF_MMAP_CAP_USER_TIME_ZERO: u64 = 1 << 4;
+/// `time_cycles` and `time_mask` are valid.
+pub const PERF_MMAP_CAP_USER_TIME_SHORT: u64 = 1 << 5;
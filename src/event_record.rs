@@ -1,8 +1,8 @@
 use crate::raw_data::RawData;
 use crate::utils::HexValue;
 use crate::{
-    constants, CommonData, CpuMode, Endianness, RecordIdParseInfo, RecordParseInfo, RecordType,
-    SampleRecord,
+    constants, AuxFlags, CommonData, CpuMode, Endianness, RecordIdParseInfo, RecordParseInfo,
+    RecordType, SampleRecord,
 };
 use byteorder::{BigEndian, ByteOrder, LittleEndian};
 use std::fmt;
@@ -39,6 +39,9 @@ pub fn get_record_identifier<T: ByteOrder>(
 /// but only if all potential attrs have the same `RecordIdParseInfo`.
 /// Once the record's ID is known, this ID can be mapped to the right attr,
 /// and then the information from the attr can be used to parse the rest of this record.
+///
+/// Returns `None` for user record types (see [`RecordType::is_user_type`]):
+/// the perf tool doesn't append sample IDs to the records it synthesizes.
 pub fn get_record_id<T: ByteOrder>(
     record_type: RecordType,
     mut data: RawData,
@@ -108,6 +111,8 @@ pub enum EventRecord<'a> {
     Throttle(ThrottleRecord),
     Unthrottle(ThrottleRecord),
     ContextSwitch(ContextSwitchRecord),
+    Aux(AuxRecord),
+    LostSamples(LostSamplesRecord),
     Raw(RawEventRecord<'a>),
 }
 
@@ -365,6 +370,23 @@ impl LostRecord {
     }
 }
 
+/// A `PERF_RECORD_LOST_SAMPLES` record: Samples were dropped, e.g. because
+/// they couldn't be generated from hardware trace data. The event the samples
+/// belong to is identified by the record's [`CommonData::id`](crate::CommonData::id).
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct LostSamplesRecord {
+    pub lost: u64,
+}
+
+impl LostSamplesRecord {
+    pub fn parse<T: ByteOrder>(data: RawData) -> Result<Self, std::io::Error> {
+        let mut cur = data;
+
+        let lost = cur.read_u64::<T>()?;
+        Ok(LostSamplesRecord { lost })
+    }
+}
+
 #[derive(Debug, Clone, PartialEq, Eq)]
 pub struct ThrottleRecord {
     pub id: u64,
@@ -381,6 +403,38 @@ impl ThrottleRecord {
     }
 }
 
+/// Announces that new data landed in the AUX area.
+///
+/// The data can be read with [`AuxRingBuffer::data_for_record`](crate::AuxRingBuffer::data_for_record).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct AuxRecord {
+    /// The position of the data in the AUX area. The position keeps growing
+    /// and needs to be wrapped around the AUX area size.
+    ///
+    /// For records with [`AuxFlags::OVERWRITE`], this is the position where
+    /// the data ends. Some tracers, e.g. Intel PT in snapshot mode, report it
+    /// as a position inside the AUX area, which doesn't keep growing.
+    pub aux_offset: u64,
+    /// The size of the new data.
+    pub aux_size: u64,
+    pub flags: AuxFlags,
+}
+
+impl AuxRecord {
+    pub fn parse<T: ByteOrder>(data: RawData) -> Result<Self, std::io::Error> {
+        let mut cur = data;
+
+        let aux_offset = cur.read_u64::<T>()?;
+        let aux_size = cur.read_u64::<T>()?;
+        let flags = AuxFlags::from_bits_retain(cur.read_u64::<T>()?);
+        Ok(AuxRecord {
+            aux_offset,
+            aux_size,
+            flags,
+        })
+    }
+}
+
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum ContextSwitchRecord {
     In {
@@ -550,9 +604,11 @@ impl<'a> RawEventRecord<'a> {
                 EventRecord::Sample(SampleRecord::parse::<T>(self.data, self.misc, parse_info)?)
             }
             RecordType::MMAP2 => EventRecord::Mmap2(Mmap2Record::parse::<T>(self.data, self.misc)?),
-            // AUX
+            RecordType::AUX => EventRecord::Aux(AuxRecord::parse::<T>(self.data)?),
             // ITRACE_START
-            // LOST_SAMPLES
+            RecordType::LOST_SAMPLES => {
+                EventRecord::LostSamples(LostSamplesRecord::parse::<T>(self.data)?)
+            }
             RecordType::SWITCH => {
                 EventRecord::ContextSwitch(ContextSwitchRecord::from_misc(self.misc))
             }
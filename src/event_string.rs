@@ -0,0 +1,707 @@
+use std::fmt;
+use std::num::NonZeroU64;
+
+use crate::constants::*;
+use crate::{
+    AttrFlags, BreakpointError, HardwareCacheId, HardwareCacheOp, HardwareCacheOpResult,
+    HardwareEventId, HwBreakpointAddr, HwBreakpointLen, HwBreakpointType, PerfEventAttr,
+    PerfEventType, PmuRegistry, PmuTypeId, SampleFormat, SamplingPolicy, SoftwareCounterType,
+};
+
+/// An error from [`PerfEventAttr::from_event_string`].
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum EventStringError {
+    #[error("Unknown event {0}")]
+    UnknownEvent(String),
+
+    #[error("Unknown PMU {0}")]
+    UnknownPmu(String),
+
+    #[error("Invalid PMU term {0}")]
+    InvalidTerm(String),
+
+    #[error("Invalid number {0}")]
+    InvalidNumber(String),
+
+    #[error("Invalid event modifier {0}")]
+    InvalidModifier(char),
+
+    /// A `mem:` event which the kernel would reject.
+    #[error("Invalid breakpoint: {0}")]
+    InvalidBreakpoint(#[from] BreakpointError),
+}
+
+const HARDWARE_EVENTS: &[(&str, HardwareEventId)] = &[
+    ("cycles", HardwareEventId::CpuCycles),
+    ("cpu-cycles", HardwareEventId::CpuCycles),
+    ("instructions", HardwareEventId::Instructions),
+    ("cache-references", HardwareEventId::CacheReferences),
+    ("cache-misses", HardwareEventId::CacheMisses),
+    ("branches", HardwareEventId::BranchInstructions),
+    ("branch-instructions", HardwareEventId::BranchInstructions),
+    ("branch-misses", HardwareEventId::BranchMisses),
+    ("bus-cycles", HardwareEventId::BusCycles),
+    (
+        "stalled-cycles-frontend",
+        HardwareEventId::StalledCyclesFrontend,
+    ),
+    (
+        "idle-cycles-frontend",
+        HardwareEventId::StalledCyclesFrontend,
+    ),
+    (
+        "stalled-cycles-backend",
+        HardwareEventId::StalledCyclesBackend,
+    ),
+    ("idle-cycles-backend", HardwareEventId::StalledCyclesBackend),
+    ("ref-cycles", HardwareEventId::RefCpuCycles),
+];
+
+const SOFTWARE_EVENTS: &[(&str, SoftwareCounterType)] = &[
+    ("cpu-clock", SoftwareCounterType::CpuClock),
+    ("task-clock", SoftwareCounterType::TaskClock),
+    ("page-faults", SoftwareCounterType::PageFaults),
+    ("faults", SoftwareCounterType::PageFaults),
+    ("context-switches", SoftwareCounterType::ContextSwitches),
+    ("cs", SoftwareCounterType::ContextSwitches),
+    ("cpu-migrations", SoftwareCounterType::CpuMigrations),
+    ("migrations", SoftwareCounterType::CpuMigrations),
+    ("minor-faults", SoftwareCounterType::PageFaultsMin),
+    ("major-faults", SoftwareCounterType::PageFaultsMaj),
+    ("alignment-faults", SoftwareCounterType::AlignmentFaults),
+    ("emulation-faults", SoftwareCounterType::EmulationFaults),
+    ("dummy", SoftwareCounterType::Dummy),
+    ("bpf-output", SoftwareCounterType::BpfOutput),
+    ("cgroup-switches", SoftwareCounterType::CgroupSwitches),
+];
+
+/// Lowercase cache names, as accepted by perf.
+const CACHES: &[(&str, HardwareCacheId)] = &[
+    ("l1-dcache", HardwareCacheId::L1d),
+    ("l1-d", HardwareCacheId::L1d),
+    ("l1d", HardwareCacheId::L1d),
+    ("l1-data", HardwareCacheId::L1d),
+    ("l1-icache", HardwareCacheId::L1i),
+    ("l1-i", HardwareCacheId::L1i),
+    ("l1i", HardwareCacheId::L1i),
+    ("l1-instruction", HardwareCacheId::L1i),
+    ("llc", HardwareCacheId::Ll),
+    ("l2", HardwareCacheId::Ll),
+    ("dtlb", HardwareCacheId::Dtlb),
+    ("d-tlb", HardwareCacheId::Dtlb),
+    ("data-tlb", HardwareCacheId::Dtlb),
+    ("itlb", HardwareCacheId::Itlb),
+    ("i-tlb", HardwareCacheId::Itlb),
+    ("instruction-tlb", HardwareCacheId::Itlb),
+    ("branch", HardwareCacheId::Bpu),
+    ("branches", HardwareCacheId::Bpu),
+    ("bpu", HardwareCacheId::Bpu),
+    ("btb", HardwareCacheId::Bpu),
+    ("bpc", HardwareCacheId::Bpu),
+    ("node", HardwareCacheId::Node),
+];
+
+const CACHE_OPS: &[(&str, HardwareCacheOp)] = &[
+    ("load", HardwareCacheOp::Read),
+    ("loads", HardwareCacheOp::Read),
+    ("read", HardwareCacheOp::Read),
+    ("store", HardwareCacheOp::Write),
+    ("stores", HardwareCacheOp::Write),
+    ("write", HardwareCacheOp::Write),
+    ("prefetch", HardwareCacheOp::Prefetch),
+    ("prefetches", HardwareCacheOp::Prefetch),
+    ("speculative-read", HardwareCacheOp::Prefetch),
+    ("speculative-load", HardwareCacheOp::Prefetch),
+];
+
+const CACHE_RESULTS: &[(&str, HardwareCacheOpResult)] = &[
+    ("refs", HardwareCacheOpResult::Access),
+    ("reference", HardwareCacheOpResult::Access),
+    ("ops", HardwareCacheOpResult::Access),
+    ("access", HardwareCacheOpResult::Access),
+    ("accesses", HardwareCacheOpResult::Access),
+    ("misses", HardwareCacheOpResult::Miss),
+    ("miss", HardwareCacheOpResult::Miss),
+];
+
+/// The config fields of the core PMU (`cpu`) on x86, from
+/// `/sys/bus/event_source/devices/cpu/format/*`: `(name, shift, bit count)`.
+///
+/// Other architectures encode raw events differently, e.g. arm64 uses a
+/// 16-bit `event` field, so there the terms need a [`PmuRegistry`].
+#[cfg(any(target_arch = "x86", target_arch = "x86_64"))]
+const CPU_FORMAT: &[(&str, u32, u32)] = &[
+    ("event", 0, 8),
+    ("umask", 8, 8),
+    ("edge", 18, 1),
+    ("pc", 19, 1),
+    ("any", 21, 1),
+    ("inv", 23, 1),
+    ("cmask", 24, 8),
+];
+
+#[cfg(not(any(target_arch = "x86", target_arch = "x86_64")))]
+const CPU_FORMAT: &[(&str, u32, u32)] = &[];
+
+fn lookup<T: Copy>(table: &[(&str, T)], name: &str) -> Option<T> {
+    table
+        .iter()
+        .find(|(entry_name, _)| *entry_name == name)
+        .map(|&(_, value)| value)
+}
+
+fn hardware_event_name(id: HardwareEventId) -> &'static str {
+    match id {
+        HardwareEventId::CpuCycles => "cycles",
+        HardwareEventId::Instructions => "instructions",
+        HardwareEventId::CacheReferences => "cache-references",
+        HardwareEventId::CacheMisses => "cache-misses",
+        HardwareEventId::BranchInstructions => "branches",
+        HardwareEventId::BranchMisses => "branch-misses",
+        HardwareEventId::BusCycles => "bus-cycles",
+        HardwareEventId::StalledCyclesFrontend => "stalled-cycles-frontend",
+        HardwareEventId::StalledCyclesBackend => "stalled-cycles-backend",
+        HardwareEventId::RefCpuCycles => "ref-cycles",
+    }
+}
+
+fn software_event_name(counter_type: SoftwareCounterType) -> &'static str {
+    match counter_type {
+        SoftwareCounterType::CpuClock => "cpu-clock",
+        SoftwareCounterType::TaskClock => "task-clock",
+        SoftwareCounterType::PageFaults => "page-faults",
+        SoftwareCounterType::ContextSwitches => "context-switches",
+        SoftwareCounterType::CpuMigrations => "cpu-migrations",
+        SoftwareCounterType::PageFaultsMin => "minor-faults",
+        SoftwareCounterType::PageFaultsMaj => "major-faults",
+        SoftwareCounterType::AlignmentFaults => "alignment-faults",
+        SoftwareCounterType::EmulationFaults => "emulation-faults",
+        SoftwareCounterType::Dummy => "dummy",
+        SoftwareCounterType::BpfOutput => "bpf-output",
+        SoftwareCounterType::CgroupSwitches => "cgroup-switches",
+    }
+}
+
+fn cache_name(cache_id: HardwareCacheId) -> &'static str {
+    match cache_id {
+        HardwareCacheId::L1d => "L1-dcache",
+        HardwareCacheId::L1i => "L1-icache",
+        HardwareCacheId::Ll => "LLC",
+        HardwareCacheId::Dtlb => "dTLB",
+        HardwareCacheId::Itlb => "iTLB",
+        HardwareCacheId::Bpu => "branch",
+        HardwareCacheId::Node => "node",
+    }
+}
+
+/// The name of a hardware or hardware cache event, without PMU.
+fn hardware_name(type_: &PerfEventType) -> Option<String> {
+    match *type_ {
+        PerfEventType::Hardware(id, _) => Some(hardware_event_name(id).to_owned()),
+        PerfEventType::HwCache(cache_id, op, result, _) => {
+            let op = match op {
+                HardwareCacheOp::Read => "load",
+                HardwareCacheOp::Write => "store",
+                HardwareCacheOp::Prefetch => "prefetch",
+            };
+            let suffix = match (result, op) {
+                (HardwareCacheOpResult::Access, "prefetch") => "es",
+                (HardwareCacheOpResult::Access, _) => "s",
+                (HardwareCacheOpResult::Miss, _) => "-misses",
+            };
+            Some(format!("{}-{op}{suffix}", cache_name(cache_id)))
+        }
+        _ => None,
+    }
+}
+
+/// Parse a hardware or hardware cache event name. The PMU type ID is filled
+/// in by the caller.
+fn parse_hardware_name(name: &str, pmu_type: PmuTypeId) -> Option<PerfEventType> {
+    if let Some(id) = lookup(HARDWARE_EVENTS, name) {
+        return Some(PerfEventType::Hardware(id, pmu_type));
+    }
+    let name = name.to_ascii_lowercase();
+    let (cache_id, rest) = CACHES.iter().find_map(|&(cache_name, cache_id)| {
+        let rest = name.strip_prefix(cache_name)?.strip_prefix('-')?;
+        Some((cache_id, rest))
+    })?;
+    let (op, result) = if let Some(op) = lookup(CACHE_OPS, rest) {
+        (op, HardwareCacheOpResult::Access)
+    } else if let Some(result) = lookup(CACHE_RESULTS, rest) {
+        (HardwareCacheOp::Read, result)
+    } else {
+        rest.match_indices('-').find_map(|(i, _)| {
+            Some((
+                lookup(CACHE_OPS, &rest[..i])?,
+                lookup(CACHE_RESULTS, &rest[i + 1..])?,
+            ))
+        })?
+    };
+    Some(PerfEventType::HwCache(cache_id, op, result, pmu_type))
+}
+
+/// Parse a decimal or `0x`-prefixed hexadecimal number.
+fn parse_number(s: &str) -> Result<u64, EventStringError> {
+    let parsed = match s.strip_prefix("0x").or_else(|| s.strip_prefix("0X")) {
+        Some(hex) => u64::from_str_radix(hex, 16),
+        None => s.parse(),
+    };
+    parsed.map_err(|_| EventStringError::InvalidNumber(s.to_owned()))
+}
+
+/// The PMU name which is used in event strings for a PMU type.
+fn pmu_name(type_: u32) -> String {
+    match type_ {
+        PERF_TYPE_SOFTWARE => "software".to_owned(),
+        PERF_TYPE_TRACEPOINT => "tracepoint".to_owned(),
+        PERF_TYPE_RAW => "cpu".to_owned(),
+        _ => type_.to_string(),
+    }
+}
+
+/// Names perf events the way `perf` does, e.g. `cycles`, `L1-dcache-load-misses`
+/// or `r1a8`.
+///
+/// Events of dynamic PMUs are displayed with the numeric PMU type, e.g.
+/// `9/config=0x1/`, because the PMU name isn't known here. Modifiers like
+/// `:u` are not included; use [`PerfEventAttr::event_string`] for those.
+impl fmt::Display for PerfEventType {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match *self {
+            Self::Hardware(_, PmuTypeId(0)) | Self::HwCache(.., PmuTypeId(0)) => {
+                f.write_str(&hardware_name(self).unwrap_or_default())
+            }
+            Self::Hardware(_, PmuTypeId(pmu)) | Self::HwCache(.., PmuTypeId(pmu)) => {
+                write!(f, "{}/{}/", pmu, hardware_name(self).unwrap_or_default())
+            }
+            Self::Software(counter_type) => f.write_str(software_event_name(counter_type)),
+            Self::Tracepoint(id) => write!(f, "tracepoint/config={id:#x}/"),
+            Self::Breakpoint(bp_type, HwBreakpointAddr(addr), len) => {
+                write!(f, "mem:{addr:#x}/{}", len.bytes())?;
+                if !bp_type.is_empty() {
+                    f.write_str(":")?;
+                    for (flag, c) in [
+                        (HwBreakpointType::R, 'r'),
+                        (HwBreakpointType::W, 'w'),
+                        (HwBreakpointType::X, 'x'),
+                    ] {
+                        if bp_type.contains(flag) {
+                            write!(f, "{c}")?;
+                        }
+                    }
+                }
+                Ok(())
+            }
+            Self::DynamicPmu(PERF_TYPE_RAW, config, 0, 0) => write!(f, "r{config:x}"),
+            Self::DynamicPmu(type_, config, config1, config2) => {
+                write!(f, "{}/config={config:#x}", pmu_name(type_))?;
+                if config1 != 0 {
+                    write!(f, ",config1={config1:#x}")?;
+                }
+                if config2 != 0 {
+                    write!(f, ",config2={config2:#x}")?;
+                }
+                f.write_str("/")
+            }
+        }
+    }
+}
+
+impl PerfEventAttr {
+    /// Parse an event in perf's event syntax, as used by `perf record -e`,
+    /// into an attr. Supported are:
+    ///
+    ///  - Hardware and software event names, e.g. `cycles` or `cs`.
+    ///  - Hardware cache events, e.g. `L1-dcache-load-misses` or `dTLB-loads`.
+    ///  - Raw events, e.g. `r1a8`.
+    ///  - PMU events with `config`, `config1`, `config2`, `period` and `freq`
+    ///    terms, e.g. `tracepoint/config=316/`. The PMU is `cpu`, `software`,
+    ///    `tracepoint` or a numeric PMU type. On x86, the `cpu` PMU also
+    ///    accepts the terms `event`, `umask`, `edge`, `pc`, `any`, `inv` and
+    ///    `cmask`, e.g. `cpu/event=0x3c,umask=0x0/`; on other architectures,
+    ///    these terms need a [`PmuRegistry`]. A PMU with a hardware event name
+    ///    as its only term selects that event on a hybrid PMU, e.g. `8/cycles/`.
+    ///  - Breakpoints, e.g. `mem:0x1000` or `mem:0x1000/8:w`. Breakpoints
+    ///    which this machine's kernel would reject are an error, see
+    ///    [`PerfEventType::validate`].
+    ///
+    /// These can be followed by modifiers, e.g. `cycles:ppp` or `cpu/.../uk`:
+    /// `u`, `k` and `h` restrict counting to user, kernel or hypervisor mode,
+    /// `G` and `H` to guest or host, `I` excludes the idle task, each `p`
+    /// increases the precision (`P` selects the maximum), `S` requests
+    /// [`SampleFormat::READ`], `D` pins the event and `e` makes it exclusive.
+    ///
+    /// Named tracepoints like `sched:sched_switch` are not supported, because
+    /// their IDs come from tracefs. For PMUs other than the ones above, and
+    /// for their named events, use [`PmuRegistry::parse_event_string`].
+    pub fn from_event_string(s: &str) -> Result<Self, EventStringError> {
+        parse_event_string(s, None)
+    }
+
+    /// The event string for this attr, i.e. the [`PerfEventType`] followed by
+    /// the modifiers for the `exclude_*`, precise IP, sample read, pinned and
+    /// exclusive settings. This can be parsed back with
+    /// [`PerfEventAttr::from_event_string`].
+    pub fn event_string(&self) -> String {
+        let flags = self.flags;
+        let mut modifiers = String::new();
+        let privilege_levels =
+            AttrFlags::EXCLUDE_USER | AttrFlags::EXCLUDE_KERNEL | AttrFlags::EXCLUDE_HV;
+        if flags.intersects(privilege_levels) {
+            for (flag, c) in [
+                (AttrFlags::EXCLUDE_USER, 'u'),
+                (AttrFlags::EXCLUDE_KERNEL, 'k'),
+                (AttrFlags::EXCLUDE_HV, 'h'),
+            ] {
+                if !flags.contains(flag) {
+                    modifiers.push(c);
+                }
+            }
+        }
+        if flags.intersects(AttrFlags::EXCLUDE_GUEST | AttrFlags::EXCLUDE_HOST) {
+            if !flags.contains(AttrFlags::EXCLUDE_GUEST) {
+                modifiers.push('G');
+            }
+            if !flags.contains(AttrFlags::EXCLUDE_HOST) {
+                modifiers.push('H');
+            }
+        }
+        if flags.contains(AttrFlags::EXCLUDE_IDLE) {
+            modifiers.push('I');
+        }
+        let precise = (flags & AttrFlags::PRECISE_IP_BITMASK).bits() >> 15;
+        modifiers.extend(std::iter::repeat_n('p', precise as usize));
+        if self.sample_format.contains(SampleFormat::READ) {
+            modifiers.push('S');
+        }
+        if flags.contains(AttrFlags::PINNED) {
+            modifiers.push('D');
+        }
+        if flags.contains(AttrFlags::EXCLUSIVE) {
+            modifiers.push('e');
+        }
+
+        let name = self.type_.to_string();
+        if modifiers.is_empty() {
+            name
+        } else if name.ends_with('/') {
+            format!("{name}{modifiers}")
+        } else {
+            format!("{name}:{modifiers}")
+        }
+    }
+}
+
+/// Parse an event string, with the PMUs of `pmus` if given. See
+/// [`PerfEventAttr::from_event_string`] and [`PmuRegistry::parse_event_string`].
+pub(crate) fn parse_event_string(
+    s: &str,
+    pmus: Option<&PmuRegistry>,
+) -> Result<PerfEventAttr, EventStringError> {
+    let (type_, sampling_policy, modifiers) = if let Some(rest) = s.strip_prefix("mem:") {
+        let (type_, modifiers) = parse_breakpoint(rest)?;
+        (type_, None, modifiers)
+    } else if let Some((pmu, rest)) = s.split_once('/') {
+        let (terms, modifiers) = rest
+            .rsplit_once('/')
+            .ok_or_else(|| EventStringError::UnknownEvent(s.to_owned()))?;
+        let (type_, sampling_policy) = parse_pmu_event(pmu, terms, pmus)?;
+        let modifiers = modifiers.strip_prefix(':').unwrap_or(modifiers);
+        (type_, sampling_policy, modifiers)
+    } else {
+        let (name, modifiers) = s.split_once(':').unwrap_or((s, ""));
+        (parse_event_name(name)?, None, modifiers)
+    };
+
+    let mut attr = PerfEventAttr::new(type_);
+    if let Some(sampling_policy) = sampling_policy {
+        attr.sampling_policy = sampling_policy;
+    }
+    apply_modifiers(&mut attr, modifiers)?;
+    Ok(attr)
+}
+
+/// Parse an event name without PMU, e.g. `cycles`, `dTLB-load-misses` or `r1a8`.
+fn parse_event_name(name: &str) -> Result<PerfEventType, EventStringError> {
+    if let Some(counter_type) = lookup(SOFTWARE_EVENTS, name) {
+        return Ok(PerfEventType::Software(counter_type));
+    }
+    if let Some(type_) = parse_hardware_name(name, PmuTypeId(0)) {
+        return Ok(type_);
+    }
+    match name.strip_prefix('r') {
+        Some(hex) if !hex.is_empty() && hex.bytes().all(|b| b.is_ascii_hexdigit()) => {
+            let config = u64::from_str_radix(hex, 16)
+                .map_err(|_| EventStringError::InvalidNumber(name.to_owned()))?;
+            Ok(PerfEventType::DynamicPmu(PERF_TYPE_RAW, config, 0, 0))
+        }
+        _ => Err(EventStringError::UnknownEvent(name.to_owned())),
+    }
+}
+
+/// Parse the part after `mem:`, e.g. `0x1000/8:rw:u`. Returns the type and
+/// the modifiers.
+fn parse_breakpoint(s: &str) -> Result<(PerfEventType, &str), EventStringError> {
+    let mut parts = s.splitn(3, ':');
+    let address_and_len = parts.next().unwrap_or_default();
+    let mut access = "";
+    let mut modifiers = parts.next().unwrap_or_default();
+    if !modifiers.is_empty() && modifiers.bytes().all(|b| matches!(b, b'r' | b'w' | b'x')) {
+        access = modifiers;
+        modifiers = parts.next().unwrap_or_default();
+    } else if let Some(rest) = parts.next() {
+        return Err(EventStringError::UnknownEvent(format!(
+            "{modifiers}:{rest}"
+        )));
+    }
+
+    let (address, len) = match address_and_len.split_once('/') {
+        Some((address, len)) => (address, Some(HwBreakpointLen::parse(parse_number(len)?))),
+        None => (address_and_len, None),
+    };
+    let address = parse_number(address)?;
+    let mut bp_type = HwBreakpointType::empty();
+    for c in access.chars() {
+        bp_type |= match c {
+            'r' => HwBreakpointType::R,
+            'w' => HwBreakpointType::W,
+            _ => HwBreakpointType::X,
+        };
+    }
+    if bp_type.is_empty() {
+        bp_type = HwBreakpointType::RW;
+    }
+    // Like perf, default to 4 bytes for data breakpoints and to the size of a
+    // pointer for execution breakpoints.
+    let len = len.unwrap_or(if bp_type == HwBreakpointType::X {
+        HwBreakpointLen::LONG
+    } else {
+        HwBreakpointLen::Len4
+    });
+    let type_ = PerfEventType::Breakpoint(bp_type, HwBreakpointAddr(address), len);
+    type_.validate()?;
+    Ok((type_, modifiers))
+}
+
+/// Parse a `pmu/terms/` event. Returns the type and, if the terms contain
+/// `period` or `freq`, the sampling policy.
+fn parse_pmu_event(
+    pmu: &str,
+    terms: &str,
+    pmus: Option<&PmuRegistry>,
+) -> Result<(PerfEventType, Option<SamplingPolicy>), EventStringError> {
+    let known_pmu = pmus.and_then(|pmus| pmus.by_name(pmu));
+    let pmu_type = match (known_pmu, pmu) {
+        (Some(known_pmu), _) => known_pmu.type_,
+        (None, "cpu") => PERF_TYPE_RAW,
+        (None, "software") => PERF_TYPE_SOFTWARE,
+        (None, "tracepoint") => PERF_TYPE_TRACEPOINT,
+        (None, _) => pmu
+            .parse()
+            .map_err(|_| EventStringError::UnknownPmu(pmu.to_owned()))?,
+    };
+
+    // Replace event aliases with their terms.
+    let mut expanded_terms = Vec::new();
+    for term in terms
+        .split(',')
+        .map(str::trim)
+        .filter(|term| !term.is_empty())
+    {
+        match known_pmu.and_then(|known_pmu| known_pmu.event(term)) {
+            Some(alias) => expanded_terms.extend(alias.terms.split(',').map(str::trim)),
+            None => expanded_terms.push(term),
+        }
+    }
+
+    let mut configs = [0u64; 3];
+    let mut named_event = None;
+    let mut sampling_policy = None;
+    for term in expanded_terms {
+        let invalid = || EventStringError::InvalidTerm(term.to_owned());
+        let (key, value) = match term.split_once('=') {
+            Some((key, value)) => (key, Some(parse_number(value)?)),
+            None => (term, None),
+        };
+        match key {
+            "config" | "config1" | "config2" => {
+                let index = match key {
+                    "config" => 0,
+                    "config1" => 1,
+                    _ => 2,
+                };
+                configs[index] = value.ok_or_else(invalid)?;
+            }
+            "period" => {
+                let period = NonZeroU64::new(value.ok_or_else(invalid)?).ok_or_else(invalid)?;
+                sampling_policy = Some(SamplingPolicy::Period(period));
+            }
+            "freq" => {
+                sampling_policy = Some(SamplingPolicy::Frequency(value.ok_or_else(invalid)?));
+            }
+            _ => {
+                if let Some(known_pmu) = known_pmu {
+                    if known_pmu.encode_term(key, value.unwrap_or(1), &mut configs)? {
+                        continue;
+                    }
+                }
+                if let Some(&(_, shift, bits)) = CPU_FORMAT.iter().find(|(name, ..)| {
+                    *name == key && pmu_type == PERF_TYPE_RAW && known_pmu.is_none()
+                }) {
+                    let value = value.unwrap_or(1);
+                    let mask = (1u64 << bits) - 1;
+                    if value > mask {
+                        return Err(invalid());
+                    }
+                    configs[0] = (configs[0] & !(mask << shift)) | (value << shift);
+                } else if value.is_none() && named_event.is_none() {
+                    // On the `cpu` PMU, a hardware event name is the same as
+                    // without PMU. On other PMUs, it selects a hybrid PMU.
+                    let hybrid_pmu = match pmu {
+                        "cpu" => PmuTypeId(0),
+                        _ => PmuTypeId(pmu_type),
+                    };
+                    named_event = Some(parse_hardware_name(key, hybrid_pmu).ok_or_else(invalid)?);
+                } else {
+                    return Err(invalid());
+                }
+            }
+        }
+    }
+
+    let type_ = match named_event {
+        Some(type_) if configs == [0; 3] => type_,
+        Some(_) => return Err(EventStringError::InvalidTerm(terms.to_owned())),
+        None => {
+            let [config, config1, config2] = configs;
+            PerfEventType::parse(pmu_type, 0, config, config1, config2)
+                .ok_or_else(|| EventStringError::UnknownEvent(format!("{pmu}/{terms}/")))?
+        }
+    };
+    Ok((type_, sampling_policy))
+}
+
+fn apply_modifiers(attr: &mut PerfEventAttr, modifiers: &str) -> Result<(), EventStringError> {
+    let (mut user, mut kernel, mut hv, mut guest, mut host) = (false, false, false, false, false);
+    let mut precise = 0u64;
+    for c in modifiers.chars() {
+        match c {
+            'u' => user = true,
+            'k' => kernel = true,
+            'h' => hv = true,
+            'G' => guest = true,
+            'H' => host = true,
+            'I' => attr.flags |= AttrFlags::EXCLUDE_IDLE,
+            'p' if precise < 3 => precise += 1,
+            'P' => precise = 3,
+            'S' => attr.sample_format |= SampleFormat::READ,
+            'D' => attr.flags |= AttrFlags::PINNED,
+            'e' => attr.flags |= AttrFlags::EXCLUSIVE,
+            _ => return Err(EventStringError::InvalidModifier(c)),
+        }
+    }
+    if user || kernel || hv {
+        attr.flags.set(AttrFlags::EXCLUDE_USER, !user);
+        attr.flags.set(AttrFlags::EXCLUDE_KERNEL, !kernel);
+        attr.flags.set(AttrFlags::EXCLUDE_HV, !hv);
+    }
+    if guest || host {
+        attr.flags.set(AttrFlags::EXCLUDE_GUEST, !guest);
+        attr.flags.set(AttrFlags::EXCLUDE_HOST, !host);
+    }
+    attr.flags |= AttrFlags::from_bits_truncate(precise << 15);
+    Ok(())
+}
+
+#[cfg(test)]
+mod test {
+    use super::EventStringError;
+    use crate::{AttrFlags, BreakpointError, IpSkidConstraint, PerfEventAttr, SamplingPolicy};
+
+    #[test]
+    fn round_trips() {
+        for s in [
+            "cycles",
+            "instructions:u",
+            "cycles:ppp",
+            "branch-misses:kIe",
+            "context-switches:S",
+            "L1-dcache-load-misses",
+            "L1-icache-loads:k",
+            "LLC-prefetches",
+            "dTLB-store-misses:GD",
+            "r1a8:p",
+            "cpu/config=0x1,config1=0x2/",
+            "tracepoint/config=0x13c/",
+            "8/cycles/u",
+            "9/config=0x1,config2=0x3/",
+            "mem:0x1000/8:w",
+            "mem:0x2000/4:rw:u",
+            "mem:0x3000/8:x",
+        ] {
+            let attr = PerfEventAttr::from_event_string(s).unwrap();
+            assert_eq!(attr.event_string(), s);
+        }
+
+        let attr = PerfEventAttr::from_event_string("cpu/config=0x13c,period=1000/uk").unwrap();
+        assert_eq!(attr.event_string(), "r13c:uk");
+        assert!(matches!(attr.sampling_policy, SamplingPolicy::Period(p) if p.get() == 1000));
+        let attr = PerfEventAttr::from_event_string("L1-dcache-misses:Pu").unwrap();
+        assert_eq!(attr.event_string(), "L1-dcache-load-misses:uppp");
+        assert_eq!(
+            attr.flags.ip_skid_constraint(),
+            IpSkidConstraint::ZeroSkidOrRandomization
+        );
+        assert!(attr
+            .flags
+            .contains(AttrFlags::EXCLUDE_KERNEL | AttrFlags::EXCLUDE_HV));
+        assert_eq!(
+            PerfEventAttr::from_event_string("mem:0x1000")
+                .unwrap()
+                .event_string(),
+            "mem:0x1000/4:rw"
+        );
+
+        assert_eq!(
+            PerfEventAttr::from_event_string("sched:sched_switch").err(),
+            Some(EventStringError::UnknownEvent("sched".to_owned()))
+        );
+        assert_eq!(
+            PerfEventAttr::from_event_string("cycles:pppp").err(),
+            Some(EventStringError::InvalidModifier('p'))
+        );
+        assert_eq!(
+            PerfEventAttr::from_event_string("foo/config=1/").err(),
+            Some(EventStringError::UnknownPmu("foo".to_owned()))
+        );
+        assert_eq!(
+            PerfEventAttr::from_event_string("mem:0x1000/9:w").err(),
+            Some(BreakpointError::InvalidLength(9).into())
+        );
+        assert_eq!(
+            PerfEventAttr::from_event_string("mem:0x1000/8:rx").err(),
+            Some(BreakpointError::InvalidType(5).into())
+        );
+        // Only x86 restricts lengths to powers of two.
+        let is_x86 = cfg!(any(target_arch = "x86", target_arch = "x86_64"));
+        assert_eq!(
+            PerfEventAttr::from_event_string("mem:0x1000/3:w").err(),
+            is_x86.then(|| BreakpointError::InvalidLength(3).into())
+        );
+    }
+
+    #[test]
+    fn cpu_format_terms() {
+        let attr = PerfEventAttr::from_event_string("cpu/event=0x3c,umask=0x1/uk");
+        if cfg!(any(target_arch = "x86", target_arch = "x86_64")) {
+            assert_eq!(attr.unwrap().event_string(), "r13c:uk");
+        } else {
+            assert_eq!(
+                attr.err(),
+                Some(EventStringError::InvalidTerm("event=0x3c".to_owned()))
+            );
+        }
+    }
+}
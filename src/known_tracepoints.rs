@@ -0,0 +1,897 @@
+use crate::{
+    Endianness, TracepointField, TracepointFieldKind, TracepointFormat, TracepointFormatError,
+    TracepointValue,
+};
+
+/// What a field of a well-known tracepoint needs to look like.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum FieldShape {
+    /// An integer which is at most this many bytes wide, so that a field
+    /// which a kernel has widened is reported instead of being truncated.
+    Int(usize),
+    /// A `char` array, or a `char` `__data_loc` / `__rel_loc` field.
+    Str,
+    /// An integer array with this many elements.
+    IntArray(usize),
+}
+
+impl FieldShape {
+    fn matches(self, field: &TracepointField) -> bool {
+        match (self, field.kind) {
+            (FieldShape::Int(max_size), TracepointFieldKind::Integer) => field.size <= max_size,
+            (FieldShape::Str, TracepointFieldKind::Array { .. })
+            | (FieldShape::Str, TracepointFieldKind::DataLoc)
+            | (FieldShape::Str, TracepointFieldKind::RelLoc) => field.is_char(),
+            (FieldShape::IntArray(expected_len), TracepointFieldKind::Array { len }) => {
+                len == expected_len && !field.is_char() && matches!(field.size / len, 1 | 2 | 4 | 8)
+            }
+            _ => false,
+        }
+    }
+}
+
+/// The fields of a well-known tracepoint, checked against its format.
+///
+/// Field offsets and sizes differ between kernel versions, so they are taken
+/// from the [`TracepointFormat`] of the recording machine, e.g. from
+/// [`TracingData::parse_event_formats`](crate::TracingData::parse_event_formats).
+/// Creating the layout fails if a field is missing or has an unexpected type,
+/// and decoding fails if a payload doesn't belong to the format's tracepoint,
+/// so that a mismatched layout is reported instead of producing garbage.
+///
+/// Created with the `layout` function of the typed tracepoint, e.g.
+/// [`SchedSwitch::layout`], and used with its `decode` function.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct KnownTracepointLayout {
+    names: &'static [&'static str],
+    id: u64,
+    common_type: TracepointField,
+    fields: Vec<TracepointField>,
+}
+
+impl KnownTracepointLayout {
+    fn new(
+        format: &TracepointFormat,
+        system: &'static str,
+        names: &'static [&'static str],
+        fields: &[(&str, FieldShape)],
+    ) -> Result<Self, TracepointFormatError> {
+        let system_matches = format.system.as_deref().is_none_or(|s| s == system);
+        if !system_matches || !names.contains(&format.name.as_str()) {
+            return Err(TracepointFormatError::WrongTracepoint {
+                expected: names[0],
+                actual: format.name.clone(),
+            });
+        }
+        let field = |name: &str, shape: FieldShape| {
+            let field = format
+                .field(name)
+                .ok_or_else(|| TracepointFormatError::MissingField(name.to_owned()))?;
+            if !shape.matches(field) {
+                return Err(TracepointFormatError::IncompatibleField(name.to_owned()));
+            }
+            Ok(field.clone())
+        };
+        Ok(Self {
+            names,
+            id: format.id,
+            common_type: field("common_type", FieldShape::Int(8))?,
+            fields: fields
+                .iter()
+                .map(|&(name, shape)| field(name, shape))
+                .collect::<Result<_, _>>()?,
+        })
+    }
+
+    /// The ID of the tracepoint.
+    pub fn id(&self) -> u64 {
+        self.id
+    }
+
+    /// The name of the tracepoint.
+    pub fn name(&self) -> &'static str {
+        self.names[0]
+    }
+
+    /// Check that this layout belongs to the decoding type and that the
+    /// payload belongs to this layout's tracepoint.
+    fn reader<'l, 'a>(
+        &'l self,
+        names: &'static [&'static str],
+        data: &'a [u8],
+        endian: Endianness,
+    ) -> Result<FieldReader<'l, 'a>, TracepointFormatError> {
+        if self.names != names {
+            return Err(TracepointFormatError::WrongTracepoint {
+                expected: names[0],
+                actual: self.names[0].to_owned(),
+            });
+        }
+        let reader = FieldReader {
+            fields: &self.fields,
+            data,
+            endian,
+        };
+        let common_type = reader
+            .decode(&self.common_type)?
+            .as_u64()
+            .unwrap_or_default();
+        if common_type != self.id {
+            return Err(TracepointFormatError::WrongTracepointId {
+                expected: self.id,
+                actual: common_type,
+            });
+        }
+        Ok(reader)
+    }
+}
+
+/// Reads the fields of a [`KnownTracepointLayout`], by their index in the
+/// field list which the layout was created with.
+struct FieldReader<'l, 'a> {
+    fields: &'l [TracepointField],
+    data: &'a [u8],
+    endian: Endianness,
+}
+
+impl<'a> FieldReader<'_, 'a> {
+    fn decode(
+        &self,
+        field: &TracepointField,
+    ) -> Result<TracepointValue<'a>, TracepointFormatError> {
+        field.decode(self.data, self.endian)
+    }
+
+    fn incompatible(&self, index: usize) -> TracepointFormatError {
+        TracepointFormatError::IncompatibleField(self.fields[index].name.clone())
+    }
+
+    fn int(&self, index: usize) -> Result<i64, TracepointFormatError> {
+        self.decode(&self.fields[index])?
+            .as_i64()
+            .ok_or_else(|| self.incompatible(index))
+    }
+
+    fn uint(&self, index: usize) -> Result<u64, TracepointFormatError> {
+        self.decode(&self.fields[index])?
+            .as_u64()
+            .ok_or_else(|| self.incompatible(index))
+    }
+
+    fn str(&self, index: usize) -> Result<&'a [u8], TracepointFormatError> {
+        match self.decode(&self.fields[index])? {
+            TracepointValue::Str(s) => Ok(s),
+            _ => Err(self.incompatible(index)),
+        }
+    }
+
+    fn uint_array<const N: usize>(&self, index: usize) -> Result<[u64; N], TracepointFormatError> {
+        let values = match self.decode(&self.fields[index])? {
+            TracepointValue::UnsignedArray(values) => values,
+            TracepointValue::SignedArray(values) => values.into_iter().map(|v| v as u64).collect(),
+            _ => return Err(self.incompatible(index)),
+        };
+        values.try_into().map_err(|_| self.incompatible(index))
+    }
+}
+
+/// `sched:sched_switch`: A task was switched out and another one was switched in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SchedSwitch<'a> {
+    pub prev_comm: &'a [u8],
+    pub prev_pid: i32,
+    pub prev_prio: i32,
+    /// The state of the previous task, e.g. 0 for runnable (preempted) and
+    /// 1 for `TASK_INTERRUPTIBLE`. The bit values depend on the kernel version;
+    /// see the format's `print_fmt`.
+    pub prev_state: u64,
+    pub next_comm: &'a [u8],
+    pub next_pid: i32,
+    pub next_prio: i32,
+}
+
+impl<'a> SchedSwitch<'a> {
+    pub const SYSTEM: &'static str = "sched";
+    pub const NAMES: &'static [&'static str] = &["sched_switch"];
+
+    /// Check `format` and get the field layout for [`SchedSwitch::decode`].
+    pub fn layout(
+        format: &TracepointFormat,
+    ) -> Result<KnownTracepointLayout, TracepointFormatError> {
+        KnownTracepointLayout::new(
+            format,
+            Self::SYSTEM,
+            Self::NAMES,
+            &[
+                ("prev_comm", FieldShape::Str),
+                ("prev_pid", FieldShape::Int(4)),
+                ("prev_prio", FieldShape::Int(4)),
+                ("prev_state", FieldShape::Int(8)),
+                ("next_comm", FieldShape::Str),
+                ("next_pid", FieldShape::Int(4)),
+                ("next_prio", FieldShape::Int(4)),
+            ],
+        )
+    }
+
+    /// Decode a payload, i.e. the `raw` data of a sample.
+    pub fn decode(
+        layout: &KnownTracepointLayout,
+        data: &'a [u8],
+        endian: Endianness,
+    ) -> Result<Self, TracepointFormatError> {
+        let r = layout.reader(Self::NAMES, data, endian)?;
+        Ok(Self {
+            prev_comm: r.str(0)?,
+            prev_pid: r.int(1)? as i32,
+            prev_prio: r.int(2)? as i32,
+            prev_state: r.uint(3)?,
+            next_comm: r.str(4)?,
+            next_pid: r.int(5)? as i32,
+            next_prio: r.int(6)? as i32,
+        })
+    }
+}
+
+/// `sched:sched_wakeup`, `sched:sched_wakeup_new` and `sched:sched_waking`:
+/// A task was woken up, or is about to be woken up.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SchedWakeup<'a> {
+    pub comm: &'a [u8],
+    pub pid: i32,
+    pub prio: i32,
+    /// The CPU on which the task will run.
+    pub target_cpu: i32,
+}
+
+impl<'a> SchedWakeup<'a> {
+    pub const SYSTEM: &'static str = "sched";
+    pub const NAMES: &'static [&'static str] =
+        &["sched_wakeup", "sched_wakeup_new", "sched_waking"];
+
+    /// Check `format` and get the field layout for [`SchedWakeup::decode`].
+    pub fn layout(
+        format: &TracepointFormat,
+    ) -> Result<KnownTracepointLayout, TracepointFormatError> {
+        KnownTracepointLayout::new(
+            format,
+            Self::SYSTEM,
+            Self::NAMES,
+            &[
+                ("comm", FieldShape::Str),
+                ("pid", FieldShape::Int(4)),
+                ("prio", FieldShape::Int(4)),
+                ("target_cpu", FieldShape::Int(4)),
+            ],
+        )
+    }
+
+    /// Decode a payload, i.e. the `raw` data of a sample.
+    pub fn decode(
+        layout: &KnownTracepointLayout,
+        data: &'a [u8],
+        endian: Endianness,
+    ) -> Result<Self, TracepointFormatError> {
+        let r = layout.reader(Self::NAMES, data, endian)?;
+        Ok(Self {
+            comm: r.str(0)?,
+            pid: r.int(1)? as i32,
+            prio: r.int(2)? as i32,
+            target_cpu: r.int(3)? as i32,
+        })
+    }
+}
+
+/// `sched:sched_process_exec`: A process called `exec`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SchedProcessExec<'a> {
+    pub filename: &'a [u8],
+    pub pid: i32,
+    /// The thread which called `exec`, if it wasn't the main thread.
+    pub old_pid: i32,
+}
+
+impl<'a> SchedProcessExec<'a> {
+    pub const SYSTEM: &'static str = "sched";
+    pub const NAMES: &'static [&'static str] = &["sched_process_exec"];
+
+    /// Check `format` and get the field layout for [`SchedProcessExec::decode`].
+    pub fn layout(
+        format: &TracepointFormat,
+    ) -> Result<KnownTracepointLayout, TracepointFormatError> {
+        KnownTracepointLayout::new(
+            format,
+            Self::SYSTEM,
+            Self::NAMES,
+            &[
+                ("filename", FieldShape::Str),
+                ("pid", FieldShape::Int(4)),
+                ("old_pid", FieldShape::Int(4)),
+            ],
+        )
+    }
+
+    /// Decode a payload, i.e. the `raw` data of a sample.
+    pub fn decode(
+        layout: &KnownTracepointLayout,
+        data: &'a [u8],
+        endian: Endianness,
+    ) -> Result<Self, TracepointFormatError> {
+        let r = layout.reader(Self::NAMES, data, endian)?;
+        Ok(Self {
+            filename: r.str(0)?,
+            pid: r.int(1)? as i32,
+            old_pid: r.int(2)? as i32,
+        })
+    }
+}
+
+/// `raw_syscalls:sys_enter`: A system call was entered.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SysEnter {
+    /// The system call number.
+    pub id: i64,
+    pub args: [u64; 6],
+}
+
+impl SysEnter {
+    pub const SYSTEM: &'static str = "raw_syscalls";
+    pub const NAMES: &'static [&'static str] = &["sys_enter"];
+
+    /// Check `format` and get the field layout for [`SysEnter::decode`].
+    pub fn layout(
+        format: &TracepointFormat,
+    ) -> Result<KnownTracepointLayout, TracepointFormatError> {
+        KnownTracepointLayout::new(
+            format,
+            Self::SYSTEM,
+            Self::NAMES,
+            &[
+                ("id", FieldShape::Int(8)),
+                ("args", FieldShape::IntArray(6)),
+            ],
+        )
+    }
+
+    /// Decode a payload, i.e. the `raw` data of a sample.
+    pub fn decode(
+        layout: &KnownTracepointLayout,
+        data: &[u8],
+        endian: Endianness,
+    ) -> Result<Self, TracepointFormatError> {
+        let r = layout.reader(Self::NAMES, data, endian)?;
+        Ok(Self {
+            id: r.int(0)?,
+            args: r.uint_array(1)?,
+        })
+    }
+}
+
+/// `raw_syscalls:sys_exit`: A system call returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SysExit {
+    /// The system call number.
+    pub id: i64,
+    pub ret: i64,
+}
+
+impl SysExit {
+    pub const SYSTEM: &'static str = "raw_syscalls";
+    pub const NAMES: &'static [&'static str] = &["sys_exit"];
+
+    /// Check `format` and get the field layout for [`SysExit::decode`].
+    pub fn layout(
+        format: &TracepointFormat,
+    ) -> Result<KnownTracepointLayout, TracepointFormatError> {
+        KnownTracepointLayout::new(
+            format,
+            Self::SYSTEM,
+            Self::NAMES,
+            &[("id", FieldShape::Int(8)), ("ret", FieldShape::Int(8))],
+        )
+    }
+
+    /// Decode a payload, i.e. the `raw` data of a sample.
+    pub fn decode(
+        layout: &KnownTracepointLayout,
+        data: &[u8],
+        endian: Endianness,
+    ) -> Result<Self, TracepointFormatError> {
+        let r = layout.reader(Self::NAMES, data, endian)?;
+        Ok(Self {
+            id: r.int(0)?,
+            ret: r.int(1)?,
+        })
+    }
+}
+
+/// `irq:irq_handler_entry`: An interrupt handler is about to run.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IrqHandlerEntry<'a> {
+    pub irq: i32,
+    /// The name of the handler.
+    pub name: &'a [u8],
+}
+
+impl<'a> IrqHandlerEntry<'a> {
+    pub const SYSTEM: &'static str = "irq";
+    pub const NAMES: &'static [&'static str] = &["irq_handler_entry"];
+
+    /// Check `format` and get the field layout for [`IrqHandlerEntry::decode`].
+    pub fn layout(
+        format: &TracepointFormat,
+    ) -> Result<KnownTracepointLayout, TracepointFormatError> {
+        KnownTracepointLayout::new(
+            format,
+            Self::SYSTEM,
+            Self::NAMES,
+            &[("irq", FieldShape::Int(4)), ("name", FieldShape::Str)],
+        )
+    }
+
+    /// Decode a payload, i.e. the `raw` data of a sample.
+    pub fn decode(
+        layout: &KnownTracepointLayout,
+        data: &'a [u8],
+        endian: Endianness,
+    ) -> Result<Self, TracepointFormatError> {
+        let r = layout.reader(Self::NAMES, data, endian)?;
+        Ok(Self {
+            irq: r.int(0)? as i32,
+            name: r.str(1)?,
+        })
+    }
+}
+
+/// `irq:irq_handler_exit`: An interrupt handler returned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IrqHandlerExit {
+    pub irq: i32,
+    /// Nonzero if the handler handled the interrupt.
+    pub ret: i32,
+}
+
+impl IrqHandlerExit {
+    pub const SYSTEM: &'static str = "irq";
+    pub const NAMES: &'static [&'static str] = &["irq_handler_exit"];
+
+    /// Check `format` and get the field layout for [`IrqHandlerExit::decode`].
+    pub fn layout(
+        format: &TracepointFormat,
+    ) -> Result<KnownTracepointLayout, TracepointFormatError> {
+        KnownTracepointLayout::new(
+            format,
+            Self::SYSTEM,
+            Self::NAMES,
+            &[("irq", FieldShape::Int(4)), ("ret", FieldShape::Int(4))],
+        )
+    }
+
+    /// Decode a payload, i.e. the `raw` data of a sample.
+    pub fn decode(
+        layout: &KnownTracepointLayout,
+        data: &[u8],
+        endian: Endianness,
+    ) -> Result<Self, TracepointFormatError> {
+        let r = layout.reader(Self::NAMES, data, endian)?;
+        Ok(Self {
+            irq: r.int(0)? as i32,
+            ret: r.int(1)? as i32,
+        })
+    }
+}
+
+/// `irq:softirq_entry`, `irq:softirq_exit` and `irq:softirq_raise`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Softirq {
+    /// The softirq number, e.g. 1 for `TIMER` and 3 for `NET_RX`.
+    pub vec: u32,
+}
+
+impl Softirq {
+    pub const SYSTEM: &'static str = "irq";
+    pub const NAMES: &'static [&'static str] = &["softirq_entry", "softirq_exit", "softirq_raise"];
+
+    /// Check `format` and get the field layout for [`Softirq::decode`].
+    pub fn layout(
+        format: &TracepointFormat,
+    ) -> Result<KnownTracepointLayout, TracepointFormatError> {
+        KnownTracepointLayout::new(
+            format,
+            Self::SYSTEM,
+            Self::NAMES,
+            &[("vec", FieldShape::Int(4))],
+        )
+    }
+
+    /// Decode a payload, i.e. the `raw` data of a sample.
+    pub fn decode(
+        layout: &KnownTracepointLayout,
+        data: &[u8],
+        endian: Endianness,
+    ) -> Result<Self, TracepointFormatError> {
+        let r = layout.reader(Self::NAMES, data, endian)?;
+        Ok(Self {
+            vec: r.uint(0)? as u32,
+        })
+    }
+}
+
+/// `block:block_rq_issue` and `block:block_rq_insert`: A block I/O request
+/// was sent to the device driver, or was inserted into the request queue.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockRqIssue<'a> {
+    /// The device, as a kernel `dev_t`: the major number is in the upper 12
+    /// bits, the minor number in the lower 20 bits.
+    pub dev: u32,
+    pub sector: u64,
+    pub nr_sector: u32,
+    pub bytes: u32,
+    /// The operation and flags, e.g. `R`, `WS` or `FF`.
+    pub rwbs: &'a [u8],
+    /// The command name of the task which issued the request.
+    pub comm: &'a [u8],
+}
+
+impl<'a> BlockRqIssue<'a> {
+    pub const SYSTEM: &'static str = "block";
+    pub const NAMES: &'static [&'static str] = &["block_rq_issue", "block_rq_insert"];
+
+    /// Check `format` and get the field layout for [`BlockRqIssue::decode`].
+    pub fn layout(
+        format: &TracepointFormat,
+    ) -> Result<KnownTracepointLayout, TracepointFormatError> {
+        KnownTracepointLayout::new(
+            format,
+            Self::SYSTEM,
+            Self::NAMES,
+            &[
+                ("dev", FieldShape::Int(4)),
+                ("sector", FieldShape::Int(8)),
+                ("nr_sector", FieldShape::Int(4)),
+                ("bytes", FieldShape::Int(4)),
+                ("rwbs", FieldShape::Str),
+                ("comm", FieldShape::Str),
+            ],
+        )
+    }
+
+    /// Decode a payload, i.e. the `raw` data of a sample.
+    pub fn decode(
+        layout: &KnownTracepointLayout,
+        data: &'a [u8],
+        endian: Endianness,
+    ) -> Result<Self, TracepointFormatError> {
+        let r = layout.reader(Self::NAMES, data, endian)?;
+        Ok(Self {
+            dev: r.uint(0)? as u32,
+            sector: r.uint(1)?,
+            nr_sector: r.uint(2)? as u32,
+            bytes: r.uint(3)? as u32,
+            rwbs: r.str(4)?,
+            comm: r.str(5)?,
+        })
+    }
+}
+
+/// `block:block_rq_complete`: A block I/O request was completed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct BlockRqComplete<'a> {
+    /// The device, as a kernel `dev_t`: the major number is in the upper 12
+    /// bits, the minor number in the lower 20 bits.
+    pub dev: u32,
+    pub sector: u64,
+    pub nr_sector: u32,
+    /// Zero on success, or a negative errno.
+    pub error: i32,
+    /// The operation and flags, e.g. `R`, `WS` or `FF`.
+    pub rwbs: &'a [u8],
+}
+
+impl<'a> BlockRqComplete<'a> {
+    pub const SYSTEM: &'static str = "block";
+    pub const NAMES: &'static [&'static str] = &["block_rq_complete"];
+
+    /// Check `format` and get the field layout for [`BlockRqComplete::decode`].
+    pub fn layout(
+        format: &TracepointFormat,
+    ) -> Result<KnownTracepointLayout, TracepointFormatError> {
+        KnownTracepointLayout::new(
+            format,
+            Self::SYSTEM,
+            Self::NAMES,
+            &[
+                ("dev", FieldShape::Int(4)),
+                ("sector", FieldShape::Int(8)),
+                ("nr_sector", FieldShape::Int(4)),
+                ("error", FieldShape::Int(4)),
+                ("rwbs", FieldShape::Str),
+            ],
+        )
+    }
+
+    /// Decode a payload, i.e. the `raw` data of a sample.
+    pub fn decode(
+        layout: &KnownTracepointLayout,
+        data: &'a [u8],
+        endian: Endianness,
+    ) -> Result<Self, TracepointFormatError> {
+        let r = layout.reader(Self::NAMES, data, endian)?;
+        Ok(Self {
+            dev: r.uint(0)? as u32,
+            sector: r.uint(1)?,
+            nr_sector: r.uint(2)? as u32,
+            error: r.int(3)? as i32,
+            rwbs: r.str(4)?,
+        })
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        BlockRqComplete, BlockRqIssue, IrqHandlerEntry, IrqHandlerExit, SchedProcessExec,
+        SchedSwitch, SchedWakeup, Softirq, SysEnter, SysExit,
+    };
+    use crate::{Endianness, TracepointFormat, TracepointFormatError};
+
+    const BLOCK_RQ_COMPLETE: &str =
+        include_str!("../tests/fixtures/tracepoint/block_rq_complete.format");
+    const BLOCK_RQ_ISSUE: &str = include_str!("../tests/fixtures/tracepoint/block_rq_issue.format");
+    const IRQ_HANDLER_ENTRY: &str =
+        include_str!("../tests/fixtures/tracepoint/irq_handler_entry.format");
+    const IRQ_HANDLER_EXIT: &str =
+        include_str!("../tests/fixtures/tracepoint/irq_handler_exit.format");
+    const SCHED_PROCESS_EXEC: &str =
+        include_str!("../tests/fixtures/tracepoint/sched_process_exec.format");
+    const SCHED_SWITCH: &str = include_str!("../tests/fixtures/tracepoint/sched_switch.format");
+    const SCHED_WAKEUP: &str = include_str!("../tests/fixtures/tracepoint/sched_wakeup.format");
+    const SCHED_WAKEUP_V3: &str =
+        include_str!("../tests/fixtures/tracepoint/sched_wakeup_v3.format");
+    const SOFTIRQ_ENTRY: &str = include_str!("../tests/fixtures/tracepoint/softirq_entry.format");
+    const SYS_ENTER: &str = include_str!("../tests/fixtures/tracepoint/sys_enter.format");
+    const SYS_EXIT: &str = include_str!("../tests/fixtures/tracepoint/sys_exit.format");
+
+    fn format(text: &str) -> TracepointFormat {
+        TracepointFormat::parse(text).unwrap()
+    }
+
+    /// A payload with the common fields, followed by `fields`.
+    fn payload(id: u16, fields: &[&[u8]]) -> Vec<u8> {
+        let mut data = id.to_le_bytes().to_vec();
+        data.extend_from_slice(&[0, 0]);
+        data.extend_from_slice(&1234i32.to_le_bytes());
+        for field in fields {
+            data.extend_from_slice(field);
+        }
+        data
+    }
+
+    #[test]
+    fn decode_and_detect_changes() {
+        let le = Endianness::LittleEndian;
+        let switch_format = format(SCHED_SWITCH);
+        let layout = SchedSwitch::layout(&switch_format).unwrap();
+        let data = payload(
+            316,
+            &[
+                b"bash\0\0\0\0\0\0\0\0\0\0\0\0",
+                &1234i32.to_le_bytes(),
+                &120i32.to_le_bytes(),
+                &1i64.to_le_bytes(),
+                b"kworker/0:1\0\0\0\0\0",
+                &15i32.to_le_bytes(),
+                &100i32.to_le_bytes(),
+            ],
+        );
+        let switch = SchedSwitch::decode(&layout, &data, le).unwrap();
+        assert_eq!(switch.prev_comm, b"bash");
+        assert_eq!((switch.prev_pid, switch.prev_state), (1234, 1));
+        assert_eq!(
+            (switch.next_comm, switch.next_pid),
+            (&b"kworker/0:1"[..], 15)
+        );
+
+        // The same payload with a different tracepoint ID is rejected.
+        let mut other = data.clone();
+        other[0] = 1;
+        assert_eq!(
+            SchedSwitch::decode(&layout, &other, le),
+            Err(TracepointFormatError::WrongTracepointId {
+                expected: 316,
+                actual: 257
+            })
+        );
+
+        // An older kernel had an extra field before target_cpu, so its offset
+        // is different.
+        let old_wakeup_format = format(SCHED_WAKEUP_V3);
+        let layout = SchedWakeup::layout(&old_wakeup_format).unwrap();
+        let data = payload(
+            64,
+            &[
+                b"sshd\0\0\0\0\0\0\0\0\0\0\0\0",
+                &42i32.to_le_bytes(),
+                &120i32.to_le_bytes(),
+                &1i32.to_le_bytes(),
+                &3i32.to_le_bytes(),
+            ],
+        );
+        let wakeup = SchedWakeup::decode(&layout, &data, le).unwrap();
+        assert_eq!((wakeup.pid, wakeup.target_cpu), (42, 3));
+        // A layout for one tracepoint can't be used for another.
+        assert!(matches!(
+            SchedSwitch::decode(&layout, &data, le),
+            Err(TracepointFormatError::WrongTracepoint { .. })
+        ));
+        assert!(matches!(
+            SchedWakeup::layout(&switch_format),
+            Err(TracepointFormatError::WrongTracepoint { .. })
+        ));
+
+        let mut enter_format = format(SYS_ENTER);
+        enter_format.system = Some("raw_syscalls".to_owned());
+        let layout = SysEnter::layout(&enter_format).unwrap();
+        let args: Vec<u8> = (1..=6u64).flat_map(u64::to_le_bytes).collect();
+        let data = payload(22, &[&257i64.to_le_bytes(), &args]);
+        let enter = SysEnter::decode(&layout, &data, le).unwrap();
+        assert_eq!((enter.id, enter.args), (257, [1, 2, 3, 4, 5, 6]));
+        // If a field's type changes, it's reported when creating the layout.
+        let mut changed = enter_format.clone();
+        changed.fields[5].size = 40;
+        changed.fields[5].kind = crate::TracepointFieldKind::Array { len: 5 };
+        assert_eq!(
+            SysEnter::layout(&changed),
+            Err(TracepointFormatError::IncompatibleField("args".to_owned()))
+        );
+
+        // A widened integer field would be truncated, so it's reported too.
+        let mut widened = switch_format.clone();
+        let next_pid = widened
+            .fields
+            .iter_mut()
+            .find(|field| field.name == "next_pid")
+            .unwrap();
+        next_pid.size = 8;
+        assert_eq!(
+            SchedSwitch::layout(&widened),
+            Err(TracepointFormatError::IncompatibleField(
+                "next_pid".to_owned()
+            ))
+        );
+
+        let complete_format = format(BLOCK_RQ_COMPLETE);
+        let layout = BlockRqComplete::layout(&complete_format).unwrap();
+        let data = payload(
+            1207,
+            &[
+                &((8u32 << 20) | 16).to_le_bytes(),
+                &[0; 4],
+                &2048u64.to_le_bytes(),
+                &8u32.to_le_bytes(),
+                &(-5i32).to_le_bytes(),
+                &0u16.to_le_bytes(),
+                b"WS\0\0\0\0\0\0\0\0",
+                &(48u32 | (1 << 16)).to_le_bytes(),
+                b"\0",
+            ],
+        );
+        let complete = BlockRqComplete::decode(&layout, &data, le).unwrap();
+        assert_eq!(complete.dev >> 20, 8);
+        assert_eq!((complete.sector, complete.nr_sector), (2048, 8));
+        assert_eq!((complete.error, complete.rwbs), (-5, &b"WS"[..]));
+        let mut missing = complete_format;
+        missing.fields.retain(|field| field.name != "error");
+        assert_eq!(
+            BlockRqComplete::layout(&missing),
+            Err(TracepointFormatError::MissingField("error".to_owned()))
+        );
+    }
+
+    #[test]
+    fn decode_current_formats() {
+        let le = Endianness::LittleEndian;
+
+        let layout = SchedWakeup::layout(&format(SCHED_WAKEUP)).unwrap();
+        let data = payload(
+            318,
+            &[
+                b"sshd\0\0\0\0\0\0\0\0\0\0\0\0",
+                &42i32.to_le_bytes(),
+                &120i32.to_le_bytes(),
+                &7i32.to_le_bytes(),
+            ],
+        );
+        let wakeup = SchedWakeup::decode(&layout, &data, le).unwrap();
+        assert_eq!((wakeup.comm, wakeup.pid), (&b"sshd"[..], 42));
+        assert_eq!((wakeup.prio, wakeup.target_cpu), (120, 7));
+
+        // The filename follows the fixed fields, at offset 20.
+        let layout = SchedProcessExec::layout(&format(SCHED_PROCESS_EXEC)).unwrap();
+        let data = payload(
+            311,
+            &[
+                &(20u32 | (14 << 16)).to_le_bytes(),
+                &500i32.to_le_bytes(),
+                &501i32.to_le_bytes(),
+                b"/usr/bin/true\0",
+            ],
+        );
+        assert_eq!(
+            SchedProcessExec::decode(&layout, &data, le).unwrap(),
+            SchedProcessExec {
+                filename: b"/usr/bin/true",
+                pid: 500,
+                old_pid: 501
+            }
+        );
+
+        let layout = SysExit::layout(&format(SYS_EXIT)).unwrap();
+        let data = payload(21, &[&0i64.to_le_bytes(), &(-11i64).to_le_bytes()]);
+        assert_eq!(
+            SysExit::decode(&layout, &data, le).unwrap(),
+            SysExit { id: 0, ret: -11 }
+        );
+
+        let layout = IrqHandlerEntry::layout(&format(IRQ_HANDLER_ENTRY)).unwrap();
+        let data = payload(
+            156,
+            &[
+                &24i32.to_le_bytes(),
+                &(16u32 | (8 << 16)).to_le_bytes(),
+                b"nvme0q1\0",
+            ],
+        );
+        assert_eq!(
+            IrqHandlerEntry::decode(&layout, &data, le).unwrap(),
+            IrqHandlerEntry {
+                irq: 24,
+                name: b"nvme0q1"
+            }
+        );
+
+        // A negative value in a 4-byte field survives the narrowing to i32.
+        let layout = IrqHandlerExit::layout(&format(IRQ_HANDLER_EXIT)).unwrap();
+        let data = payload(155, &[&(-1i32).to_le_bytes(), &1i32.to_le_bytes()]);
+        assert_eq!(
+            IrqHandlerExit::decode(&layout, &data, le).unwrap(),
+            IrqHandlerExit { irq: -1, ret: 1 }
+        );
+
+        let layout = Softirq::layout(&format(SOFTIRQ_ENTRY)).unwrap();
+        let data = payload(154, &[&3u32.to_le_bytes()]);
+        assert_eq!(
+            Softirq::decode(&layout, &data, le).unwrap(),
+            Softirq { vec: 3 }
+        );
+
+        // A large unsigned value survives the narrowing to u32.
+        let layout = BlockRqIssue::layout(&format(BLOCK_RQ_ISSUE)).unwrap();
+        let data = payload(
+            1205,
+            &[
+                &((259u32 << 20) | 1).to_le_bytes(),
+                &[0; 4],
+                &123456u64.to_le_bytes(),
+                &8u32.to_le_bytes(),
+                &u32::MAX.to_le_bytes(),
+                &0u16.to_le_bytes(),
+                b"R\0\0\0\0\0\0\0\0\0",
+                b"fio\0\0\0\0\0\0\0\0\0\0\0\0\0",
+                &(64u32 | (1 << 16)).to_le_bytes(),
+                b"\0",
+            ],
+        );
+        assert_eq!(
+            BlockRqIssue::decode(&layout, &data, le).unwrap(),
+            BlockRqIssue {
+                dev: (259 << 20) | 1,
+                sector: 123456,
+                nr_sector: 8,
+                bytes: u32::MAX,
+                rwbs: b"R",
+                comm: b"fio"
+            }
+        );
+    }
+}
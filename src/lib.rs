@@ -40,26 +40,62 @@
 //! );
 //! # }
 //! ```
+mod address_space;
+mod attr_table;
+mod aux_buffer;
 mod common_data;
 pub mod constants;
 mod endian;
 mod event_record;
+mod event_string;
+mod known_tracepoints;
+mod loss_tracker;
+#[cfg(all(feature = "open", target_os = "linux"))]
+mod open;
+mod ordered_queue;
 mod parse_info;
+mod perf_data;
 mod perf_event;
+mod pmu;
+mod probe_event;
+mod process_tracker;
 mod raw_data;
+mod read_format;
 mod registers;
+mod ring_buffer;
 mod sample;
+mod sched_timeline;
+mod time_conv;
+mod tracepoint;
 mod types;
 mod utils;
 
+pub use address_space::*;
+pub use attr_table::*;
+pub use aux_buffer::*;
 pub use common_data::*;
 pub use endian::*;
 pub use event_record::*;
+pub use event_string::*;
+pub use known_tracepoints::*;
+pub use loss_tracker::*;
+#[cfg(all(feature = "open", target_os = "linux"))]
+pub use open::*;
+pub use ordered_queue::*;
 pub use parse_info::*;
+pub use perf_data::*;
 pub use perf_event::*;
+pub use pmu::*;
+pub use probe_event::*;
+pub use process_tracker::*;
 pub use raw_data::*;
+pub use read_format::*;
 pub use registers::*;
+pub use ring_buffer::*;
 pub use sample::*;
+pub use sched_timeline::*;
+pub use time_conv::*;
+pub use tracepoint::*;
 pub use types::*;
 
 #[cfg(test)]
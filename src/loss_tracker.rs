@@ -0,0 +1,341 @@
+use std::collections::{BTreeMap, HashMap};
+
+use crate::{CommonData, EventRecord, LostRecord, LostSamplesRecord, ThrottleRecord};
+
+/// Lost data for one event ID.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct LossCounts {
+    /// Records which were dropped because the ring buffer was full, from
+    /// `PERF_RECORD_LOST` records.
+    pub lost_records: u64,
+    /// Samples which were dropped, from `PERF_RECORD_LOST_SAMPLES` records.
+    pub lost_samples: u64,
+}
+
+/// A period during which the kernel throttled an event because it generated
+/// samples too quickly.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ThrottleWindow {
+    /// The ID of the throttled event.
+    pub id: u64,
+    /// The time of the THROTTLE record.
+    pub start: u64,
+    /// The time of the UNTHROTTLE record, or `None` if the event was still
+    /// throttled at the end of the recording.
+    pub end: Option<u64>,
+}
+
+/// The sample and loss counts in one time bucket of a [`LossTracker`].
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimeBucket {
+    /// The start time of the bucket.
+    pub start: u64,
+    /// The number of samples which arrived.
+    pub samples: u64,
+    /// The number of lost records and lost samples.
+    pub lost: u64,
+    /// The throttled time in this bucket, summed over all events.
+    pub throttled_time: u64,
+}
+
+impl TimeBucket {
+    /// The fraction of samples which arrived, out of all samples which arrived
+    /// or were lost, between 0.0 and 1.0. Returns 1.0 for buckets without
+    /// samples or losses.
+    ///
+    /// Throttling isn't included: throttled samples were never taken, so they
+    /// can't be counted. Check `throttled_time` for that.
+    pub fn estimated_coverage(&self) -> f64 {
+        let expected = self.samples + self.lost;
+        if expected == 0 {
+            return 1.0;
+        }
+        self.samples as f64 / expected as f64
+    }
+}
+
+/// Accounts for data which was lost or never recorded, from LOST,
+/// LOST_SAMPLES, THROTTLE and UNTHROTTLE records.
+///
+/// Losses are attributed to event IDs, and are also collected in time
+/// buckets together with the number of samples, so that regions of a profile
+/// with unreliable data can be found with [`LossTracker::buckets`].
+///
+/// Lost records are only assigned to a bucket if the records have a timestamp,
+/// i.e. if the attr has [`AttrFlags::SAMPLE_ID_ALL`](crate::AttrFlags::SAMPLE_ID_ALL)
+/// and [`SampleFormat::TIME`](crate::SampleFormat::TIME).
+#[derive(Debug, Clone)]
+pub struct LossTracker {
+    bucket_duration: u64,
+    counts_by_id: HashMap<u64, LossCounts>,
+    /// Losses from LOST_SAMPLES records without an ID.
+    unattributed: LossCounts,
+    /// Throttle windows, in the order in which they started.
+    throttle_windows: Vec<ThrottleWindow>,
+    /// The index in `throttle_windows` of the open window for each ID.
+    open_throttle_windows: HashMap<u64, usize>,
+    /// Keyed by bucket index, i.e. the timestamp divided by the bucket duration.
+    buckets: BTreeMap<u64, TimeBucket>,
+    last_timestamp: Option<u64>,
+}
+
+impl LossTracker {
+    /// Create a tracker which collects samples and losses into buckets of
+    /// `bucket_duration` nanoseconds (or whatever unit the timestamps use).
+    ///
+    /// # Panics
+    ///
+    /// If `bucket_duration` is zero.
+    pub fn new(bucket_duration: u64) -> Self {
+        assert!(bucket_duration > 0, "The bucket duration must not be zero");
+        Self {
+            bucket_duration,
+            counts_by_id: HashMap::new(),
+            unattributed: LossCounts::default(),
+            throttle_windows: Vec::new(),
+            open_throttle_windows: HashMap::new(),
+            buckets: BTreeMap::new(),
+            last_timestamp: None,
+        }
+    }
+
+    /// Apply a record. Samples are counted, and LOST, LOST_SAMPLES, THROTTLE
+    /// and UNTHROTTLE records are accounted for. Other records are ignored.
+    pub fn handle_record(&mut self, record: &EventRecord, common_data: &CommonData) {
+        let timestamp = common_data.timestamp;
+        match record {
+            EventRecord::Sample(_) => self.handle_sample(timestamp),
+            EventRecord::Lost(record) => self.handle_lost(record, timestamp),
+            EventRecord::Throttle(record) => self.handle_throttle(record),
+            EventRecord::Unthrottle(record) => self.handle_unthrottle(record),
+            EventRecord::LostSamples(record) => {
+                self.handle_lost_samples(record, common_data.id, timestamp)
+            }
+            _ => {}
+        }
+    }
+
+    /// Count a sample.
+    pub fn handle_sample(&mut self, timestamp: Option<u64>) {
+        if let Some(timestamp) = timestamp {
+            self.bucket_mut(timestamp).samples += 1;
+        }
+    }
+
+    /// Apply a LOST record.
+    pub fn handle_lost(&mut self, record: &LostRecord, timestamp: Option<u64>) {
+        self.counts_by_id.entry(record.id).or_default().lost_records += record.count;
+        if let Some(timestamp) = timestamp {
+            self.bucket_mut(timestamp).lost += record.count;
+        }
+    }
+
+    /// Apply a LOST_SAMPLES record. `id` is the record's [`CommonData::id`].
+    pub fn handle_lost_samples(
+        &mut self,
+        record: &LostSamplesRecord,
+        id: Option<u64>,
+        timestamp: Option<u64>,
+    ) {
+        let counts = match id {
+            Some(id) => self.counts_by_id.entry(id).or_default(),
+            None => &mut self.unattributed,
+        };
+        counts.lost_samples += record.lost;
+        if let Some(timestamp) = timestamp {
+            self.bucket_mut(timestamp).lost += record.lost;
+        }
+    }
+
+    /// Apply a THROTTLE record.
+    pub fn handle_throttle(&mut self, record: &ThrottleRecord) {
+        self.note_timestamp(record.timestamp);
+        if self.open_throttle_windows.contains_key(&record.id) {
+            return;
+        }
+        self.open_throttle_windows
+            .insert(record.id, self.throttle_windows.len());
+        self.throttle_windows.push(ThrottleWindow {
+            id: record.id,
+            start: record.timestamp,
+            end: None,
+        });
+    }
+
+    /// Apply an UNTHROTTLE record.
+    pub fn handle_unthrottle(&mut self, record: &ThrottleRecord) {
+        self.note_timestamp(record.timestamp);
+        if let Some(index) = self.open_throttle_windows.remove(&record.id) {
+            self.throttle_windows[index].end = Some(record.timestamp);
+        }
+    }
+
+    /// The losses for the event with this ID.
+    pub fn counts_for_id(&self, id: u64) -> LossCounts {
+        self.counts_by_id.get(&id).copied().unwrap_or_default()
+    }
+
+    /// The losses for all events, including the ones that couldn't be
+    /// attributed to an event.
+    pub fn total_counts(&self) -> LossCounts {
+        self.counts_by_id
+            .values()
+            .fold(self.unattributed, |total, counts| LossCounts {
+                lost_records: total.lost_records + counts.lost_records,
+                lost_samples: total.lost_samples + counts.lost_samples,
+            })
+    }
+
+    /// All throttle windows, in the order in which they started.
+    pub fn throttle_windows(&self) -> &[ThrottleWindow] {
+        &self.throttle_windows
+    }
+
+    /// The time buckets which have samples or losses, or in which a throttle
+    /// window starts or ends, in ascending order. Empty buckets are left out,
+    /// so that a single outlier timestamp doesn't create millions of them.
+    ///
+    /// Throttle windows which haven't ended are counted until the last
+    /// timestamp. Buckets which lie entirely inside a throttle window are
+    /// only included if they have samples or losses; see
+    /// [`LossTracker::throttle_windows`] for the complete windows.
+    pub fn buckets(&self) -> Vec<TimeBucket> {
+        let Some(last_timestamp) = self.last_timestamp else {
+            return Vec::new();
+        };
+        let mut buckets = self.buckets.clone();
+        for window in &self.throttle_windows {
+            let start = window.start;
+            let end = window.end.unwrap_or(last_timestamp).max(start);
+            let first_index = start / self.bucket_duration;
+            let last_index = end / self.bucket_duration;
+            for index in [first_index, last_index] {
+                buckets.entry(index).or_insert(TimeBucket {
+                    start: index * self.bucket_duration,
+                    ..Default::default()
+                });
+            }
+            for (_, bucket) in buckets.range_mut(first_index..=last_index) {
+                let overlap_start = start.max(bucket.start);
+                let overlap_end = end.min(bucket.start.saturating_add(self.bucket_duration));
+                bucket.throttled_time += overlap_end.saturating_sub(overlap_start);
+            }
+        }
+        buckets.into_values().collect()
+    }
+
+    fn bucket_mut(&mut self, timestamp: u64) -> &mut TimeBucket {
+        self.note_timestamp(timestamp);
+        let index = timestamp / self.bucket_duration;
+        self.buckets.entry(index).or_insert(TimeBucket {
+            start: index * self.bucket_duration,
+            ..Default::default()
+        })
+    }
+
+    fn note_timestamp(&mut self, timestamp: u64) {
+        self.last_timestamp = self.last_timestamp.max(Some(timestamp));
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{LossCounts, LossTracker, ThrottleWindow};
+    use crate::{
+        CommonData, Endianness, EventRecord, LostRecord, LostSamplesRecord, PerfEventAttr,
+        PerfEventType, RawData, RawEventRecord, RecordParseInfo, RecordType, SoftwareCounterType,
+        ThrottleRecord,
+    };
+
+    #[test]
+    fn losses_and_throttling() {
+        let mut tracker = LossTracker::new(100);
+        for timestamp in [10, 20, 30, 250] {
+            tracker.handle_sample(Some(timestamp));
+        }
+        tracker.handle_lost(&LostRecord { id: 5, count: 3 }, Some(40));
+        tracker.handle_lost_samples(&LostSamplesRecord { lost: 2 }, Some(6), None);
+        tracker.handle_throttle(&ThrottleRecord {
+            id: 5,
+            timestamp: 150,
+        });
+        tracker.handle_unthrottle(&ThrottleRecord {
+            id: 5,
+            timestamp: 220,
+        });
+
+        assert_eq!(
+            tracker.counts_for_id(5),
+            LossCounts {
+                lost_records: 3,
+                lost_samples: 0
+            }
+        );
+        assert_eq!(
+            tracker.total_counts(),
+            LossCounts {
+                lost_records: 3,
+                lost_samples: 2
+            }
+        );
+        assert_eq!(
+            tracker.throttle_windows(),
+            [ThrottleWindow {
+                id: 5,
+                start: 150,
+                end: Some(220)
+            }]
+        );
+
+        let buckets = tracker.buckets();
+        assert_eq!(buckets.len(), 3);
+        assert_eq!((buckets[0].samples, buckets[0].lost), (3, 3));
+        assert_eq!(buckets[0].estimated_coverage(), 0.5);
+        assert_eq!(buckets[1].throttled_time, 50);
+        assert_eq!(buckets[2].throttled_time, 20);
+        assert_eq!(buckets[2].estimated_coverage(), 1.0);
+    }
+
+    #[test]
+    fn outlier_timestamp() {
+        let mut tracker = LossTracker::new(1);
+        tracker.handle_sample(Some(10));
+        tracker.handle_sample(Some(1 << 60));
+        tracker.handle_throttle(&ThrottleRecord {
+            id: 1,
+            timestamp: 11,
+        });
+
+        let buckets = tracker.buckets();
+        let starts: Vec<u64> = buckets.iter().map(|bucket| bucket.start).collect();
+        assert_eq!(starts, [10, 11, 1 << 60]);
+        assert_eq!(buckets[1].throttled_time, 1);
+        assert_eq!(buckets[2].throttled_time, 0);
+    }
+
+    #[test]
+    fn lost_samples_record() {
+        let attr = PerfEventAttr::new(PerfEventType::Software(SoftwareCounterType::CpuClock));
+        let parse_info = RecordParseInfo::new(&attr, Endianness::LittleEndian);
+        let data = 7u64.to_le_bytes();
+        let raw = RawEventRecord::new(
+            RecordType::LOST_SAMPLES,
+            0,
+            RawData::from(&data[..]),
+            parse_info,
+        );
+        let record = raw.parse().unwrap();
+        assert_eq!(
+            record,
+            EventRecord::LostSamples(LostSamplesRecord { lost: 7 })
+        );
+
+        let mut tracker = LossTracker::new(100);
+        let common_data = CommonData {
+            id: Some(3),
+            ..Default::default()
+        };
+        tracker.handle_record(&record, &common_data);
+        assert_eq!(tracker.counts_for_id(3).lost_samples, 7);
+    }
+}
@@ -0,0 +1,350 @@
+use crate::constants::*;
+use crate::{
+    MmapRingBuffer, PerfEventAttr, PerfEventMmapPage, RawData, ReadFormat, ReadFormatValues,
+    RingBufferDirection,
+};
+use bitflags::bitflags;
+use byteorder::NativeEndian;
+use std::io;
+use std::os::fd::{AsFd, AsRawFd, BorrowedFd, FromRawFd, OwnedFd, RawFd};
+use std::ptr::NonNull;
+
+const PERF_EVENT_IOC_ENABLE: libc::Ioctl = libc::_IO(b'$' as u32, 0);
+const PERF_EVENT_IOC_DISABLE: libc::Ioctl = libc::_IO(b'$' as u32, 1);
+const PERF_EVENT_IOC_REFRESH: libc::Ioctl = libc::_IO(b'$' as u32, 2);
+const PERF_EVENT_IOC_RESET: libc::Ioctl = libc::_IO(b'$' as u32, 3);
+const PERF_EVENT_IOC_SET_OUTPUT: libc::Ioctl = libc::_IO(b'$' as u32, 5);
+const PERF_EVENT_IOC_ID: libc::Ioctl = libc::_IOR::<*mut u64>(b'$' as u32, 7);
+const PERF_EVENT_IOC_PAUSE_OUTPUT: libc::Ioctl = libc::_IOW::<u32>(b'$' as u32, 9);
+
+/// `PERF_IOC_FLAG_GROUP`: Apply an ioctl to all events in the group.
+const PERF_IOC_FLAG_GROUP: libc::c_int = 1;
+
+bitflags! {
+    /// The flags argument of `perf_event_open`.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub struct PerfEventOpenFlags: u64 {
+        /// Ignore the group_fd argument (except with `FD_OUTPUT`).
+        const FD_NO_GROUP = PERF_FLAG_FD_NO_GROUP;
+        /// Reroute the output to the group_fd's ring buffer.
+        const FD_OUTPUT = PERF_FLAG_FD_OUTPUT;
+        /// The pid argument is a cgroup fd; per-container monitoring.
+        const PID_CGROUP = PERF_FLAG_PID_CGROUP;
+        /// Set the close-on-exec flag on the new fd.
+        const FD_CLOEXEC = PERF_FLAG_FD_CLOEXEC;
+    }
+}
+
+/// An owned perf event file descriptor, as returned by `perf_event_open`.
+///
+/// The fd is closed when this object is dropped.
+#[derive(Debug)]
+pub struct PerfEventFd {
+    fd: OwnedFd,
+    direction: RingBufferDirection,
+    read_format: ReadFormat,
+}
+
+impl PerfEventFd {
+    /// Call `perf_event_open` with the serialized `attr`.
+    ///
+    /// From the `perf_event_open` man page:
+    ///
+    ///  - `pid == 0` and `cpu == -1`: This measures the calling process/thread on any CPU.
+    ///  - `pid == 0` and `cpu >= 0`: This measures the calling process/thread only
+    ///    when running on the specified CPU.
+    ///  - `pid > 0` and `cpu == -1`: This measures the specified process/thread on any CPU.
+    ///  - `pid > 0` and `cpu >= 0`: This measures the specified process/thread only
+    ///    when running on the specified CPU.
+    ///  - `pid == -1` and `cpu >= 0`: This measures all processes/threads on the
+    ///    specified CPU.
+    ///
+    /// `group_fd` is the group leader, if this event should be part of an
+    /// existing event group.
+    pub fn open(
+        attr: &PerfEventAttr,
+        pid: i32,
+        cpu: i32,
+        group_fd: Option<BorrowedFd<'_>>,
+        flags: PerfEventOpenFlags,
+    ) -> Result<Self, io::Error> {
+        let mut attr_bytes = Vec::with_capacity(PERF_ATTR_SIZE_VER7 as usize);
+        attr.write::<_, NativeEndian>(&mut attr_bytes)?;
+        let group_fd = group_fd.map_or(-1, |fd| fd.as_raw_fd());
+
+        // Safety: attr_bytes is a complete perf_event_attr, and the kernel
+        // only reads it.
+        let ret = unsafe {
+            libc::syscall(
+                libc::SYS_perf_event_open,
+                attr_bytes.as_ptr(),
+                pid as libc::pid_t,
+                cpu as libc::c_int,
+                group_fd as libc::c_int,
+                flags.bits() as libc::c_ulong,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+
+        // Safety: The syscall returned a new fd which nobody else owns.
+        let fd = unsafe { OwnedFd::from_raw_fd(ret as RawFd) };
+        Ok(Self {
+            fd,
+            direction: RingBufferDirection::from_attr_flags(attr.flags),
+            read_format: attr.read_format,
+        })
+    }
+
+    /// Start counting. (`PERF_EVENT_IOC_ENABLE`)
+    ///
+    /// If `group` is true, all events in the group of this group leader are enabled.
+    pub fn enable(&self, group: bool) -> Result<(), io::Error> {
+        self.ioctl(PERF_EVENT_IOC_ENABLE, group_flag(group))
+    }
+
+    /// Stop counting. (`PERF_EVENT_IOC_DISABLE`)
+    pub fn disable(&self, group: bool) -> Result<(), io::Error> {
+        self.ioctl(PERF_EVENT_IOC_DISABLE, group_flag(group))
+    }
+
+    /// Reset the event count to zero. (`PERF_EVENT_IOC_RESET`)
+    pub fn reset(&self, group: bool) -> Result<(), io::Error> {
+        self.ioctl(PERF_EVENT_IOC_RESET, group_flag(group))
+    }
+
+    /// Enable the event for `count` more overflows, after which it is
+    /// disabled again. (`PERF_EVENT_IOC_REFRESH`)
+    pub fn refresh(&self, count: i32) -> Result<(), io::Error> {
+        self.ioctl(PERF_EVENT_IOC_REFRESH, count)
+    }
+
+    /// Pause or resume writing into the ring buffer. (`PERF_EVENT_IOC_PAUSE_OUTPUT`)
+    ///
+    /// This should be used while reading a buffer in backward mode.
+    pub fn pause_output(&self, pause: bool) -> Result<(), io::Error> {
+        self.ioctl(PERF_EVENT_IOC_PAUSE_OUTPUT, libc::c_int::from(pause))
+    }
+
+    /// Send this event's records to the ring buffer of `output`, which must
+    /// be on the same CPU. (`PERF_EVENT_IOC_SET_OUTPUT`)
+    pub fn set_output(&self, output: BorrowedFd<'_>) -> Result<(), io::Error> {
+        self.ioctl(PERF_EVENT_IOC_SET_OUTPUT, output.as_raw_fd())
+    }
+
+    /// The event ID, which matches the ID in records with
+    /// `SampleFormat::ID` or `SampleFormat::IDENTIFIER`. (`PERF_EVENT_IOC_ID`)
+    pub fn id(&self) -> Result<u64, io::Error> {
+        let mut id = 0u64;
+        // Safety: The kernel writes a u64 to the pointer.
+        let ret = unsafe {
+            libc::ioctl(
+                self.fd.as_raw_fd(),
+                PERF_EVENT_IOC_ID as _,
+                &mut id as *mut u64,
+            )
+        };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(id)
+    }
+
+    /// Read the current counter values, in the layout given by the attr's
+    /// `read_format`.
+    pub fn read_values(&self) -> Result<ReadFormatValues, io::Error> {
+        // Start with room for a small group, and grow if the kernel says
+        // that the buffer is too small.
+        let mut buf = vec![0u8; 512];
+        loop {
+            // Safety: buf is valid for writes of buf.len() bytes.
+            let ret = unsafe {
+                libc::read(
+                    self.fd.as_raw_fd(),
+                    buf.as_mut_ptr() as *mut libc::c_void,
+                    buf.len(),
+                )
+            };
+            if ret >= 0 {
+                let data = RawData::from(&buf[..ret as usize]);
+                return ReadFormatValues::parse::<NativeEndian>(data, self.read_format);
+            }
+            let err = io::Error::last_os_error();
+            if err.raw_os_error() != Some(libc::ENOSPC) {
+                return Err(err);
+            }
+            buf.resize(buf.len() * 2, 0);
+        }
+    }
+
+    /// Map the ring buffer with `data_pages` pages of data, which must be a
+    /// power of two. With zero data pages, only the [`PerfEventMmapPage`] is mapped.
+    ///
+    /// For events with `AttrFlags::WRITE_BACKWARD`, the mapping is read-only,
+    /// which puts the buffer into overwrite mode.
+    pub fn mmap(&self, data_pages: usize) -> Result<PerfEventMmap, io::Error> {
+        if data_pages != 0 && !data_pages.is_power_of_two() {
+            return Err(io::ErrorKind::InvalidInput.into());
+        }
+        // Safety: sysconf has no preconditions.
+        let page_size = unsafe { libc::sysconf(libc::_SC_PAGESIZE) } as usize;
+        let len = (data_pages + 1) * page_size;
+        let prot = match self.direction {
+            RingBufferDirection::Forward => libc::PROT_READ | libc::PROT_WRITE,
+            RingBufferDirection::Backward => libc::PROT_READ,
+        };
+        // Safety: This creates a new mapping and doesn't touch existing memory.
+        let ptr = unsafe {
+            libc::mmap(
+                std::ptr::null_mut(),
+                len,
+                prot,
+                libc::MAP_SHARED,
+                self.fd.as_raw_fd(),
+                0,
+            )
+        };
+        if ptr == libc::MAP_FAILED {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(PerfEventMmap {
+            base: NonNull::new(ptr as *mut u8).ok_or(io::ErrorKind::Other)?,
+            len,
+            direction: self.direction,
+        })
+    }
+
+    fn ioctl(&self, request: libc::Ioctl, arg: libc::c_int) -> Result<(), io::Error> {
+        // Safety: All ioctls used with this helper take an integer argument.
+        let ret = unsafe { libc::ioctl(self.fd.as_raw_fd(), request as _, arg) };
+        if ret < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(())
+    }
+}
+
+fn group_flag(group: bool) -> libc::c_int {
+    if group {
+        PERF_IOC_FLAG_GROUP
+    } else {
+        0
+    }
+}
+
+impl AsFd for PerfEventFd {
+    fn as_fd(&self) -> BorrowedFd<'_> {
+        self.fd.as_fd()
+    }
+}
+
+impl AsRawFd for PerfEventFd {
+    fn as_raw_fd(&self) -> RawFd {
+        self.fd.as_raw_fd()
+    }
+}
+
+impl From<PerfEventFd> for OwnedFd {
+    fn from(fd: PerfEventFd) -> Self {
+        fd.fd
+    }
+}
+
+/// The mmap'd ring buffer of a [`PerfEventFd`]. Unmapped on drop.
+#[derive(Debug)]
+pub struct PerfEventMmap {
+    base: NonNull<u8>,
+    len: usize,
+    direction: RingBufferDirection,
+}
+
+// Safety: The mapping is not tied to a thread.
+unsafe impl Send for PerfEventMmap {}
+
+impl PerfEventMmap {
+    /// A reader for the ring buffer. Fails if no data pages were mapped.
+    pub fn ring_buffer(&mut self) -> Result<MmapRingBuffer<'_>, io::Error> {
+        // Safety: The mapping is page-aligned and lives as long as self,
+        // and it is only written to by the kernel and by the ring buffer reader.
+        unsafe { MmapRingBuffer::from_raw_parts(self.base, self.len, self.direction) }
+    }
+
+    /// Read the current values of the [`PerfEventMmapPage`].
+    pub fn mmap_page(&mut self) -> Result<PerfEventMmapPage, io::Error> {
+        Ok(self.ring_buffer()?.mmap_page())
+    }
+}
+
+impl Drop for PerfEventMmap {
+    fn drop(&mut self) {
+        // Safety: The mapping was created by mmap with this length.
+        unsafe {
+            libc::munmap(self.base.as_ptr() as *mut libc::c_void, self.len);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{PerfEventFd, PerfEventOpenFlags};
+    use crate::{
+        AttrFlags, EventRecord, PerfEventAttr, PerfEventType, ReadFormat, RecordParseInfo,
+        RecordType, SoftwareCounterType,
+    };
+
+    // These tests need perf_event_open to be allowed, which it often isn't in
+    // containers or with a restrictive perf_event_paranoid setting. Run them
+    // with `cargo test --features open -- --ignored`.
+
+    fn open(attr: &PerfEventAttr) -> PerfEventFd {
+        PerfEventFd::open(attr, 0, -1, None, PerfEventOpenFlags::FD_CLOEXEC).unwrap()
+    }
+
+    #[test]
+    #[ignore = "needs perf_event_open"]
+    fn task_clock_ioctls() {
+        let mut attr = PerfEventAttr::new(PerfEventType::Software(SoftwareCounterType::TaskClock));
+        attr.flags = AttrFlags::DISABLED;
+        attr.read_format =
+            ReadFormat::TOTAL_TIME_ENABLED | ReadFormat::TOTAL_TIME_RUNNING | ReadFormat::ID;
+        let fd = open(&attr);
+        fd.reset(false).unwrap();
+        fd.enable(false).unwrap();
+        let start = std::time::Instant::now();
+        while start.elapsed().as_millis() < 2 {}
+        fd.disable(false).unwrap();
+
+        let values = fd.read_values().unwrap();
+        assert_eq!(values.values.len(), 1);
+        assert_eq!(values.values[0].id, Some(fd.id().unwrap()));
+        assert_ne!(values.scale(values.values[0].value), Some(0));
+    }
+
+    #[test]
+    #[ignore = "needs perf_event_open"]
+    fn dummy_event_ring_buffer() {
+        let mut attr = PerfEventAttr::new(PerfEventType::Software(SoftwareCounterType::Dummy));
+        attr.flags = AttrFlags::DISABLED | AttrFlags::COMM | AttrFlags::COMM_EXEC;
+        let fd = open(&attr);
+        let mut mmap = fd.mmap(1).unwrap();
+        fd.enable(false).unwrap();
+        let thread_name = c"renamed";
+        // Safety: The name is nul-terminated.
+        unsafe { libc::prctl(libc::PR_SET_NAME, thread_name.as_ptr()) };
+        fd.disable(false).unwrap();
+
+        let parse_info = RecordParseInfo::new(&attr, crate::Endianness::NATIVE);
+        let mut ring_buffer = mmap.ring_buffer().unwrap();
+        let mut found_comm = false;
+        while let Some(record) = ring_buffer.next_record() {
+            if record.record_type != RecordType::COMM {
+                continue;
+            }
+            if let Ok(EventRecord::Comm(comm)) = record.to_raw_event_record(parse_info).parse() {
+                found_comm |= &comm.name.as_slice()[..] == b"renamed";
+            }
+        }
+        assert!(found_comm);
+    }
+}
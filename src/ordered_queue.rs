@@ -0,0 +1,337 @@
+use std::cmp::{Ordering, Reverse};
+use std::collections::{BinaryHeap, VecDeque};
+
+use crate::{RawData, RawEventRecord, RecordParseInfo, RecordType};
+
+/// An event record which owns its data, so that it can be kept around after
+/// the buffer it was read from has been reused.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct OwnedEventRecord {
+    /// The record type. Must be a builtin type, i.e. not a user type.
+    pub record_type: RecordType,
+    /// The `misc` value on this record.
+    pub misc: u16,
+    /// The raw bytes in the body of this record.
+    pub data: Vec<u8>,
+    /// The parse info from the record's attr.
+    pub parse_info: RecordParseInfo,
+}
+
+impl OwnedEventRecord {
+    /// Borrow this record as a [`RawEventRecord`], e.g. for parsing it.
+    pub fn as_raw(&self) -> RawEventRecord<'_> {
+        RawEventRecord::new(
+            self.record_type,
+            self.misc,
+            RawData::from(&self.data[..]),
+            self.parse_info,
+        )
+    }
+}
+
+impl From<&RawEventRecord<'_>> for OwnedEventRecord {
+    fn from(record: &RawEventRecord<'_>) -> Self {
+        Self {
+            record_type: record.record_type,
+            misc: record.misc,
+            data: record.data.as_slice().into_owned(),
+            parse_info: record.parse_info,
+        }
+    }
+}
+
+/// Whether a record which was pushed into an [`OrderedEventQueue`] could be
+/// put in order.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum QueuedRecordOrder {
+    /// The record will be returned in timestamp order.
+    InOrder,
+    /// The record arrived after records with later timestamps had already
+    /// been flushed. It will still be returned, with the next flush, but out
+    /// of order.
+    Late {
+        /// The record's timestamp.
+        timestamp: u64,
+        /// The timestamp of the latest record which had been flushed.
+        flushed_until: u64,
+    },
+    /// The record has no timestamp, so it can't be sorted. It is returned
+    /// right away.
+    NoTimestamp,
+}
+
+/// Sorts event records by timestamp.
+///
+/// perf records from one ring buffer per CPU, and `perf record` writes the
+/// contents of these buffers one after the other. So the records in a
+/// perf.data file are only sorted within each buffer, not across buffers.
+/// The usual way to merge them, which is also what `perf report` does, works
+/// in rounds: whenever `perf record` has written all buffers once, it emits a
+/// `PERF_RECORD_FINISHED_ROUND` record. All records from the next round have
+/// timestamps which are later than those of the records from the round before
+/// the last one, so at the end of a round, everything up to the maximum
+/// timestamp of the previous round can be returned in sorted order.
+///
+/// Usage: Push every event record with [`OrderedEventQueue::push`], call
+/// [`OrderedEventQueue::flush_round`] for every `PERF_RECORD_FINISHED_ROUND`
+/// record, and call [`OrderedEventQueue::flush_all`] at the end of the
+/// stream. Sorted records can be taken out with [`OrderedEventQueue::pop`]
+/// after each of these calls.
+///
+/// For streams without rounds, a watermark can be set with
+/// [`OrderedEventQueue::set_watermark`]: records which are older than the
+/// latest timestamp minus the watermark are flushed as soon as a record is
+/// pushed.
+///
+/// The queue holds on to all records until they are flushed. To limit its
+/// memory use, set [`OrderedEventQueue::set_max_buffered_bytes`]. When the
+/// limit is exceeded, the oldest records are flushed early, which can cause
+/// later records to be out of order. The limit only covers records which
+/// haven't been flushed yet: flushed records stay in the queue until they're
+/// taken out with [`OrderedEventQueue::pop`], so pop them after every push
+/// and flush to keep the total memory use bounded.
+#[derive(Debug, Clone, Default)]
+pub struct OrderedEventQueue {
+    /// The records which haven't been flushed, with the oldest at the top.
+    queue: BinaryHeap<Reverse<QueueEntry>>,
+    /// The records which have been flushed but not popped, in order.
+    flushed: VecDeque<OwnedEventRecord>,
+    /// Used to keep records with the same timestamp in push order.
+    next_sequence: u64,
+    /// The maximum timestamp of all records pushed so far.
+    max_timestamp: Option<u64>,
+    /// The maximum timestamp at the end of the previous round.
+    next_round_flush: Option<u64>,
+    /// The timestamp of the latest flushed record.
+    flushed_until: Option<u64>,
+    watermark: Option<u64>,
+    max_buffered_bytes: Option<usize>,
+    /// The total size of the data of the records in `queue`.
+    buffered_bytes: usize,
+    late_record_count: u64,
+}
+
+#[derive(Debug, Clone)]
+struct QueueEntry {
+    timestamp: u64,
+    sequence: u64,
+    record: OwnedEventRecord,
+}
+
+impl PartialEq for QueueEntry {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl Eq for QueueEntry {}
+
+impl PartialOrd for QueueEntry {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for QueueEntry {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (self.timestamp, self.sequence).cmp(&(other.timestamp, other.sequence))
+    }
+}
+
+impl OrderedEventQueue {
+    /// Create an empty queue without a watermark and without a memory limit.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Flush records whose timestamp is more than `watermark` older than the
+    /// latest timestamp, whenever a record is pushed. `None` disables this.
+    pub fn set_watermark(&mut self, watermark: Option<u64>) {
+        self.watermark = watermark;
+    }
+
+    /// Flush the oldest records early whenever the data of the queued records
+    /// exceeds `max_buffered_bytes`. `None` disables the limit.
+    ///
+    /// Records which have been flushed but not popped don't count towards
+    /// the limit, because flushing more records can't reduce their size.
+    pub fn set_max_buffered_bytes(&mut self, max_buffered_bytes: Option<usize>) {
+        self.max_buffered_bytes = max_buffered_bytes;
+        self.enforce_memory_limit();
+    }
+
+    /// Add a record. The record's data is copied into the queue.
+    pub fn push(&mut self, record: &RawEventRecord) -> QueuedRecordOrder {
+        let Some(timestamp) = record.timestamp() else {
+            self.flushed.push_back(record.into());
+            return QueuedRecordOrder::NoTimestamp;
+        };
+
+        let order = match self.flushed_until {
+            Some(flushed_until) if timestamp < flushed_until => {
+                self.late_record_count += 1;
+                QueuedRecordOrder::Late {
+                    timestamp,
+                    flushed_until,
+                }
+            }
+            _ => QueuedRecordOrder::InOrder,
+        };
+
+        let record = OwnedEventRecord::from(record);
+        self.buffered_bytes += record.data.len();
+        self.queue.push(Reverse(QueueEntry {
+            timestamp,
+            sequence: self.next_sequence,
+            record,
+        }));
+        self.next_sequence += 1;
+        self.max_timestamp = self.max_timestamp.max(Some(timestamp));
+
+        if let (Some(watermark), Some(max_timestamp)) = (self.watermark, self.max_timestamp) {
+            self.flush_until(max_timestamp.saturating_sub(watermark));
+        }
+        self.enforce_memory_limit();
+        order
+    }
+
+    /// Call this for every `PERF_RECORD_FINISHED_ROUND` record. Flushes all
+    /// records up to the maximum timestamp that had been seen at the end of
+    /// the previous round.
+    pub fn flush_round(&mut self) {
+        if let Some(limit) = self.next_round_flush {
+            self.flush_until(limit);
+        }
+        self.next_round_flush = self.max_timestamp;
+    }
+
+    /// Flush all records whose timestamp is less than or equal to `timestamp`.
+    pub fn flush_until(&mut self, timestamp: u64) {
+        while let Some(Reverse(entry)) = self.queue.peek() {
+            if entry.timestamp > timestamp {
+                break;
+            }
+            self.flush_oldest();
+        }
+    }
+
+    /// Flush all records, e.g. at the end of the stream.
+    pub fn flush_all(&mut self) {
+        while !self.queue.is_empty() {
+            self.flush_oldest();
+        }
+    }
+
+    /// Take the next flushed record, in timestamp order.
+    pub fn pop(&mut self) -> Option<OwnedEventRecord> {
+        self.flushed.pop_front()
+    }
+
+    /// The number of records which haven't been flushed yet.
+    pub fn queued_len(&self) -> usize {
+        self.queue.len()
+    }
+
+    /// The total size of the data of the records which haven't been flushed yet.
+    /// This doesn't include flushed records which haven't been popped.
+    pub fn buffered_bytes(&self) -> usize {
+        self.buffered_bytes
+    }
+
+    /// The timestamp of the latest flushed record.
+    pub fn flushed_until(&self) -> Option<u64> {
+        self.flushed_until
+    }
+
+    /// The number of records which were pushed after records with later
+    /// timestamps had been flushed. See [`QueuedRecordOrder::Late`].
+    pub fn late_record_count(&self) -> u64 {
+        self.late_record_count
+    }
+
+    fn flush_oldest(&mut self) {
+        if let Some(Reverse(entry)) = self.queue.pop() {
+            self.buffered_bytes -= entry.record.data.len();
+            self.flushed_until = self.flushed_until.max(Some(entry.timestamp));
+            self.flushed.push_back(entry.record);
+        }
+    }
+
+    fn enforce_memory_limit(&mut self) {
+        if let Some(max_buffered_bytes) = self.max_buffered_bytes {
+            while self.buffered_bytes > max_buffered_bytes {
+                self.flush_oldest();
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{OrderedEventQueue, QueuedRecordOrder};
+    use crate::{
+        AttrFlags, Endianness, PerfEventAttr, PerfEventType, RawData, RawEventRecord,
+        RecordParseInfo, RecordType, SampleFormat, SoftwareCounterType,
+    };
+
+    fn pop_timestamps(queue: &mut OrderedEventQueue) -> Vec<u64> {
+        std::iter::from_fn(|| queue.pop())
+            .map(|record| record.as_raw().timestamp().unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn rounds_and_late_records() {
+        let mut attr = PerfEventAttr::new(PerfEventType::Software(SoftwareCounterType::CpuClock));
+        attr.sample_format = SampleFormat::TIME;
+        attr.flags = AttrFlags::SAMPLE_ID_ALL;
+        let parse_info = RecordParseInfo::new(&attr, Endianness::LittleEndian);
+        let mut queue = OrderedEventQueue::new();
+        let push = |queue: &mut OrderedEventQueue, timestamp: u64| {
+            let data = timestamp.to_le_bytes();
+            let record =
+                RawEventRecord::new(RecordType::SAMPLE, 0, RawData::from(&data[..]), parse_info);
+            queue.push(&record)
+        };
+
+        // Round 1: two CPU buffers.
+        for timestamp in [10, 30, 20, 40] {
+            assert_eq!(push(&mut queue, timestamp), QueuedRecordOrder::InOrder);
+        }
+        queue.flush_round();
+        assert_eq!(pop_timestamps(&mut queue), []);
+
+        // Round 2: flushes everything up to the end of round 1.
+        for timestamp in [35, 50, 45] {
+            push(&mut queue, timestamp);
+        }
+        queue.flush_round();
+        assert_eq!(pop_timestamps(&mut queue), [10, 20, 30, 35, 40]);
+        assert_eq!(queue.flushed_until(), Some(40));
+
+        assert_eq!(
+            push(&mut queue, 38),
+            QueuedRecordOrder::Late {
+                timestamp: 38,
+                flushed_until: 40
+            }
+        );
+        queue.flush_all();
+        assert_eq!(pop_timestamps(&mut queue), [38, 45, 50]);
+        assert_eq!(queue.late_record_count(), 1);
+
+        // Watermark and memory limit.
+        queue.set_watermark(Some(100));
+        for timestamp in [200, 160, 300] {
+            push(&mut queue, timestamp);
+        }
+        assert_eq!(pop_timestamps(&mut queue), [160, 200]);
+        queue.set_watermark(None);
+        queue.set_max_buffered_bytes(Some(16));
+        for timestamp in [500, 400] {
+            push(&mut queue, timestamp);
+        }
+        assert_eq!(pop_timestamps(&mut queue), [300]);
+        assert_eq!(queue.buffered_bytes(), 16);
+    }
+}
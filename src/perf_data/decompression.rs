@@ -0,0 +1,184 @@
+use byteorder::ByteOrder;
+use std::ops::Range;
+use zstd::stream::raw::{Decoder, InBuffer, Operation, OutBuffer};
+
+use super::PerfDataError;
+use crate::PerfEventHeader;
+
+/// Decompresses the payloads of `PERF_RECORD_COMPRESSED` records and splits
+/// the decompressed bytes into records.
+///
+/// `perf record -z` compresses the contents of its mmap buffers as one
+/// continuous zstd stream, and cuts the compressed stream into COMPRESSED
+/// records. So a single decoder is used for all payloads, and a record can
+/// be split across two payloads. Incomplete records stay in the buffer until
+/// the next payload arrives.
+///
+/// The output of a single payload is limited, so that a small, corrupt or
+/// malicious record can't make the decompressed buffer grow without bounds.
+/// perf compresses at most one mmap buffer at a time, so the mmap size from
+/// the `COMPRESSED` feature section is used as the limit when it's known.
+pub(crate) struct Decompressor {
+    /// Created when the first payload arrives.
+    decoder: Option<Decoder<'static>>,
+    /// The decompressed bytes.
+    buffer: Vec<u8>,
+    /// The offset of the first record in `buffer` which hasn't been returned yet.
+    position: usize,
+    /// The maximum number of bytes which a single payload may decompress to.
+    max_output_per_record: usize,
+}
+
+impl Decompressor {
+    /// The limit for the output of a single payload if the mmap size isn't known.
+    const DEFAULT_MAX_OUTPUT_PER_RECORD: usize = 64 * 1024 * 1024;
+
+    pub fn new() -> Self {
+        Self {
+            decoder: None,
+            buffer: Vec::new(),
+            position: 0,
+            max_output_per_record: Self::DEFAULT_MAX_OUTPUT_PER_RECORD,
+        }
+    }
+
+    /// Limit the output of a single payload to the size of the mmap buffers
+    /// which were compressed, from [`CompressionInfo::mmap_len`](super::CompressionInfo::mmap_len).
+    pub fn set_mmap_len(&mut self, mmap_len: u32) {
+        self.max_output_per_record = mmap_len as usize;
+    }
+
+    /// Decompress the payload of a COMPRESSED record and append the result
+    /// to the buffer.
+    ///
+    /// This invalidates the ranges returned by [`Decompressor::next_record`].
+    pub fn decompress(&mut self, compressed: &[u8]) -> Result<(), PerfDataError> {
+        self.buffer.drain(..self.position);
+        self.position = 0;
+
+        let decoder = match &mut self.decoder {
+            Some(decoder) => decoder,
+            None => self.decoder.insert(Decoder::new()?),
+        };
+        let start = self.buffer.len();
+        let mut input = InBuffer::around(compressed);
+        loop {
+            // Leave room for one byte more than the limit, so that exceeding
+            // the limit can be detected.
+            let allowed = (start + self.max_output_per_record + 1) - self.buffer.len();
+            self.buffer
+                .reserve((compressed.len().max(4096) * 4).min(allowed));
+            let len = self.buffer.len();
+            let mut output = OutBuffer::around_pos(&mut self.buffer, len);
+            decoder.run(&mut input, &mut output)?;
+            let output_is_full = output.pos() == output.capacity();
+            if self.buffer.len() - start > self.max_output_per_record {
+                // The decoder state is unusable after dropping its output.
+                self.buffer.truncate(start);
+                self.decoder = None;
+                return Err(PerfDataError::DecompressedRecordTooLarge(
+                    self.max_output_per_record,
+                ));
+            }
+            if input.pos() == compressed.len() && !output_is_full {
+                // All input is consumed, and the decoder had enough room to
+                // flush everything it could produce from it.
+                return Ok(());
+            }
+        }
+    }
+
+    /// Return the header of the next complete record and the range of its
+    /// body in the buffer, or `None` if the buffer doesn't contain a complete
+    /// record.
+    pub fn next_record<T: ByteOrder>(
+        &mut self,
+    ) -> Result<Option<(PerfEventHeader, Range<usize>)>, PerfDataError> {
+        let remaining = &self.buffer[self.position..];
+        if remaining.len() < PerfEventHeader::STRUCT_SIZE {
+            return Ok(None);
+        }
+        let header = PerfEventHeader::parse::<_, T>(remaining)?;
+        let size = usize::from(header.size);
+        if size < PerfEventHeader::STRUCT_SIZE {
+            // The rest of the buffer can't be split into records.
+            self.position = self.buffer.len();
+            return Err(PerfDataError::InvalidRecordSize(header.size));
+        }
+        if remaining.len() < size {
+            return Ok(None);
+        }
+        let body = self.position + PerfEventHeader::STRUCT_SIZE..self.position + size;
+        self.position += size;
+        Ok(Some((header, body)))
+    }
+
+    /// Called at the end of the data. Returns an error if there are
+    /// decompressed bytes left which don't form a complete record, and
+    /// discards them.
+    pub fn finish(&mut self) -> Result<(), PerfDataError> {
+        let remaining = self.buffer.len() - self.position;
+        self.buffer.clear();
+        self.position = 0;
+        if remaining != 0 {
+            return Err(PerfDataError::IncompleteCompressedRecord(remaining));
+        }
+        Ok(())
+    }
+
+    /// The bytes of a record body returned by [`Decompressor::next_record`].
+    pub fn record_data(&self, range: Range<usize>) -> &[u8] {
+        &self.buffer[range]
+    }
+}
+
+impl std::fmt::Debug for Decompressor {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("Decompressor")
+            .field("buffered_bytes", &(self.buffer.len() - self.position))
+            .finish_non_exhaustive()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::Decompressor;
+    use crate::PerfDataError;
+    use byteorder::LittleEndian;
+
+    #[test]
+    fn limits_output_per_record() {
+        let compressed = zstd::encode_all(&[0u8; 10000][..], 0).unwrap();
+
+        let mut decompressor = Decompressor::new();
+        decompressor.set_mmap_len(4096);
+        assert!(matches!(
+            decompressor.decompress(&compressed),
+            Err(PerfDataError::DecompressedRecordTooLarge(4096))
+        ));
+
+        let mut decompressor = Decompressor::new();
+        decompressor.set_mmap_len(10000);
+        decompressor.decompress(&compressed).unwrap();
+    }
+
+    #[test]
+    fn reports_incomplete_record_at_end() {
+        // A header for a 16-byte record, followed by only 4 bytes of its body.
+        let mut records = vec![9, 0, 0, 0, 0, 0, 16, 0];
+        records.extend_from_slice(&[1, 2, 3, 4]);
+        let compressed = zstd::encode_all(&records[..], 0).unwrap();
+
+        let mut decompressor = Decompressor::new();
+        decompressor.decompress(&compressed).unwrap();
+        assert!(decompressor
+            .next_record::<LittleEndian>()
+            .unwrap()
+            .is_none());
+        assert!(matches!(
+            decompressor.finish(),
+            Err(PerfDataError::IncompleteCompressedRecord(12))
+        ));
+        decompressor.finish().unwrap();
+    }
+}
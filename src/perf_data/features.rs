@@ -0,0 +1,827 @@
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use std::collections::HashMap;
+use std::io;
+
+use super::PerfFeature;
+use crate::constants::PERF_RECORD_MISC_BUILD_ID_SIZE;
+use crate::{CpuMode, Endianness, PerfEventAttr, PerfEventHeader, RawData, TracingData};
+
+/// Read a string in the format that perf uses in feature sections:
+///
+/// ```pseudo-c
+/// struct perf_header_string {
+///     u32 len; // includes the nul terminator and padding
+///     char string[len]; // nul-terminated, zero-padded to 64 bytes
+/// };
+/// ```
+fn read_perf_string<'a, T: ByteOrder>(cur: &mut RawData<'a>) -> Result<RawData<'a>, io::Error> {
+    let len = cur.read_u32::<T>()?;
+    let mut s = cur.split_off_prefix(len as usize)?;
+    Ok(s.read_string().unwrap_or(s))
+}
+
+/// Read a `perf_header_string_list`: a u32 count followed by that many strings.
+fn read_perf_string_list<'a, T: ByteOrder>(
+    cur: &mut RawData<'a>,
+) -> Result<Vec<RawData<'a>>, io::Error> {
+    let nr = cur.read_u32::<T>()?;
+    let mut list = Vec::new();
+    for _ in 0..nr {
+        list.push(read_perf_string::<T>(cur)?);
+    }
+    Ok(list)
+}
+
+/// The `HOSTNAME`, `OSRELEASE`, `VERSION`, `ARCH`, `CPUDESC` and `CPUID`
+/// sections each contain a single string.
+pub fn parse_string_feature<T: ByteOrder>(data: RawData) -> Result<RawData, io::Error> {
+    let mut cur = data;
+    read_perf_string::<T>(&mut cur)
+}
+
+/// The `CMDLINE` section contains the command line arguments of the `perf`
+/// invocation which created the file.
+pub fn parse_string_list_feature<T: ByteOrder>(data: RawData) -> Result<Vec<RawData>, io::Error> {
+    let mut cur = data;
+    read_perf_string_list::<T>(&mut cur)
+}
+
+/// The `NRCPUS` section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct NrCpus {
+    /// The number of CPUs in the system.
+    pub nr_cpus_available: u32,
+    /// The number of CPUs which were online during the recording.
+    pub nr_cpus_online: u32,
+}
+
+impl NrCpus {
+    pub fn parse<T: ByteOrder>(data: RawData) -> Result<Self, io::Error> {
+        let mut cur = data;
+        let nr_cpus_available = cur.read_u32::<T>()?;
+        let nr_cpus_online = cur.read_u32::<T>()?;
+        Ok(Self {
+            nr_cpus_available,
+            nr_cpus_online,
+        })
+    }
+}
+
+/// An entry in the `BUILD_ID` section, or the body of a `PERF_RECORD_HEADER_BUILD_ID`
+/// record in pipe mode.
+///
+/// ```pseudo-c
+/// struct perf_record_header_build_id {
+///     struct perf_event_header header;
+///     pid_t pid;
+///     union {
+///         u8 build_id[24];
+///         struct {
+///             u8 data[20];
+///             u8 size;
+///             u8 reserved1__;
+///             u16 reserved2__;
+///         };
+///     };
+///     char filename[];
+/// };
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct BuildIdEvent<'a> {
+    /// Whether this is a kernel or a user space binary, from the header's `misc` field.
+    pub cpu_mode: CpuMode,
+    /// The pid, or -1 for the kernel.
+    pub pid: i32,
+    /// The build ID bytes.
+    pub build_id: Vec<u8>,
+    /// The path of the binary.
+    pub file_path: RawData<'a>,
+}
+
+impl<'a> BuildIdEvent<'a> {
+    /// Parse the part after the `perf_event_header`.
+    pub fn parse<T: ByteOrder>(data: RawData<'a>, misc: u16) -> Result<Self, io::Error> {
+        let mut cur = data;
+        let pid = cur.read_i32::<T>()?;
+        let mut build_id_bytes = [0; 24];
+        cur.read_exact(&mut build_id_bytes)?;
+        let build_id_len = if misc & PERF_RECORD_MISC_BUILD_ID_SIZE != 0 {
+            (build_id_bytes[20] as usize).min(20)
+        } else {
+            // Older perf versions always wrote 20 bytes, zero-padded.
+            20
+        };
+        let file_path = cur.read_string().unwrap_or(cur);
+        Ok(Self {
+            cpu_mode: CpuMode::from_misc(misc),
+            pid,
+            build_id: build_id_bytes[..build_id_len].to_owned(),
+            file_path,
+        })
+    }
+
+    /// Parse all the entries in a `BUILD_ID` section.
+    pub fn parse_section<T: ByteOrder>(data: RawData<'a>) -> Result<Vec<Self>, io::Error> {
+        let mut cur = data;
+        let mut entries = Vec::new();
+        while !cur.is_empty() {
+            let header = PerfEventHeader {
+                type_: cur.read_u32::<T>()?,
+                misc: cur.read_u16::<T>()?,
+                size: cur.read_u16::<T>()?,
+            };
+            let body_size = (header.size as usize)
+                .checked_sub(PerfEventHeader::STRUCT_SIZE)
+                .ok_or(io::ErrorKind::InvalidData)?;
+            let body = cur.split_off_prefix(body_size)?;
+            entries.push(Self::parse::<T>(body, header.misc)?);
+        }
+        Ok(entries)
+    }
+}
+
+/// The IDs of the core, socket and die of a CPU, from the `CPU_TOPOLOGY` section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CpuTopologyIds {
+    pub core_id: u32,
+    pub socket_id: u32,
+    /// Only present in files from perf 5.5 or newer.
+    pub die_id: Option<u32>,
+}
+
+/// The `CPU_TOPOLOGY` section.
+///
+/// The sibling lists are CPU list strings, like `0-3,8-11`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CpuTopology<'a> {
+    /// One entry per set of CPUs which share a socket.
+    pub core_siblings: Vec<RawData<'a>>,
+    /// One entry per set of CPUs which share a core (SMT siblings).
+    pub thread_siblings: Vec<RawData<'a>>,
+    /// One entry per set of CPUs which share a die. Empty in older files.
+    pub die_siblings: Vec<RawData<'a>>,
+    /// The IDs for each CPU, indexed by CPU number. Empty in older files.
+    pub cpus: Vec<CpuTopologyIds>,
+}
+
+impl<'a> CpuTopology<'a> {
+    /// Parse the section. The per-CPU IDs can only be parsed if
+    /// `nr_cpus_available` from the [`NrCpus`] section is known.
+    pub fn parse<T: ByteOrder>(
+        data: RawData<'a>,
+        nr_cpus_available: Option<u32>,
+    ) -> Result<Self, io::Error> {
+        let mut cur = data;
+        let core_siblings = read_perf_string_list::<T>(&mut cur)?;
+        let thread_siblings = read_perf_string_list::<T>(&mut cur)?;
+        let mut topology = Self {
+            core_siblings,
+            thread_siblings,
+            die_siblings: Vec::new(),
+            cpus: Vec::new(),
+        };
+        let nr_cpus = match nr_cpus_available {
+            Some(nr_cpus) if !cur.is_empty() => nr_cpus,
+            _ => return Ok(topology),
+        };
+        for _ in 0..nr_cpus {
+            let core_id = cur.read_u32::<T>()?;
+            let socket_id = cur.read_u32::<T>()?;
+            topology.cpus.push(CpuTopologyIds {
+                core_id,
+                socket_id,
+                die_id: None,
+            });
+        }
+        if cur.is_empty() {
+            return Ok(topology);
+        }
+        topology.die_siblings = read_perf_string_list::<T>(&mut cur)?;
+        for cpu in &mut topology.cpus {
+            cpu.die_id = Some(cur.read_u32::<T>()?);
+        }
+        Ok(topology)
+    }
+}
+
+/// An entry in the `NUMA_TOPOLOGY` section.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct NumaNode<'a> {
+    pub node_id: u32,
+    /// The total memory of this node, in kB.
+    pub mem_total: u64,
+    /// The free memory of this node, in kB.
+    pub mem_free: u64,
+    /// The CPUs of this node, as a CPU list string like `0-3,8-11`.
+    pub cpus: RawData<'a>,
+}
+
+impl<'a> NumaNode<'a> {
+    /// Parse all the entries in a `NUMA_TOPOLOGY` section.
+    pub fn parse_section<T: ByteOrder>(data: RawData<'a>) -> Result<Vec<Self>, io::Error> {
+        let mut cur = data;
+        let nr = cur.read_u32::<T>()?;
+        let mut nodes = Vec::new();
+        for _ in 0..nr {
+            let node_id = cur.read_u32::<T>()?;
+            let mem_total = cur.read_u64::<T>()?;
+            let mem_free = cur.read_u64::<T>()?;
+            let cpus = read_perf_string::<T>(&mut cur)?;
+            nodes.push(Self {
+                node_id,
+                mem_total,
+                mem_free,
+                cpus,
+            });
+        }
+        Ok(nodes)
+    }
+}
+
+/// An entry in the `EVENT_DESC` section: The attr, name and IDs of an event.
+#[derive(Debug, Clone)]
+pub struct EventDesc<'a> {
+    pub attr: PerfEventAttr,
+    /// The event name, e.g. `cycles:u` or `sched:sched_switch`.
+    pub name: RawData<'a>,
+    pub ids: Vec<u64>,
+}
+
+impl<'a> EventDesc<'a> {
+    /// Parse all the entries in an `EVENT_DESC` section.
+    pub fn parse_section<T: ByteOrder>(data: RawData<'a>) -> Result<Vec<Self>, io::Error> {
+        let mut cur = data;
+        let nr_events = cur.read_u32::<T>()?;
+        let attr_size = cur.read_u32::<T>()?;
+        let mut events = Vec::new();
+        for _ in 0..nr_events {
+            let attr_data = cur.split_off_prefix(attr_size as usize)?;
+            let (attr, _size) = PerfEventAttr::parse::<_, T>(&attr_data.as_slice()[..])?;
+            let nr_ids = cur.read_u32::<T>()?;
+            let name = read_perf_string::<T>(&mut cur)?;
+            let mut ids = Vec::new();
+            for _ in 0..nr_ids {
+                ids.push(cur.read_u64::<T>()?);
+            }
+            events.push(Self { attr, name, ids });
+        }
+        Ok(events)
+    }
+}
+
+/// The `CLOCK_DATA` section: A reference point for converting timestamps
+/// of the recording clock into wall-clock time.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ClockData {
+    /// The clock ID of the recording clock.
+    pub clockid: u32,
+    /// The wall-clock time (`CLOCK_REALTIME`) at the reference point, in nanoseconds.
+    pub wall_clock_ns: u64,
+    /// The time of the recording clock at the reference point, in nanoseconds.
+    pub clockid_time_ns: u64,
+}
+
+impl ClockData {
+    pub fn parse<T: ByteOrder>(data: RawData) -> Result<Self, io::Error> {
+        let mut cur = data;
+        let version = cur.read_u32::<T>()?;
+        if version != 1 {
+            return Err(io::ErrorKind::InvalidData.into());
+        }
+        let clockid = cur.read_u32::<T>()?;
+        let wall_clock_ns = cur.read_u64::<T>()?;
+        let clockid_time_ns = cur.read_u64::<T>()?;
+        Ok(Self {
+            clockid,
+            wall_clock_ns,
+            clockid_time_ns,
+        })
+    }
+}
+
+/// The `SAMPLE_TIME` section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SampleTime {
+    pub first_sample_time: u64,
+    pub last_sample_time: u64,
+}
+
+impl SampleTime {
+    pub fn parse<T: ByteOrder>(data: RawData) -> Result<Self, io::Error> {
+        let mut cur = data;
+        let first_sample_time = cur.read_u64::<T>()?;
+        let last_sample_time = cur.read_u64::<T>()?;
+        Ok(Self {
+            first_sample_time,
+            last_sample_time,
+        })
+    }
+}
+
+/// An entry in the `PMU_MAPPINGS` section: The name of a PMU and the value
+/// which goes into `perf_event_attr.type` for it.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PmuMapping<'a> {
+    pub pmu_type: u32,
+    pub name: RawData<'a>,
+}
+
+impl<'a> PmuMapping<'a> {
+    /// Parse all the entries in a `PMU_MAPPINGS` section.
+    pub fn parse_section<T: ByteOrder>(data: RawData<'a>) -> Result<Vec<Self>, io::Error> {
+        let mut cur = data;
+        let nr = cur.read_u32::<T>()?;
+        let mut mappings = Vec::new();
+        for _ in 0..nr {
+            let pmu_type = cur.read_u32::<T>()?;
+            let name = read_perf_string::<T>(&mut cur)?;
+            mappings.push(Self { pmu_type, name });
+        }
+        Ok(mappings)
+    }
+}
+
+/// An entry in the `GROUP_DESC` section.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct GroupDesc<'a> {
+    /// The group name, e.g. `{cycles,instructions}`.
+    pub name: RawData<'a>,
+    /// The index of the group leader in the attrs.
+    pub leader_index: u32,
+    /// The number of events in the group, including the leader.
+    pub member_count: u32,
+}
+
+impl<'a> GroupDesc<'a> {
+    /// Parse all the entries in a `GROUP_DESC` section.
+    pub fn parse_section<T: ByteOrder>(data: RawData<'a>) -> Result<Vec<Self>, io::Error> {
+        let mut cur = data;
+        let nr = cur.read_u32::<T>()?;
+        let mut groups = Vec::new();
+        for _ in 0..nr {
+            let name = read_perf_string::<T>(&mut cur)?;
+            let leader_index = cur.read_u32::<T>()?;
+            let member_count = cur.read_u32::<T>()?;
+            groups.push(Self {
+                name,
+                leader_index,
+                member_count,
+            });
+        }
+        Ok(groups)
+    }
+}
+
+/// The `COMPRESSED` section: The compression settings of `perf record -z`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct CompressionInfo {
+    pub version: u32,
+    /// The compression algorithm. 1 is zstd.
+    pub type_: u32,
+    pub level: u32,
+    /// The average compression ratio.
+    pub ratio: u32,
+    /// The size of the mmap buffers whose contents were compressed.
+    pub mmap_len: u32,
+}
+
+impl CompressionInfo {
+    /// `type_` for zstd compression.
+    pub const ZSTD: u32 = 1;
+
+    pub fn parse<T: ByteOrder>(data: RawData) -> Result<Self, io::Error> {
+        let mut cur = data;
+        Ok(Self {
+            version: cur.read_u32::<T>()?,
+            type_: cur.read_u32::<T>()?,
+            level: cur.read_u32::<T>()?,
+            ratio: cur.read_u32::<T>()?,
+            mmap_len: cur.read_u32::<T>()?,
+        })
+    }
+}
+
+/// The feature sections of a perf.data file, with typed accessors.
+///
+/// Each accessor returns `Ok(None)` if the file doesn't have the section.
+#[derive(Debug, Clone)]
+pub struct PerfFeatureSections {
+    endian: Endianness,
+    sections: HashMap<PerfFeature, Vec<u8>>,
+}
+
+impl PerfFeatureSections {
+    /// Create an empty set of sections.
+    pub fn new(endian: Endianness) -> Self {
+        Self {
+            endian,
+            sections: HashMap::new(),
+        }
+    }
+
+    /// Add or replace the section data for `feature`.
+    pub fn insert(&mut self, feature: PerfFeature, data: Vec<u8>) {
+        self.sections.insert(feature, data);
+    }
+
+    /// The endianness of the section data.
+    pub fn endian(&self) -> Endianness {
+        self.endian
+    }
+
+    /// The raw bytes of the section for `feature`.
+    pub fn get(&self, feature: PerfFeature) -> Option<&[u8]> {
+        self.sections.get(&feature).map(Vec::as_slice)
+    }
+
+    /// The features which have a section.
+    pub fn features(&self) -> impl Iterator<Item = PerfFeature> + '_ {
+        self.sections.keys().copied()
+    }
+
+    fn parse_section<'a, R>(
+        &'a self,
+        feature: PerfFeature,
+        parse_le: impl FnOnce(RawData<'a>) -> Result<R, io::Error>,
+        parse_be: impl FnOnce(RawData<'a>) -> Result<R, io::Error>,
+    ) -> Result<Option<R>, io::Error> {
+        let Some(data) = self.get(feature) else {
+            return Ok(None);
+        };
+        let data = RawData::from(data);
+        let parsed = match self.endian {
+            Endianness::LittleEndian => parse_le(data)?,
+            Endianness::BigEndian => parse_be(data)?,
+        };
+        Ok(Some(parsed))
+    }
+
+    fn string(&self, feature: PerfFeature) -> Result<Option<RawData<'_>>, io::Error> {
+        self.parse_section(
+            feature,
+            parse_string_feature::<LittleEndian>,
+            parse_string_feature::<BigEndian>,
+        )
+    }
+
+    /// The hostname of the recording machine.
+    pub fn hostname(&self) -> Result<Option<RawData<'_>>, io::Error> {
+        self.string(PerfFeature::HOSTNAME)
+    }
+
+    /// The kernel release of the recording machine, as in `uname -r`.
+    pub fn os_release(&self) -> Result<Option<RawData<'_>>, io::Error> {
+        self.string(PerfFeature::OSRELEASE)
+    }
+
+    /// The version of perf which created the file.
+    pub fn perf_version(&self) -> Result<Option<RawData<'_>>, io::Error> {
+        self.string(PerfFeature::VERSION)
+    }
+
+    /// The architecture of the recording machine, as in `uname -m`.
+    pub fn arch(&self) -> Result<Option<RawData<'_>>, io::Error> {
+        self.string(PerfFeature::ARCH)
+    }
+
+    /// The CPU model name.
+    pub fn cpu_desc(&self) -> Result<Option<RawData<'_>>, io::Error> {
+        self.string(PerfFeature::CPUDESC)
+    }
+
+    /// The CPU identifier, e.g. `GenuineIntel,6,85,4`.
+    pub fn cpu_id(&self) -> Result<Option<RawData<'_>>, io::Error> {
+        self.string(PerfFeature::CPUID)
+    }
+
+    /// The command line of the `perf` invocation.
+    pub fn cmdline(&self) -> Result<Option<Vec<RawData<'_>>>, io::Error> {
+        self.parse_section(
+            PerfFeature::CMDLINE,
+            parse_string_list_feature::<LittleEndian>,
+            parse_string_list_feature::<BigEndian>,
+        )
+    }
+
+    /// The number of available and online CPUs.
+    pub fn nr_cpus(&self) -> Result<Option<NrCpus>, io::Error> {
+        self.parse_section(
+            PerfFeature::NRCPUS,
+            NrCpus::parse::<LittleEndian>,
+            NrCpus::parse::<BigEndian>,
+        )
+    }
+
+    /// The total memory of the recording machine, in kB.
+    pub fn total_mem(&self) -> Result<Option<u64>, io::Error> {
+        self.parse_section(
+            PerfFeature::TOTAL_MEM,
+            |mut cur| cur.read_u64::<LittleEndian>(),
+            |mut cur| cur.read_u64::<BigEndian>(),
+        )
+    }
+
+    /// The build IDs of the binaries which were hit by samples.
+    pub fn build_ids(&self) -> Result<Option<Vec<BuildIdEvent<'_>>>, io::Error> {
+        self.parse_section(
+            PerfFeature::BUILD_ID,
+            BuildIdEvent::parse_section::<LittleEndian>,
+            BuildIdEvent::parse_section::<BigEndian>,
+        )
+    }
+
+    /// The CPU topology. The per-CPU IDs are only available if the file
+    /// also has an `NRCPUS` section.
+    pub fn cpu_topology(&self) -> Result<Option<CpuTopology<'_>>, io::Error> {
+        let nr_cpus_available = self.nr_cpus()?.map(|nr_cpus| nr_cpus.nr_cpus_available);
+        self.parse_section(
+            PerfFeature::CPU_TOPOLOGY,
+            |data| CpuTopology::parse::<LittleEndian>(data, nr_cpus_available),
+            |data| CpuTopology::parse::<BigEndian>(data, nr_cpus_available),
+        )
+    }
+
+    /// The NUMA nodes.
+    pub fn numa_topology(&self) -> Result<Option<Vec<NumaNode<'_>>>, io::Error> {
+        self.parse_section(
+            PerfFeature::NUMA_TOPOLOGY,
+            NumaNode::parse_section::<LittleEndian>,
+            NumaNode::parse_section::<BigEndian>,
+        )
+    }
+
+    /// The names of the events, with their attrs and IDs.
+    pub fn event_desc(&self) -> Result<Option<Vec<EventDesc<'_>>>, io::Error> {
+        self.parse_section(
+            PerfFeature::EVENT_DESC,
+            EventDesc::parse_section::<LittleEndian>,
+            EventDesc::parse_section::<BigEndian>,
+        )
+    }
+
+    /// The resolution of the recording clock, in nanoseconds.
+    pub fn clockid_res_ns(&self) -> Result<Option<u64>, io::Error> {
+        self.parse_section(
+            PerfFeature::CLOCKID,
+            |mut cur| cur.read_u64::<LittleEndian>(),
+            |mut cur| cur.read_u64::<BigEndian>(),
+        )
+    }
+
+    /// The reference point for converting timestamps to wall-clock time.
+    pub fn clock_data(&self) -> Result<Option<ClockData>, io::Error> {
+        self.parse_section(
+            PerfFeature::CLOCK_DATA,
+            ClockData::parse::<LittleEndian>,
+            ClockData::parse::<BigEndian>,
+        )
+    }
+
+    /// The timestamps of the first and the last sample.
+    pub fn sample_time(&self) -> Result<Option<SampleTime>, io::Error> {
+        self.parse_section(
+            PerfFeature::SAMPLE_TIME,
+            SampleTime::parse::<LittleEndian>,
+            SampleTime::parse::<BigEndian>,
+        )
+    }
+
+    /// The PMUs of the recording machine.
+    pub fn pmu_mappings(&self) -> Result<Option<Vec<PmuMapping<'_>>>, io::Error> {
+        self.parse_section(
+            PerfFeature::PMU_MAPPINGS,
+            PmuMapping::parse_section::<LittleEndian>,
+            PmuMapping::parse_section::<BigEndian>,
+        )
+    }
+
+    /// The event groups.
+    pub fn group_desc(&self) -> Result<Option<Vec<GroupDesc<'_>>>, io::Error> {
+        self.parse_section(
+            PerfFeature::GROUP_DESC,
+            GroupDesc::parse_section::<LittleEndian>,
+            GroupDesc::parse_section::<BigEndian>,
+        )
+    }
+
+    /// The compression settings, for files with `PERF_RECORD_COMPRESSED` records.
+    pub fn compressed(&self) -> Result<Option<CompressionInfo>, io::Error> {
+        self.parse_section(
+            PerfFeature::COMPRESSED,
+            CompressionInfo::parse::<LittleEndian>,
+            CompressionInfo::parse::<BigEndian>,
+        )
+    }
+
+    /// The tracepoint formats and related data, for files which recorded
+    /// tracepoint events.
+    pub fn tracing_data(&self) -> Result<Option<TracingData<'_>>, io::Error> {
+        self.get(PerfFeature::TRACING_DATA)
+            .map(TracingData::parse)
+            .transpose()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        ClockData, CpuTopology, CpuTopologyIds, EventDesc, GroupDesc, NumaNode,
+        PerfFeatureSections, PmuMapping,
+    };
+    use crate::{
+        CpuMode, Endianness, PerfEventAttr, PerfEventType, PerfFeature, RawData,
+        SoftwareCounterType,
+    };
+    use byteorder::LittleEndian;
+
+    fn perf_string(s: &str) -> Vec<u8> {
+        let len = (s.len() + 1).next_multiple_of(64);
+        let mut bytes = (len as u32).to_le_bytes().to_vec();
+        bytes.extend_from_slice(s.as_bytes());
+        bytes.resize(4 + len, 0);
+        bytes
+    }
+
+    #[test]
+    fn strings_and_build_ids() {
+        let mut sections = PerfFeatureSections::new(Endianness::LittleEndian);
+        sections.insert(PerfFeature::HOSTNAME, perf_string("myhost"));
+        let mut cmdline = 2u32.to_le_bytes().to_vec();
+        cmdline.extend(perf_string("perf"));
+        cmdline.extend(perf_string("record"));
+        sections.insert(PerfFeature::CMDLINE, cmdline);
+
+        let mut build_id = Vec::new();
+        build_id.extend_from_slice(&67u32.to_le_bytes());
+        build_id.extend_from_slice(&(0x8000u16 | 2).to_le_bytes()); // BUILD_ID_SIZE | USER
+        build_id.extend_from_slice(&(8u16 + 4 + 24 + 16).to_le_bytes());
+        build_id.extend_from_slice(&1234i32.to_le_bytes());
+        build_id.extend_from_slice(&[0xab; 20]);
+        build_id.extend_from_slice(&[4, 0, 0, 0]);
+        build_id.extend_from_slice(b"/usr/bin/ls\0\0\0\0\0");
+        sections.insert(PerfFeature::BUILD_ID, build_id);
+
+        assert_eq!(
+            sections.hostname().unwrap(),
+            Some(RawData::Single(b"myhost"))
+        );
+        assert_eq!(
+            sections.cmdline().unwrap(),
+            Some(vec![RawData::Single(b"perf"), RawData::Single(b"record")])
+        );
+        let build_ids = sections.build_ids().unwrap().unwrap();
+        assert_eq!(build_ids.len(), 1);
+        assert_eq!(build_ids[0].cpu_mode, CpuMode::User);
+        assert_eq!(build_ids[0].pid, 1234);
+        assert_eq!(build_ids[0].build_id, vec![0xab; 4]);
+        assert_eq!(build_ids[0].file_path, RawData::Single(b"/usr/bin/ls"));
+        assert_eq!(sections.arch().unwrap(), None);
+    }
+
+    #[test]
+    fn cpu_topology() {
+        let mut data = 1u32.to_le_bytes().to_vec();
+        data.extend(perf_string("0-1"));
+        data.extend_from_slice(&2u32.to_le_bytes());
+        data.extend(perf_string("0"));
+        data.extend(perf_string("1"));
+        for (core_id, socket_id) in [(0u32, 0u32), (1, 0)] {
+            data.extend_from_slice(&core_id.to_le_bytes());
+            data.extend_from_slice(&socket_id.to_le_bytes());
+        }
+
+        let topology = CpuTopology::parse::<LittleEndian>(RawData::from(&data[..]), None).unwrap();
+        assert_eq!(topology.thread_siblings.len(), 2);
+        assert!(topology.cpus.is_empty());
+
+        let topology =
+            CpuTopology::parse::<LittleEndian>(RawData::from(&data[..]), Some(2)).unwrap();
+        assert_eq!(topology.core_siblings, vec![RawData::Single(b"0-1")]);
+        assert_eq!(
+            topology.cpus[1],
+            CpuTopologyIds {
+                core_id: 1,
+                socket_id: 0,
+                die_id: None
+            }
+        );
+    }
+
+    #[test]
+    fn numa_topology() {
+        let mut data = 2u32.to_le_bytes().to_vec();
+        for (node_id, mem_total, mem_free, cpus) in
+            [(0u32, 1000u64, 600u64, "0-3"), (1, 2000, 5, "4-7")]
+        {
+            data.extend_from_slice(&node_id.to_le_bytes());
+            data.extend_from_slice(&mem_total.to_le_bytes());
+            data.extend_from_slice(&mem_free.to_le_bytes());
+            data.extend(perf_string(cpus));
+        }
+        let nodes = NumaNode::parse_section::<LittleEndian>(RawData::from(&data[..])).unwrap();
+        assert_eq!(
+            nodes[1],
+            NumaNode {
+                node_id: 1,
+                mem_total: 2000,
+                mem_free: 5,
+                cpus: RawData::Single(b"4-7"),
+            }
+        );
+        assert_eq!(nodes[0].cpus, RawData::Single(b"0-3"));
+
+        let truncated = RawData::from(&data[..data.len() - 1]);
+        assert!(NumaNode::parse_section::<LittleEndian>(truncated).is_err());
+    }
+
+    #[test]
+    fn event_desc() {
+        let attr = PerfEventAttr::new(PerfEventType::Software(SoftwareCounterType::TaskClock));
+        let mut attr_bytes = Vec::new();
+        attr.write::<_, LittleEndian>(&mut attr_bytes).unwrap();
+
+        let mut data = 1u32.to_le_bytes().to_vec();
+        data.extend_from_slice(&(attr_bytes.len() as u32).to_le_bytes());
+        data.extend_from_slice(&attr_bytes);
+        data.extend_from_slice(&2u32.to_le_bytes());
+        data.extend(perf_string("task-clock:u"));
+        data.extend_from_slice(&7u64.to_le_bytes());
+        data.extend_from_slice(&9u64.to_le_bytes());
+
+        let events = EventDesc::parse_section::<LittleEndian>(RawData::from(&data[..])).unwrap();
+        assert_eq!(events.len(), 1);
+        assert_eq!(events[0].attr.type_, attr.type_);
+        assert_eq!(events[0].name, RawData::Single(b"task-clock:u"));
+        assert_eq!(events[0].ids, vec![7, 9]);
+    }
+
+    #[test]
+    fn clock_data() {
+        let mut data = 1u32.to_le_bytes().to_vec();
+        data.extend_from_slice(&1u32.to_le_bytes()); // CLOCK_MONOTONIC
+        data.extend_from_slice(&1_700_000_000_000_000_000u64.to_le_bytes());
+        data.extend_from_slice(&123_456u64.to_le_bytes());
+        assert_eq!(
+            ClockData::parse::<LittleEndian>(RawData::from(&data[..])).unwrap(),
+            ClockData {
+                clockid: 1,
+                wall_clock_ns: 1_700_000_000_000_000_000,
+                clockid_time_ns: 123_456,
+            }
+        );
+
+        data[0] = 2;
+        assert!(ClockData::parse::<LittleEndian>(RawData::from(&data[..])).is_err());
+    }
+
+    #[test]
+    fn group_desc() {
+        let mut data = 2u32.to_le_bytes().to_vec();
+        for (name, leader_index, member_count) in
+            [("{cycles,instructions}", 0u32, 2u32), ("", 2, 3)]
+        {
+            data.extend(perf_string(name));
+            data.extend_from_slice(&leader_index.to_le_bytes());
+            data.extend_from_slice(&member_count.to_le_bytes());
+        }
+        let groups = GroupDesc::parse_section::<LittleEndian>(RawData::from(&data[..])).unwrap();
+        assert_eq!(
+            groups,
+            vec![
+                GroupDesc {
+                    name: RawData::Single(b"{cycles,instructions}"),
+                    leader_index: 0,
+                    member_count: 2,
+                },
+                GroupDesc {
+                    name: RawData::Single(b""),
+                    leader_index: 2,
+                    member_count: 3,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn pmu_mappings() {
+        let mut data = 2u32.to_le_bytes().to_vec();
+        for (pmu_type, name) in [(4u32, "cpu"), (6, "kprobe")] {
+            data.extend_from_slice(&pmu_type.to_le_bytes());
+            data.extend(perf_string(name));
+        }
+        let mappings = PmuMapping::parse_section::<LittleEndian>(RawData::from(&data[..])).unwrap();
+        assert_eq!(
+            mappings,
+            vec![
+                PmuMapping {
+                    pmu_type: 4,
+                    name: RawData::Single(b"cpu"),
+                },
+                PmuMapping {
+                    pmu_type: 6,
+                    name: RawData::Single(b"kprobe"),
+                },
+            ]
+        );
+    }
+}
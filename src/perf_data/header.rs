@@ -0,0 +1,215 @@
+use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt};
+use std::io::Read;
+
+use super::PerfDataError;
+use crate::Endianness;
+
+/// `perf_file_section`: The location of a section in the perf.data file.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PerfFileSection {
+    /// The offset of the section, from the start of the file.
+    pub offset: u64,
+    /// The size of the section, in bytes.
+    pub size: u64,
+}
+
+impl PerfFileSection {
+    pub const STRUCT_SIZE: u64 = 8 + 8;
+
+    pub fn parse<R: Read, T: ByteOrder>(mut reader: R) -> Result<Self, std::io::Error> {
+        let offset = reader.read_u64::<T>()?;
+        let size = reader.read_u64::<T>()?;
+        Ok(Self { offset, size })
+    }
+}
+
+/// `perf_file_header`: The header at the start of a perf.data file.
+///
+/// ```pseudo-c
+/// struct perf_file_header {
+///     u64 magic; // "PERFILE2"
+///     u64 size; // size of this header
+///     u64 attr_size; // size of one perf_file_attr
+///     struct perf_file_section attrs;
+///     struct perf_file_section data;
+///     struct perf_file_section event_types; // unused
+///     DECLARE_BITMAP(adds_features, HEADER_FEAT_BITS); // 256 bits
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PerfHeader {
+    /// The size of this header, in bytes.
+    pub header_size: u64,
+    /// The size of one entry in the attrs section: a `perf_event_attr`
+    /// followed by a [`PerfFileSection`] for its IDs.
+    pub attr_size: u64,
+    /// The section with the attrs.
+    pub attr_section: PerfFileSection,
+    /// The section with the records.
+    pub data_section: PerfFileSection,
+    /// An unused section, from a time when perf.data files stored event type names.
+    pub event_types_section: PerfFileSection,
+    /// The feature sections which follow the data section.
+    pub features: PerfFeatureSet,
+}
+
+impl PerfHeader {
+    /// The magic bytes at the start of a little-endian perf.data file.
+    pub const MAGIC_LE: [u8; 8] = *b"PERFILE2";
+    /// The magic bytes at the start of a big-endian perf.data file.
+    pub const MAGIC_BE: [u8; 8] = *b"2ELIFREP";
+
+    pub const STRUCT_SIZE: u64 = 8 + 8 + 8 + 3 * PerfFileSection::STRUCT_SIZE + 4 * 8;
+
+    /// Parse the header, and detect the file's endianness from the magic bytes.
+    pub fn parse<R: Read>(mut reader: R) -> Result<(Self, Endianness), PerfDataError> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        let endian = Self::endian_for_magic(magic)?;
+        let header = match endian {
+            Endianness::LittleEndian => Self::parse_after_magic::<_, LittleEndian>(reader)?,
+            Endianness::BigEndian => Self::parse_after_magic::<_, BigEndian>(reader)?,
+        };
+        Ok((header, endian))
+    }
+
+    /// The endianness of the file which starts with these magic bytes.
+    pub fn endian_for_magic(magic: [u8; 8]) -> Result<Endianness, PerfDataError> {
+        match magic {
+            Self::MAGIC_LE => Ok(Endianness::LittleEndian),
+            Self::MAGIC_BE => Ok(Endianness::BigEndian),
+            _ => Err(PerfDataError::UnrecognizedMagicValue(magic)),
+        }
+    }
+
+    fn parse_after_magic<R: Read, T: ByteOrder>(mut reader: R) -> Result<Self, std::io::Error> {
+        let header_size = reader.read_u64::<T>()?;
+        let attr_size = reader.read_u64::<T>()?;
+        let attr_section = PerfFileSection::parse::<_, T>(&mut reader)?;
+        let data_section = PerfFileSection::parse::<_, T>(&mut reader)?;
+        let event_types_section = PerfFileSection::parse::<_, T>(&mut reader)?;
+        let mut features = [0; 4];
+        for chunk in &mut features {
+            *chunk = reader.read_u64::<T>()?;
+        }
+        Ok(Self {
+            header_size,
+            attr_size,
+            attr_section,
+            data_section,
+            event_types_section,
+            features: PerfFeatureSet(features),
+        })
+    }
+}
+
+/// The set of feature sections in a perf.data file (`adds_features`).
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct PerfFeatureSet(pub [u64; 4]);
+
+impl PerfFeatureSet {
+    /// Whether the file has a section for this feature.
+    pub fn has_feature(&self, feature: PerfFeature) -> bool {
+        let index = feature.0 as usize;
+        match self.0.get(index / 64) {
+            Some(chunk) => chunk & (1 << (index % 64)) != 0,
+            None => false,
+        }
+    }
+
+    /// The features in this set, in ascending order. This is also the order
+    /// of the feature sections in the file.
+    pub fn iter(&self) -> impl Iterator<Item = PerfFeature> + '_ {
+        (0..256)
+            .map(PerfFeature)
+            .filter(move |feature| self.has_feature(*feature))
+    }
+
+    /// The number of features in this set.
+    pub fn len(&self) -> usize {
+        self.0.iter().map(|chunk| chunk.count_ones() as usize).sum()
+    }
+
+    /// Whether there are no features in this set.
+    pub fn is_empty(&self) -> bool {
+        self.0.iter().all(|&chunk| chunk == 0)
+    }
+}
+
+/// The ID of a feature section in a perf.data file. (`HEADER_*` in perf's `header.h`)
+#[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct PerfFeature(pub u32);
+
+impl PerfFeature {
+    pub const TRACING_DATA: Self = Self(1);
+    pub const BUILD_ID: Self = Self(2);
+    pub const HOSTNAME: Self = Self(3);
+    pub const OSRELEASE: Self = Self(4);
+    pub const VERSION: Self = Self(5);
+    pub const ARCH: Self = Self(6);
+    pub const NRCPUS: Self = Self(7);
+    pub const CPUDESC: Self = Self(8);
+    pub const CPUID: Self = Self(9);
+    pub const TOTAL_MEM: Self = Self(10);
+    pub const CMDLINE: Self = Self(11);
+    pub const EVENT_DESC: Self = Self(12);
+    pub const CPU_TOPOLOGY: Self = Self(13);
+    pub const NUMA_TOPOLOGY: Self = Self(14);
+    pub const BRANCH_STACK: Self = Self(15);
+    pub const PMU_MAPPINGS: Self = Self(16);
+    pub const GROUP_DESC: Self = Self(17);
+    pub const AUXTRACE: Self = Self(18);
+    pub const STAT: Self = Self(19);
+    pub const CACHE: Self = Self(20);
+    pub const SAMPLE_TIME: Self = Self(21);
+    pub const MEM_TOPOLOGY: Self = Self(22);
+    pub const CLOCKID: Self = Self(23);
+    pub const DIR_FORMAT: Self = Self(24);
+    pub const BPF_PROG_INFO: Self = Self(25);
+    pub const BPF_BTF: Self = Self(26);
+    pub const COMPRESSED: Self = Self(27);
+    pub const CPU_PMU_CAPS: Self = Self(28);
+    pub const CLOCK_DATA: Self = Self(29);
+    pub const HYBRID_TOPOLOGY: Self = Self(30);
+    pub const PMU_CAPS: Self = Self(31);
+}
+
+impl std::fmt::Debug for PerfFeature {
+    fn fmt(&self, fmt: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error> {
+        let s = match *self {
+            Self::TRACING_DATA => "TRACING_DATA",
+            Self::BUILD_ID => "BUILD_ID",
+            Self::HOSTNAME => "HOSTNAME",
+            Self::OSRELEASE => "OSRELEASE",
+            Self::VERSION => "VERSION",
+            Self::ARCH => "ARCH",
+            Self::NRCPUS => "NRCPUS",
+            Self::CPUDESC => "CPUDESC",
+            Self::CPUID => "CPUID",
+            Self::TOTAL_MEM => "TOTAL_MEM",
+            Self::CMDLINE => "CMDLINE",
+            Self::EVENT_DESC => "EVENT_DESC",
+            Self::CPU_TOPOLOGY => "CPU_TOPOLOGY",
+            Self::NUMA_TOPOLOGY => "NUMA_TOPOLOGY",
+            Self::BRANCH_STACK => "BRANCH_STACK",
+            Self::PMU_MAPPINGS => "PMU_MAPPINGS",
+            Self::GROUP_DESC => "GROUP_DESC",
+            Self::AUXTRACE => "AUXTRACE",
+            Self::STAT => "STAT",
+            Self::CACHE => "CACHE",
+            Self::SAMPLE_TIME => "SAMPLE_TIME",
+            Self::MEM_TOPOLOGY => "MEM_TOPOLOGY",
+            Self::CLOCKID => "CLOCKID",
+            Self::DIR_FORMAT => "DIR_FORMAT",
+            Self::BPF_PROG_INFO => "BPF_PROG_INFO",
+            Self::BPF_BTF => "BPF_BTF",
+            Self::COMPRESSED => "COMPRESSED",
+            Self::CPU_PMU_CAPS => "CPU_PMU_CAPS",
+            Self::CLOCK_DATA => "CLOCK_DATA",
+            Self::HYBRID_TOPOLOGY => "HYBRID_TOPOLOGY",
+            Self::PMU_CAPS => "PMU_CAPS",
+            other => return fmt.write_fmt(format_args!("Unknown feature: {}", other.0)),
+        };
+        fmt.write_str(s)
+    }
+}
@@ -0,0 +1,77 @@
+//! Reading perf.data files and pipe-mode streams, as written by `perf record`.
+
+#[cfg(feature = "zstd")]
+mod decompression;
+mod features;
+mod header;
+mod pipe;
+mod reader;
+mod record;
+mod user_record;
+
+pub use features::*;
+pub use header::*;
+pub use pipe::*;
+pub use reader::*;
+pub use record::*;
+pub use user_record::*;
+
+use crate::{AttrTableError, RecordType};
+
+/// An error that can occur when reading a perf.data file.
+#[derive(thiserror::Error, Debug)]
+#[non_exhaustive]
+pub enum PerfDataError {
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
+
+    /// The file didn't start with `PERFILE2` in either byte order.
+    #[error("Unrecognized magic value {0:?}, this is not a perf.data file")]
+    UnrecognizedMagicValue([u8; 8]),
+
+    /// The stream header of a pipe-mode file has an unexpected size. This
+    /// happens when a regular perf.data file is read as a stream.
+    #[error("Unexpected header size {0} for a pipe-mode file")]
+    NotPipeMode(u64),
+
+    /// The header's `attr_size` is too small to hold a `perf_event_attr` and
+    /// the section for its IDs, or larger than any `perf_event_attr` or than
+    /// the attrs section.
+    #[error("Invalid attr size {0}")]
+    InvalidAttrSize(u64),
+
+    /// The attrs can't be combined into an [`AttrTable`](crate::AttrTable).
+    #[error("Could not create the attr table: {0}")]
+    AttrTable(#[from] AttrTableError),
+
+    /// A record header had a size which is smaller than the header itself,
+    /// or larger than the remaining data.
+    #[error("Invalid record size {0}")]
+    InvalidRecordSize(u16),
+
+    /// The attr for a record could not be determined, because its ID didn't
+    /// match any of the attrs' IDs.
+    #[error("Could not find the attr for a {0:?} record")]
+    NoAttrForRecord(RecordType),
+
+    /// A `PERF_RECORD_ID_INDEX` entry referred to an attr which doesn't exist.
+    #[error(
+        "ID index entry for ID {id} refers to attr {idx}, but there are only {attr_count} attrs"
+    )]
+    InvalidIdIndex {
+        id: u64,
+        idx: u64,
+        attr_count: usize,
+    },
+
+    /// A `PERF_RECORD_COMPRESSED` record decompressed to more than the given
+    /// number of bytes, which is the size of the mmap buffers that perf
+    /// compresses, if known.
+    #[error("A compressed record decompressed to more than {0} bytes")]
+    DecompressedRecordTooLarge(usize),
+
+    /// The decompressed data ended with the given number of bytes which don't
+    /// form a complete record.
+    #[error("The compressed data ended with {0} bytes of an incomplete record")]
+    IncompleteCompressedRecord(usize),
+}
@@ -0,0 +1,363 @@
+use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt};
+use std::io::{self, Read};
+
+use super::record::read_record_body;
+use super::{
+    HeaderAttrRecord, HeaderFeatureRecord, HeaderTracingDataRecord, IdIndexRecord, PerfDataError,
+    PerfFeature, PerfFeatureSections, PerfFileRecord, PerfHeader,
+};
+use crate::{AttrTable, Endianness, PerfEventHeader, RawData, RecordType};
+
+/// A reader for the streaming perf.data format, as written by `perf record -o -`.
+///
+/// In pipe mode, the file only starts with a small header. Everything which
+/// a regular perf.data file stores in its header sections arrives in-band as
+/// user records instead:
+///
+///  - `PERF_RECORD_HEADER_ATTR` records carry the attrs and their IDs, and
+///    `PERF_RECORD_ID_INDEX` records can add more IDs. Both are applied to the
+///    reader's [`AttrTable`] as they arrive.
+///  - `PERF_RECORD_HEADER_FEATURE` records carry the feature sections, and
+///    `PERF_RECORD_HEADER_TRACING_DATA` carries the `TRACING_DATA` section.
+///    They are collected in [`PerfPipeReader::feature_sections`].
+///
+/// All records, including the ones above, are also returned from
+/// [`PerfPipeReader::next_record`].
+///
+/// With the `zstd` feature, `PERF_RECORD_COMPRESSED` records are decompressed,
+/// and the records inside them are returned instead.
+#[derive(Debug)]
+pub struct PerfPipeReader<R: Read> {
+    reader: R,
+    endian: Endianness,
+    attr_table: AttrTable,
+    feature_sections: PerfFeatureSections,
+    buffer: Vec<u8>,
+    #[cfg(feature = "zstd")]
+    decompressor: super::decompression::Decompressor,
+}
+
+impl<R: Read> PerfPipeReader<R> {
+    /// The size of `perf_pipe_file_header`: the magic and the header size.
+    pub const HEADER_SIZE: u64 = 8 + 8;
+
+    /// Read the pipe header and detect the endianness.
+    pub fn new(mut reader: R) -> Result<Self, PerfDataError> {
+        let mut magic = [0; 8];
+        reader.read_exact(&mut magic)?;
+        let endian = PerfHeader::endian_for_magic(magic)?;
+        let header_size = match endian {
+            Endianness::LittleEndian => reader.read_u64::<LittleEndian>()?,
+            Endianness::BigEndian => reader.read_u64::<BigEndian>()?,
+        };
+        if header_size != Self::HEADER_SIZE {
+            return Err(PerfDataError::NotPipeMode(header_size));
+        }
+        Ok(Self {
+            reader,
+            endian,
+            attr_table: AttrTable::new(endian),
+            feature_sections: PerfFeatureSections::new(endian),
+            buffer: Vec::new(),
+            #[cfg(feature = "zstd")]
+            decompressor: super::decompression::Decompressor::new(),
+        })
+    }
+
+    /// The endianness of the stream.
+    pub fn endian(&self) -> Endianness {
+        self.endian
+    }
+
+    /// The attrs which have been received so far, with their IDs.
+    pub fn attr_table(&self) -> &AttrTable {
+        &self.attr_table
+    }
+
+    /// The feature sections which have been received so far.
+    pub fn feature_sections(&self) -> &PerfFeatureSections {
+        &self.feature_sections
+    }
+
+    /// Read the next record. Returns `Ok(None)` at the end of the stream.
+    pub fn next_record(&mut self) -> Result<Option<PerfFileRecord<'_>>, PerfDataError> {
+        match self.endian {
+            Endianness::LittleEndian => self.next_record_impl::<LittleEndian>(),
+            Endianness::BigEndian => self.next_record_impl::<BigEndian>(),
+        }
+    }
+
+    // Without the zstd feature, every iteration returns.
+    #[cfg_attr(not(feature = "zstd"), allow(clippy::never_loop))]
+    fn next_record_impl<T: ByteOrder>(
+        &mut self,
+    ) -> Result<Option<PerfFileRecord<'_>>, PerfDataError> {
+        loop {
+            #[cfg(feature = "zstd")]
+            if let Some((header, range)) = self.decompressor.next_record::<T>()? {
+                let data = RawData::from(self.decompressor.record_data(range));
+                let record = apply_record::<T>(
+                    &mut self.attr_table,
+                    &mut self.feature_sections,
+                    &header,
+                    data,
+                )?;
+                return Ok(Some(record));
+            }
+
+            let mut header_bytes = [0; PerfEventHeader::STRUCT_SIZE];
+            if !read_exact_or_eof(&mut self.reader, &mut header_bytes)? {
+                #[cfg(feature = "zstd")]
+                self.decompressor.finish()?;
+                return Ok(None);
+            }
+            let header = PerfEventHeader::parse::<_, T>(&header_bytes[..])?;
+            read_record_body::<_, T>(&mut self.reader, &header, &mut self.buffer)?;
+
+            #[cfg(feature = "zstd")]
+            if header.type_ == crate::constants::PERF_RECORD_COMPRESSED {
+                // The COMPRESSED feature section arrives before the first
+                // compressed record.
+                if let Some(info) = self.feature_sections.compressed()? {
+                    self.decompressor.set_mmap_len(info.mmap_len);
+                }
+                self.decompressor.decompress(&self.buffer)?;
+                continue;
+            }
+
+            let data = RawData::from(&self.buffer[..]);
+            let record = apply_record::<T>(
+                &mut self.attr_table,
+                &mut self.feature_sections,
+                &header,
+                data,
+            )?;
+            return Ok(Some(record));
+        }
+    }
+
+    /// Return the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+/// Apply the attrs, IDs and feature sections carried by a record, and wrap it.
+fn apply_record<'a, T: ByteOrder>(
+    attr_table: &mut AttrTable,
+    feature_sections: &mut PerfFeatureSections,
+    header: &PerfEventHeader,
+    data: RawData<'a>,
+) -> Result<PerfFileRecord<'a>, PerfDataError> {
+    let record_type = RecordType(header.type_);
+    match record_type {
+        RecordType::HEADER_ATTR => {
+            let record = HeaderAttrRecord::parse::<T>(data)?;
+            attr_table.add_attr(record.attr, record.ids)?;
+        }
+        RecordType::ID_INDEX => {
+            for entry in IdIndexRecord::parse::<T>(data)?.entries {
+                let index = usize::try_from(entry.idx)
+                    .ok()
+                    .filter(|&index| index < attr_table.len())
+                    .ok_or(PerfDataError::InvalidIdIndex {
+                        id: entry.id,
+                        idx: entry.idx,
+                        attr_count: attr_table.len(),
+                    })?;
+                attr_table.add_ids(index, &[entry.id])?;
+            }
+        }
+        RecordType::HEADER_FEATURE => {
+            let record = HeaderFeatureRecord::parse::<T>(data)?;
+            let section = record.data.as_slice().into_owned();
+            feature_sections.insert(record.feature, section);
+        }
+        RecordType::HEADER_TRACING_DATA => {
+            let record = HeaderTracingDataRecord::parse::<T>(data)?;
+            let section = record.data.as_slice().into_owned();
+            feature_sections.insert(PerfFeature::TRACING_DATA, section);
+        }
+        _ => {}
+    }
+    PerfFileRecord::new(attr_table, record_type, header.misc, data)
+}
+
+/// Like `read_exact`, but returns `Ok(false)` if the reader is at EOF before
+/// the first byte.
+fn read_exact_or_eof<R: Read>(reader: &mut R, buf: &mut [u8]) -> Result<bool, io::Error> {
+    let mut read = 0;
+    while read < buf.len() {
+        match reader.read(&mut buf[read..]) {
+            Ok(0) if read == 0 => return Ok(false),
+            Ok(0) => return Err(io::ErrorKind::UnexpectedEof.into()),
+            Ok(n) => read += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(true)
+}
+
+#[cfg(test)]
+mod test {
+    use super::PerfPipeReader;
+    use crate::{
+        AttrFlags, EventRecord, PerfDataError, PerfEventAttr, PerfEventType, PerfFeature,
+        PerfFileRecord, RecordType, SampleFormat, SoftwareCounterType,
+    };
+    use byteorder::{LittleEndian, WriteBytesExt};
+
+    fn write_header(stream: &mut Vec<u8>, record_type: RecordType, body_size: usize) {
+        stream.write_u32::<LittleEndian>(record_type.0).unwrap();
+        stream.write_u16::<LittleEndian>(0).unwrap();
+        stream
+            .write_u16::<LittleEndian>(8 + body_size as u16)
+            .unwrap();
+    }
+
+    #[test]
+    fn attrs_and_features_in_band() {
+        let mut stream = Vec::new();
+        stream.extend_from_slice(b"PERFILE2");
+        stream.write_u64::<LittleEndian>(16).unwrap();
+
+        let mut attr = PerfEventAttr::new(PerfEventType::Software(SoftwareCounterType::CpuClock));
+        attr.sample_format = SampleFormat::IDENTIFIER | SampleFormat::TID;
+        attr.flags = AttrFlags::SAMPLE_ID_ALL;
+        for id in [1u64, 2] {
+            write_header(&mut stream, RecordType::HEADER_ATTR, 128 + 8);
+            attr.write::<_, LittleEndian>(&mut stream).unwrap();
+            stream.write_u64::<LittleEndian>(id).unwrap();
+        }
+
+        write_header(&mut stream, RecordType::HEADER_FEATURE, 8 + 8);
+        stream.write_u64::<LittleEndian>(7).unwrap(); // NRCPUS
+        stream.write_u32::<LittleEndian>(8).unwrap();
+        stream.write_u32::<LittleEndian>(4).unwrap();
+
+        write_header(&mut stream, RecordType::SAMPLE, 16);
+        stream.write_u64::<LittleEndian>(2).unwrap();
+        stream.write_i32::<LittleEndian>(100).unwrap();
+        stream.write_i32::<LittleEndian>(101).unwrap();
+
+        write_header(&mut stream, RecordType::FINISHED_ROUND, 0);
+
+        let mut reader = PerfPipeReader::new(&stream[..]).unwrap();
+        let mut user_record_count = 0;
+        let mut sample = None;
+        while let Some(record) = reader.next_record().unwrap() {
+            match record {
+                PerfFileRecord::EventRecord { attr_index, record } => {
+                    let EventRecord::Sample(s) = record.parse().unwrap() else {
+                        panic!("expected a sample");
+                    };
+                    sample = Some((attr_index, s.pid, s.tid));
+                }
+                PerfFileRecord::UserRecord(_) => user_record_count += 1,
+            }
+        }
+        assert_eq!(user_record_count, 4);
+        assert_eq!(sample, Some((1, Some(100), Some(101))));
+        assert_eq!(reader.attr_table().index_for_id(2), Some(1));
+        let nr_cpus = reader.feature_sections().nr_cpus().unwrap().unwrap();
+        assert_eq!(nr_cpus.nr_cpus_online, 4);
+        assert_eq!(reader.feature_sections().get(PerfFeature::HOSTNAME), None);
+    }
+
+    #[test]
+    fn rejects_id_index_for_unknown_attr() {
+        let mut stream = Vec::new();
+        stream.extend_from_slice(b"PERFILE2");
+        stream.write_u64::<LittleEndian>(16).unwrap();
+
+        let attr = PerfEventAttr::new(PerfEventType::Software(SoftwareCounterType::CpuClock));
+        write_header(&mut stream, RecordType::HEADER_ATTR, 128 + 8);
+        attr.write::<_, LittleEndian>(&mut stream).unwrap();
+        stream.write_u64::<LittleEndian>(1).unwrap();
+
+        // nr, then { id, idx, cpu, tid }
+        write_header(&mut stream, RecordType::ID_INDEX, 8 + 32);
+        stream.write_u64::<LittleEndian>(1).unwrap();
+        stream.write_u64::<LittleEndian>(5).unwrap();
+        stream.write_u64::<LittleEndian>(3).unwrap();
+        stream.write_u64::<LittleEndian>(u64::MAX).unwrap();
+        stream.write_u64::<LittleEndian>(u64::MAX).unwrap();
+
+        let mut reader = PerfPipeReader::new(&stream[..]).unwrap();
+        assert!(reader.next_record().unwrap().is_some());
+        assert!(matches!(
+            reader.next_record(),
+            Err(PerfDataError::InvalidIdIndex {
+                id: 5,
+                idx: 3,
+                attr_count: 1
+            })
+        ));
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn compressed_records() {
+        use std::io::Write;
+
+        let mut stream = Vec::new();
+        stream.extend_from_slice(b"PERFILE2");
+        stream.write_u64::<LittleEndian>(16).unwrap();
+
+        let mut attr = PerfEventAttr::new(PerfEventType::Software(SoftwareCounterType::CpuClock));
+        attr.sample_format = SampleFormat::IDENTIFIER | SampleFormat::TID;
+        write_header(&mut stream, RecordType::HEADER_ATTR, 128 + 8);
+        attr.write::<_, LittleEndian>(&mut stream).unwrap();
+        stream.write_u64::<LittleEndian>(1).unwrap();
+
+        let mut records = Vec::new();
+        for tid in [100, 101, 102] {
+            write_header(&mut records, RecordType::SAMPLE, 16);
+            records.write_u64::<LittleEndian>(1).unwrap();
+            records.write_i32::<LittleEndian>(100).unwrap();
+            records.write_i32::<LittleEndian>(tid).unwrap();
+        }
+
+        // Compress the records as one stream, and cut it in the middle of
+        // the second record.
+        let mut encoder = zstd::stream::write::Encoder::new(Vec::new(), 0).unwrap();
+        encoder.write_all(&records[..30]).unwrap();
+        encoder.flush().unwrap();
+        let first_chunk_len = encoder.get_ref().len();
+        encoder.write_all(&records[30..]).unwrap();
+        let compressed = encoder.finish().unwrap();
+        for chunk in [
+            &compressed[..first_chunk_len],
+            &compressed[first_chunk_len..],
+        ] {
+            write_header(&mut stream, RecordType::COMPRESSED, chunk.len());
+            stream.extend_from_slice(chunk);
+        }
+        write_header(&mut stream, RecordType::FINISHED_ROUND, 0);
+
+        let mut reader = PerfPipeReader::new(&stream[..]).unwrap();
+        let mut record_types = Vec::new();
+        let mut tids = Vec::new();
+        while let Some(record) = reader.next_record().unwrap() {
+            record_types.push(record.record_type());
+            if let PerfFileRecord::EventRecord { record, .. } = record {
+                let EventRecord::Sample(s) = record.parse().unwrap() else {
+                    panic!("expected a sample");
+                };
+                tids.push(s.tid.unwrap());
+            }
+        }
+        assert_eq!(
+            record_types,
+            [
+                RecordType::HEADER_ATTR,
+                RecordType::SAMPLE,
+                RecordType::SAMPLE,
+                RecordType::SAMPLE,
+                RecordType::FINISHED_ROUND
+            ]
+        );
+        assert_eq!(tids, [100, 101, 102]);
+    }
+}
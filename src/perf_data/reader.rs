@@ -0,0 +1,410 @@
+use byteorder::{BigEndian, ByteOrder, LittleEndian, ReadBytesExt};
+use std::io::{Read, Seek, SeekFrom};
+
+use super::record::read_record_body;
+use super::{
+    PerfDataError, PerfFeature, PerfFeatureSections, PerfFileRecord, PerfFileSection, PerfHeader,
+};
+use crate::constants::PERF_ATTR_SIZE_VER0;
+use crate::{AttrTable, Endianness, PerfEventAttr, PerfEventHeader, RawData, RecordType};
+
+/// A reader for perf.data files.
+///
+/// `PerfFileReader` parses the header and the attrs up front, and then
+/// iterates over the records in the data section. Every kernel record is
+/// paired with the [`RecordParseInfo`](crate::RecordParseInfo) of its attr,
+/// which is found with an [`AttrTable`].
+///
+/// Records are read one by one, so the reader should be buffered, e.g. with
+/// a [`BufReader`](std::io::BufReader).
+///
+/// With the `zstd` feature, `PERF_RECORD_COMPRESSED` records, as written by
+/// `perf record -z`, are decompressed, and the records inside them are
+/// returned instead.
+#[derive(Debug)]
+pub struct PerfFileReader<R: Read + Seek> {
+    reader: R,
+    endian: Endianness,
+    header: PerfHeader,
+    attr_table: AttrTable,
+    remaining_data_size: u64,
+    buffer: Vec<u8>,
+    #[cfg(feature = "zstd")]
+    decompressor: super::decompression::Decompressor,
+}
+
+impl<R: Read + Seek> PerfFileReader<R> {
+    /// Parse the header and the attrs of the perf.data file, and prepare for
+    /// reading the records. `reader` must be positioned at the start of the file.
+    pub fn parse_file(mut reader: R) -> Result<Self, PerfDataError> {
+        let (header, endian) = PerfHeader::parse(&mut reader)?;
+        let attrs = match endian {
+            Endianness::LittleEndian => read_attrs::<_, LittleEndian>(&mut reader, &header)?,
+            Endianness::BigEndian => read_attrs::<_, BigEndian>(&mut reader, &header)?,
+        };
+        let attr_table = AttrTable::from_attrs(attrs, endian)?;
+        let mut file_reader = Self {
+            reader,
+            endian,
+            header,
+            attr_table,
+            remaining_data_size: header.data_section.size,
+            buffer: Vec::new(),
+            #[cfg(feature = "zstd")]
+            decompressor: super::decompression::Decompressor::new(),
+        };
+        #[cfg(feature = "zstd")]
+        if header.features.has_feature(PerfFeature::COMPRESSED) {
+            let sections = match endian {
+                Endianness::LittleEndian => file_reader
+                    .read_feature_sections_impl::<LittleEndian>(|f| f == PerfFeature::COMPRESSED),
+                Endianness::BigEndian => file_reader
+                    .read_feature_sections_impl::<BigEndian>(|f| f == PerfFeature::COMPRESSED),
+            }?;
+            if let Some(info) = sections.compressed()? {
+                file_reader.decompressor.set_mmap_len(info.mmap_len);
+            }
+        }
+        file_reader
+            .reader
+            .seek(SeekFrom::Start(header.data_section.offset))?;
+        Ok(file_reader)
+    }
+
+    /// The endianness of the file.
+    pub fn endian(&self) -> Endianness {
+        self.endian
+    }
+
+    /// The file header.
+    pub fn header(&self) -> &PerfHeader {
+        &self.header
+    }
+
+    /// The attrs of this file, with their IDs.
+    pub fn attr_table(&self) -> &AttrTable {
+        &self.attr_table
+    }
+
+    /// Read the feature sections which follow the data section.
+    ///
+    /// This can be called at any time; it doesn't affect the position of
+    /// [`PerfFileReader::next_record`].
+    pub fn read_feature_sections(&mut self) -> Result<PerfFeatureSections, PerfDataError> {
+        let position = self.reader.stream_position()?;
+        let sections = match self.endian {
+            Endianness::LittleEndian => self.read_feature_sections_impl::<LittleEndian>(|_| true),
+            Endianness::BigEndian => self.read_feature_sections_impl::<BigEndian>(|_| true),
+        };
+        self.reader.seek(SeekFrom::Start(position))?;
+        sections
+    }
+
+    /// Read the feature sections for which `wanted` returns true.
+    fn read_feature_sections_impl<T: ByteOrder>(
+        &mut self,
+        wanted: impl Fn(PerfFeature) -> bool,
+    ) -> Result<PerfFeatureSections, PerfDataError> {
+        // The feature section table follows the data section, with one
+        // perf_file_section per feature, in the order of the feature bits.
+        let data_section = self.header.data_section;
+        self.reader
+            .seek(SeekFrom::Start(data_section.offset + data_section.size))?;
+        let mut feature_sections = Vec::new();
+        for feature in self.header.features.iter() {
+            let section = PerfFileSection::parse::<_, T>(&mut self.reader)?;
+            feature_sections.push((feature, section));
+        }
+
+        let mut sections = PerfFeatureSections::new(self.endian);
+        for (feature, section) in feature_sections {
+            if !wanted(feature) {
+                continue;
+            }
+            self.reader.seek(SeekFrom::Start(section.offset))?;
+            let mut data = Vec::new();
+            (&mut self.reader)
+                .take(section.size)
+                .read_to_end(&mut data)?;
+            if (data.len() as u64) < section.size {
+                return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+            }
+            sections.insert(feature, data);
+        }
+        Ok(sections)
+    }
+
+    /// Read the next record from the data section. Returns `Ok(None)` at the
+    /// end of the data section.
+    ///
+    /// If an error is returned for a record whose header could be read, the
+    /// record is skipped, and the next call continues with the record after it.
+    pub fn next_record(&mut self) -> Result<Option<PerfFileRecord<'_>>, PerfDataError> {
+        match self.endian {
+            Endianness::LittleEndian => self.next_record_impl::<LittleEndian>(),
+            Endianness::BigEndian => self.next_record_impl::<BigEndian>(),
+        }
+    }
+
+    // Without the zstd feature, every iteration returns.
+    #[cfg_attr(not(feature = "zstd"), allow(clippy::never_loop))]
+    fn next_record_impl<T: ByteOrder>(
+        &mut self,
+    ) -> Result<Option<PerfFileRecord<'_>>, PerfDataError> {
+        loop {
+            #[cfg(feature = "zstd")]
+            if let Some((header, range)) = self.decompressor.next_record::<T>()? {
+                let data = RawData::from(self.decompressor.record_data(range));
+                let record = PerfFileRecord::new(
+                    &self.attr_table,
+                    RecordType(header.type_),
+                    header.misc,
+                    data,
+                )?;
+                return Ok(Some(record));
+            }
+
+            if self.remaining_data_size < PerfEventHeader::STRUCT_SIZE as u64 {
+                #[cfg(feature = "zstd")]
+                self.decompressor.finish()?;
+                return Ok(None);
+            }
+            let header = PerfEventHeader::parse::<_, T>(&mut self.reader)?;
+            let size = u64::from(header.size);
+            if size > self.remaining_data_size {
+                self.remaining_data_size = 0;
+                return Err(PerfDataError::InvalidRecordSize(header.size));
+            }
+            match read_record_body::<_, T>(&mut self.reader, &header, &mut self.buffer) {
+                Ok(read_size) => {
+                    let record_size = PerfEventHeader::STRUCT_SIZE as u64 + read_size;
+                    self.remaining_data_size = self.remaining_data_size.saturating_sub(record_size);
+                }
+                Err(e) => {
+                    self.remaining_data_size = 0;
+                    return Err(e);
+                }
+            }
+
+            #[cfg(feature = "zstd")]
+            if header.type_ == crate::constants::PERF_RECORD_COMPRESSED {
+                self.decompressor.decompress(&self.buffer)?;
+                continue;
+            }
+
+            let data = RawData::from(&self.buffer[..]);
+            let record = PerfFileRecord::new(
+                &self.attr_table,
+                RecordType(header.type_),
+                header.misc,
+                data,
+            )?;
+            return Ok(Some(record));
+        }
+    }
+
+    /// Return the underlying reader.
+    pub fn into_inner(self) -> R {
+        self.reader
+    }
+}
+
+/// Read the `perf_file_attr` entries in the attrs section, and the IDs for each attr.
+fn read_attrs<R: Read + Seek, T: ByteOrder>(
+    reader: &mut R,
+    header: &PerfHeader,
+) -> Result<Vec<(PerfEventAttr, Vec<u64>)>, PerfDataError> {
+    // The kernel limits perf_event_attr to a page, so anything larger is
+    // a corrupt header, and must not be used for the buffer allocation.
+    const MAX_ATTR_SIZE: u64 = 4096 + PerfFileSection::STRUCT_SIZE;
+    let attr_size = header.attr_size;
+    if attr_size < u64::from(PERF_ATTR_SIZE_VER0) + PerfFileSection::STRUCT_SIZE
+        || attr_size > MAX_ATTR_SIZE
+        || attr_size > header.attr_section.size
+    {
+        return Err(PerfDataError::InvalidAttrSize(attr_size));
+    }
+    let attr_count = header.attr_section.size / attr_size;
+
+    // struct perf_file_attr {
+    //     struct perf_event_attr attr;
+    //     struct perf_file_section ids;
+    // };
+    reader.seek(SeekFrom::Start(header.attr_section.offset))?;
+    let mut buffer = vec![0; attr_size as usize];
+    let mut attrs_and_id_sections = Vec::new();
+    for _ in 0..attr_count {
+        reader.read_exact(&mut buffer)?;
+        let ids_offset = buffer.len() - PerfFileSection::STRUCT_SIZE as usize;
+        let (attr_bytes, ids_bytes) = buffer.split_at(ids_offset);
+        let (attr, _size) = PerfEventAttr::parse::<_, T>(attr_bytes)?;
+        let ids_section = PerfFileSection::parse::<_, T>(ids_bytes)?;
+        attrs_and_id_sections.push((attr, ids_section));
+    }
+
+    let mut attrs = Vec::with_capacity(attrs_and_id_sections.len());
+    for (attr, ids_section) in attrs_and_id_sections {
+        reader.seek(SeekFrom::Start(ids_section.offset))?;
+        let id_count = ids_section.size / 8;
+        let mut ids = Vec::new();
+        for _ in 0..id_count {
+            ids.push(reader.read_u64::<T>()?);
+        }
+        attrs.push((attr, ids));
+    }
+    Ok(attrs)
+}
+
+#[cfg(test)]
+mod test {
+    use super::PerfFileReader;
+    use crate::{
+        AttrFlags, CommOrExecRecord, Endianness, EventRecord, HardwareEventId, PerfDataError,
+        PerfEventAttr, PerfEventType, PerfFeature, PerfFileRecord, PmuTypeId, RawData, RecordType,
+        SampleFormat, SamplingPolicy,
+    };
+    use byteorder::{BigEndian, ByteOrder, LittleEndian, WriteBytesExt};
+    use std::io::Cursor;
+
+    /// Build a perf.data file with two attrs, a COMM, a SAMPLE and a user
+    /// record, and a HOSTNAME feature section.
+    fn build_file<T: ByteOrder>() -> Vec<u8> {
+        let mut attrs = Vec::new();
+        for id in [10, 20] {
+            let mut attr = PerfEventAttr::new(PerfEventType::Hardware(
+                HardwareEventId::CpuCycles,
+                PmuTypeId(0),
+            ));
+            attr.sampling_policy = SamplingPolicy::Frequency(1000);
+            attr.sample_format = SampleFormat::IDENTIFIER | SampleFormat::TID;
+            attr.flags = AttrFlags::SAMPLE_ID_ALL | AttrFlags::COMM;
+            if id == 20 {
+                attr.sample_format |= SampleFormat::TIME;
+            }
+            attrs.push((attr, id));
+        }
+
+        let mut records = Vec::new();
+        // COMM with sample_id (tid, time, identifier) for attr 1
+        records.write_u32::<T>(RecordType::COMM.0).unwrap();
+        records.write_u16::<T>(0).unwrap();
+        records.write_u16::<T>(8 + 8 + 8 + 8 + 8 + 8).unwrap();
+        records.write_i32::<T>(12).unwrap();
+        records.write_i32::<T>(13).unwrap();
+        records.extend_from_slice(b"thread\0\0");
+        records.write_i32::<T>(12).unwrap();
+        records.write_i32::<T>(13).unwrap();
+        records.write_u64::<T>(1234).unwrap();
+        records.write_u64::<T>(20).unwrap();
+        // SAMPLE for attr 0
+        records.write_u32::<T>(RecordType::SAMPLE.0).unwrap();
+        records.write_u16::<T>(0).unwrap();
+        records.write_u16::<T>(8 + 8 + 8).unwrap();
+        records.write_u64::<T>(10).unwrap();
+        records.write_i32::<T>(12).unwrap();
+        records.write_i32::<T>(13).unwrap();
+        // FINISHED_ROUND
+        records.write_u32::<T>(68).unwrap();
+        records.write_u16::<T>(0).unwrap();
+        records.write_u16::<T>(8).unwrap();
+
+        let header_size = 104;
+        let attr_size = 128 + 16;
+        let attrs_offset = header_size;
+        let ids_offset = attrs_offset + attrs.len() as u64 * attr_size;
+        let data_offset = ids_offset + attrs.len() as u64 * 8;
+
+        let mut file = Vec::new();
+        if T::read_u16(&[1, 0]) == 1 {
+            file.extend_from_slice(b"PERFILE2");
+        } else {
+            file.extend_from_slice(b"2ELIFREP");
+        }
+        file.write_u64::<T>(header_size).unwrap();
+        file.write_u64::<T>(attr_size).unwrap();
+        file.write_u64::<T>(attrs_offset).unwrap();
+        file.write_u64::<T>(attrs.len() as u64 * attr_size).unwrap();
+        file.write_u64::<T>(data_offset).unwrap();
+        file.write_u64::<T>(records.len() as u64).unwrap();
+        file.extend_from_slice(&[0; 16]);
+        file.write_u64::<T>(1 << PerfFeature::HOSTNAME.0).unwrap();
+        file.extend_from_slice(&[0; 24]);
+        for (i, (attr, _)) in attrs.iter().enumerate() {
+            attr.write::<_, T>(&mut file).unwrap();
+            file.write_u64::<T>(ids_offset + i as u64 * 8).unwrap();
+            file.write_u64::<T>(8).unwrap();
+        }
+        for (_, id) in &attrs {
+            file.write_u64::<T>(*id).unwrap();
+        }
+        file.extend_from_slice(&records);
+
+        // The feature section table, followed by the HOSTNAME section.
+        let hostname_offset = file.len() as u64 + 16;
+        file.write_u64::<T>(hostname_offset).unwrap();
+        file.write_u64::<T>(4 + 64).unwrap();
+        file.write_u32::<T>(64).unwrap();
+        file.extend_from_slice(b"myhost");
+        file.extend_from_slice(&[0; 58]);
+        file
+    }
+
+    fn check_file(file: Vec<u8>, endian: Endianness) {
+        let mut reader = PerfFileReader::parse_file(Cursor::new(file)).unwrap();
+        assert_eq!(reader.endian(), endian);
+        assert_eq!(reader.attr_table().len(), 2);
+
+        let Some(PerfFileRecord::EventRecord { attr_index, record }) =
+            reader.next_record().unwrap()
+        else {
+            panic!("expected an event record");
+        };
+        assert_eq!(attr_index, 1);
+        assert_eq!(
+            record.parse().unwrap(),
+            EventRecord::Comm(CommOrExecRecord {
+                pid: 12,
+                tid: 13,
+                name: RawData::Single(b"thread"),
+                is_execve: false,
+            })
+        );
+        assert_eq!(record.common_data().unwrap().timestamp, Some(1234));
+
+        let Some(PerfFileRecord::EventRecord { attr_index, .. }) = reader.next_record().unwrap()
+        else {
+            panic!("expected an event record");
+        };
+        assert_eq!(attr_index, 0);
+
+        let Some(PerfFileRecord::UserRecord(record)) = reader.next_record().unwrap() else {
+            panic!("expected a user record");
+        };
+        assert_eq!(record.record_type, RecordType(68));
+        assert!(reader.next_record().unwrap().is_none());
+
+        let features = reader.read_feature_sections().unwrap();
+        assert_eq!(
+            features.hostname().unwrap(),
+            Some(RawData::Single(b"myhost"))
+        );
+    }
+
+    #[test]
+    fn little_and_big_endian() {
+        check_file(build_file::<LittleEndian>(), Endianness::LittleEndian);
+        check_file(build_file::<BigEndian>(), Endianness::BigEndian);
+    }
+
+    #[test]
+    fn rejects_huge_attr_size() {
+        for attr_size in [u64::MAX, 1 << 20, 2 * 144 + 8] {
+            let mut file = build_file::<LittleEndian>();
+            file[16..24].copy_from_slice(&attr_size.to_le_bytes());
+            assert!(matches!(
+                PerfFileReader::parse_file(Cursor::new(file)),
+                Err(PerfDataError::InvalidAttrSize(size)) if size == attr_size
+            ));
+        }
+    }
+}
@@ -0,0 +1,121 @@
+use byteorder::ByteOrder;
+use std::io::Read;
+
+use super::PerfDataError;
+use crate::constants::{PERF_RECORD_AUXTRACE, PERF_RECORD_HEADER_TRACING_DATA};
+use crate::{AttrTable, Endianness, PerfEventHeader, RawData, RawEventRecord, RecordType};
+
+/// A record from the data section of a perf.data file.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum PerfFileRecord<'a> {
+    /// A record which was emitted by the kernel, i.e. a record with a builtin
+    /// record type. `attr_index` is the index of the attr it belongs to, in
+    /// the reader's [`AttrTable`](crate::AttrTable).
+    EventRecord {
+        attr_index: usize,
+        record: RawEventRecord<'a>,
+    },
+    /// A record which was synthesized by the perf tool, i.e. a record with a
+    /// user record type. See [`RecordType::is_user_type`].
+    UserRecord(RawUserRecord<'a>),
+}
+
+impl<'a> PerfFileRecord<'a> {
+    /// The record type.
+    pub fn record_type(&self) -> RecordType {
+        match self {
+            Self::EventRecord { record, .. } => record.record_type,
+            Self::UserRecord(record) => record.record_type,
+        }
+    }
+
+    /// The `misc` value on this record.
+    pub fn misc(&self) -> u16 {
+        match self {
+            Self::EventRecord { record, .. } => record.misc,
+            Self::UserRecord(record) => record.misc,
+        }
+    }
+
+    /// The raw bytes in the body of this record.
+    pub fn data(&self) -> RawData<'a> {
+        match self {
+            Self::EventRecord { record, .. } => record.data,
+            Self::UserRecord(record) => record.data,
+        }
+    }
+
+    /// Wrap a record body. Kernel records get the parse info of their attr.
+    pub(crate) fn new(
+        attr_table: &AttrTable,
+        record_type: RecordType,
+        misc: u16,
+        data: RawData<'a>,
+    ) -> Result<Self, PerfDataError> {
+        if record_type.is_user_type() {
+            return Ok(Self::UserRecord(RawUserRecord {
+                record_type,
+                misc,
+                data,
+                endian: attr_table.endian(),
+            }));
+        }
+        let attr_index = attr_table
+            .attr_index_for_record(record_type, data)
+            .ok_or(PerfDataError::NoAttrForRecord(record_type))?;
+        let parse_info = attr_table.entries()[attr_index].parse_info;
+        let record = RawEventRecord::new(record_type, misc, data, parse_info);
+        Ok(Self::EventRecord { attr_index, record })
+    }
+}
+
+/// Read the body of the record with this header into `buffer`, followed by
+/// the payload which some user records carry outside of their `header.size`:
+/// `PERF_RECORD_HEADER_TRACING_DATA` and `PERF_RECORD_AUXTRACE` records are
+/// followed by the tracing data and the AUX data, respectively.
+///
+/// Returns the number of bytes that were read.
+pub(crate) fn read_record_body<R: Read, T: ByteOrder>(
+    reader: &mut R,
+    header: &PerfEventHeader,
+    buffer: &mut Vec<u8>,
+) -> Result<u64, PerfDataError> {
+    let body_size = (header.size as usize)
+        .checked_sub(PerfEventHeader::STRUCT_SIZE)
+        .ok_or(PerfDataError::InvalidRecordSize(header.size))?;
+    buffer.resize(body_size, 0);
+    reader.read_exact(buffer)?;
+
+    let payload_size = match header.type_ {
+        // struct perf_record_header_tracing_data { header; u32 size; u32 pad; }
+        PERF_RECORD_HEADER_TRACING_DATA if body_size >= 4 => {
+            u64::from(T::read_u32(&buffer[..4])).next_multiple_of(8)
+        }
+        // struct perf_record_auxtrace { header; u64 size; u64 offset; ... }
+        PERF_RECORD_AUXTRACE if body_size >= 8 => T::read_u64(&buffer[..8]),
+        _ => 0,
+    };
+    if payload_size != 0 {
+        reader.take(payload_size).read_to_end(buffer)?;
+        if (buffer.len() - body_size) as u64 != payload_size {
+            return Err(std::io::Error::from(std::io::ErrorKind::UnexpectedEof).into());
+        }
+    }
+    Ok(body_size as u64 + payload_size)
+}
+
+/// A record with a user record type, which was synthesized by the perf tool
+/// rather than emitted by the kernel.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RawUserRecord<'a> {
+    /// The record type. This is a user type, i.e. `record_type.is_user_type()` is `true`.
+    pub record_type: RecordType,
+    /// The `misc` value on this record.
+    pub misc: u16,
+    /// The raw bytes in the body of this record. For records which are
+    /// followed by a payload (`PERF_RECORD_HEADER_TRACING_DATA` and
+    /// `PERF_RECORD_AUXTRACE`), the payload is included.
+    pub data: RawData<'a>,
+    /// The endianness of the file this record came from.
+    pub endian: Endianness,
+}
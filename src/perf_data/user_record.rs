@@ -0,0 +1,1042 @@
+use byteorder::{BigEndian, ByteOrder, LittleEndian};
+use std::io;
+
+use super::{BuildIdEvent, PerfFeature, RawUserRecord};
+use crate::{Endianness, PerfEventAttr, RawData, RawDataU64, RecordType};
+
+/// A parsed user record, i.e. a record which was synthesized by the perf
+/// tool rather than emitted by the kernel.
+///
+/// User records don't carry a sample ID, so [`get_record_id`](crate::get_record_id)
+/// returns `None` for them. They don't belong to any attr and are parsed
+/// with [`RawUserRecord::parse`] instead.
+#[derive(Debug, Clone)]
+#[non_exhaustive]
+pub enum UserRecord<'a> {
+    /// `PERF_RECORD_HEADER_ATTR`
+    HeaderAttr(HeaderAttrRecord),
+    /// `PERF_RECORD_HEADER_EVENT_TYPE`
+    HeaderEventType(HeaderEventTypeRecord<'a>),
+    /// `PERF_RECORD_HEADER_TRACING_DATA`
+    HeaderTracingData(HeaderTracingDataRecord<'a>),
+    /// `PERF_RECORD_HEADER_BUILD_ID`
+    HeaderBuildId(BuildIdEvent<'a>),
+    /// `PERF_RECORD_FINISHED_ROUND`: All records before this one can be sorted
+    /// and processed, see [`OrderedEventQueue`](crate::OrderedEventQueue).
+    FinishedRound,
+    /// `PERF_RECORD_ID_INDEX`
+    IdIndex(IdIndexRecord),
+    /// `PERF_RECORD_AUXTRACE_INFO`
+    AuxtraceInfo(AuxtraceInfoRecord<'a>),
+    /// `PERF_RECORD_AUXTRACE`
+    Auxtrace(AuxtraceRecord<'a>),
+    /// `PERF_RECORD_AUXTRACE_ERROR`
+    AuxtraceError(AuxtraceErrorRecord<'a>),
+    /// `PERF_RECORD_THREAD_MAP`
+    ThreadMap(ThreadMapRecord<'a>),
+    /// `PERF_RECORD_CPU_MAP`
+    CpuMap(CpuMapRecord),
+    /// `PERF_RECORD_STAT_CONFIG`
+    StatConfig(StatConfigRecord),
+    /// `PERF_RECORD_STAT`
+    Stat(StatRecord),
+    /// `PERF_RECORD_STAT_ROUND`
+    StatRound(StatRoundRecord),
+    /// `PERF_RECORD_EVENT_UPDATE`
+    EventUpdate(EventUpdateRecord<'a>),
+    /// `PERF_RECORD_TIME_CONV`
+    TimeConv(TimeConvRecord),
+    /// `PERF_RECORD_HEADER_FEATURE`
+    HeaderFeature(HeaderFeatureRecord<'a>),
+    /// `PERF_RECORD_COMPRESSED`
+    Compressed(CompressedRecord<'a>),
+    /// `PERF_RECORD_FINISHED_INIT`: The end of the synthesized records which
+    /// describe the initial state, e.g. the attrs and the existing threads.
+    FinishedInit,
+    /// A user record type which this crate doesn't know about.
+    Raw(RawUserRecord<'a>),
+}
+
+impl<'a> RawUserRecord<'a> {
+    /// Parse this record into a [`UserRecord`]. Unknown record types are
+    /// returned as [`UserRecord::Raw`].
+    pub fn parse(&self) -> Result<UserRecord<'a>, io::Error> {
+        match self.endian {
+            Endianness::LittleEndian => self.parse_impl::<LittleEndian>(),
+            Endianness::BigEndian => self.parse_impl::<BigEndian>(),
+        }
+    }
+
+    fn parse_impl<T: ByteOrder>(&self) -> Result<UserRecord<'a>, io::Error> {
+        let data = self.data;
+        let record = match self.record_type {
+            RecordType::HEADER_ATTR => UserRecord::HeaderAttr(HeaderAttrRecord::parse::<T>(data)?),
+            RecordType::HEADER_EVENT_TYPE => {
+                UserRecord::HeaderEventType(HeaderEventTypeRecord::parse::<T>(data)?)
+            }
+            RecordType::HEADER_TRACING_DATA => {
+                UserRecord::HeaderTracingData(HeaderTracingDataRecord::parse::<T>(data)?)
+            }
+            RecordType::HEADER_BUILD_ID => {
+                UserRecord::HeaderBuildId(BuildIdEvent::parse::<T>(data, self.misc)?)
+            }
+            RecordType::FINISHED_ROUND => UserRecord::FinishedRound,
+            RecordType::ID_INDEX => UserRecord::IdIndex(IdIndexRecord::parse::<T>(data)?),
+            RecordType::AUXTRACE_INFO => {
+                UserRecord::AuxtraceInfo(AuxtraceInfoRecord::parse::<T>(data)?)
+            }
+            RecordType::AUXTRACE => UserRecord::Auxtrace(AuxtraceRecord::parse::<T>(data)?),
+            RecordType::AUXTRACE_ERROR => {
+                UserRecord::AuxtraceError(AuxtraceErrorRecord::parse::<T>(data)?)
+            }
+            RecordType::THREAD_MAP => UserRecord::ThreadMap(ThreadMapRecord::parse::<T>(data)?),
+            RecordType::CPU_MAP => UserRecord::CpuMap(CpuMapRecord::parse::<T>(data)?),
+            RecordType::STAT_CONFIG => UserRecord::StatConfig(StatConfigRecord::parse::<T>(data)?),
+            RecordType::STAT => UserRecord::Stat(StatRecord::parse::<T>(data)?),
+            RecordType::STAT_ROUND => UserRecord::StatRound(StatRoundRecord::parse::<T>(data)?),
+            RecordType::EVENT_UPDATE => {
+                UserRecord::EventUpdate(EventUpdateRecord::parse::<T>(data)?)
+            }
+            RecordType::TIME_CONV => UserRecord::TimeConv(TimeConvRecord::parse::<T>(data)?),
+            RecordType::HEADER_FEATURE => {
+                UserRecord::HeaderFeature(HeaderFeatureRecord::parse::<T>(data)?)
+            }
+            RecordType::COMPRESSED => UserRecord::Compressed(CompressedRecord { data }),
+            RecordType::FINISHED_INIT => UserRecord::FinishedInit,
+            _ => UserRecord::Raw(*self),
+        };
+        Ok(record)
+    }
+}
+
+/// `PERF_RECORD_HEADER_ATTR`: An attr and its IDs, in pipe mode.
+///
+/// ```pseudo-c
+/// struct {
+///     struct perf_event_header header;
+///     struct perf_event_attr attr;
+///     u64 id[];
+/// };
+/// ```
+#[derive(Debug, Clone)]
+pub struct HeaderAttrRecord {
+    /// The attr.
+    pub attr: PerfEventAttr,
+    /// The IDs of the events which were opened with this attr.
+    pub ids: Vec<u64>,
+}
+
+impl HeaderAttrRecord {
+    pub fn parse<T: ByteOrder>(data: RawData) -> Result<Self, io::Error> {
+        let (attr, attr_size) = PerfEventAttr::parse::<_, T>(&data.as_slice()[..])?;
+        let mut cur = data;
+        cur.skip(attr_size as usize)?;
+        let mut ids = Vec::with_capacity(cur.len() / 8);
+        while !cur.is_empty() {
+            ids.push(cur.read_u64::<T>()?);
+        }
+        Ok(Self { attr, ids })
+    }
+}
+
+/// `PERF_RECORD_HEADER_EVENT_TYPE`: The name of a tracepoint event. Only
+/// written by very old versions of perf.
+///
+/// ```pseudo-c
+/// struct {
+///     struct perf_event_header header;
+///     u64 event_id;
+///     char name[64];
+/// };
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderEventTypeRecord<'a> {
+    /// The tracepoint ID, as in the attr's `config`.
+    pub event_id: u64,
+    /// The tracepoint name, e.g. `sched:sched_switch`.
+    pub name: RawData<'a>,
+}
+
+impl<'a> HeaderEventTypeRecord<'a> {
+    pub fn parse<T: ByteOrder>(data: RawData<'a>) -> Result<Self, io::Error> {
+        let mut cur = data;
+        let event_id = cur.read_u64::<T>()?;
+        let name = cur.read_string().unwrap_or(cur);
+        Ok(Self { event_id, name })
+    }
+}
+
+/// `PERF_RECORD_HEADER_TRACING_DATA`: The tracing data in pipe mode, i.e.
+/// the contents of the `TRACING_DATA` feature section.
+///
+/// ```pseudo-c
+/// struct {
+///     struct perf_event_header header;
+///     u32 size;
+///     u32 pad;
+/// };
+/// // followed by `size` bytes of tracing data
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderTracingDataRecord<'a> {
+    /// The size of the tracing data, including padding.
+    pub size: u32,
+    /// The tracing data, which follows the record.
+    pub data: RawData<'a>,
+}
+
+impl<'a> HeaderTracingDataRecord<'a> {
+    pub fn parse<T: ByteOrder>(data: RawData<'a>) -> Result<Self, io::Error> {
+        let mut cur = data;
+        let size = cur.read_u32::<T>()?;
+        let _pad = cur.read_u32::<T>()?;
+        Ok(Self { size, data: cur })
+    }
+}
+
+/// An entry of a [`IdIndexRecord`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IdIndexEntry {
+    /// The event ID.
+    pub id: u64,
+    /// The index of the attr which the ID belongs to.
+    pub idx: u64,
+    /// The CPU of the event, or -1 if the event isn't per-CPU.
+    pub cpu: i32,
+    /// The thread of the event, or -1 if the event isn't per-thread.
+    pub tid: i32,
+}
+
+/// `PERF_RECORD_ID_INDEX`: Maps event IDs to attrs, CPUs and threads.
+///
+/// ```pseudo-c
+/// struct {
+///     struct perf_event_header header;
+///     u64 nr;
+///     struct { u64 id; u64 idx; u64 cpu; u64 tid; } entries[nr];
+///     // since perf 5.19, if the record is large enough:
+///     struct { u64 machine_pid; u64 vcpu; } entries_ext[nr];
+/// };
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct IdIndexRecord {
+    /// One entry per event ID.
+    pub entries: Vec<IdIndexEntry>,
+}
+
+impl IdIndexRecord {
+    pub fn parse<T: ByteOrder>(data: RawData) -> Result<Self, io::Error> {
+        let mut cur = data;
+        let nr = cur.read_u64::<T>()?;
+        let mut entries = Vec::with_capacity((nr as usize).min(cur.len() / 32));
+        for _ in 0..nr {
+            let id = cur.read_u64::<T>()?;
+            let idx = cur.read_u64::<T>()?;
+            let cpu = cur.read_u64::<T>()? as i32;
+            let tid = cur.read_u64::<T>()? as i32;
+            entries.push(IdIndexEntry { id, idx, cpu, tid });
+        }
+        Ok(Self { entries })
+    }
+}
+
+/// `PERF_RECORD_AUXTRACE_INFO`: Decoder parameters for hardware trace data.
+///
+/// ```pseudo-c
+/// struct {
+///     struct perf_event_header header;
+///     u32 type;
+///     u32 reserved;
+///     u64 priv[];
+/// };
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuxtraceInfoRecord<'a> {
+    /// The type of the hardware tracer, e.g. Intel PT or ARM CoreSight.
+    pub type_: u32,
+    /// Tracer-specific parameters.
+    pub priv_data: RawDataU64<'a>,
+}
+
+impl<'a> AuxtraceInfoRecord<'a> {
+    pub fn parse<T: ByteOrder>(data: RawData<'a>) -> Result<Self, io::Error> {
+        let mut cur = data;
+        let type_ = cur.read_u32::<T>()?;
+        let _reserved = cur.read_u32::<T>()?;
+        Ok(Self {
+            type_,
+            priv_data: RawDataU64::from_raw_data::<T>(cur),
+        })
+    }
+}
+
+/// `PERF_RECORD_AUXTRACE`: A chunk of hardware trace data, copied from the
+/// AUX area.
+///
+/// ```pseudo-c
+/// struct {
+///     struct perf_event_header header;
+///     u64 size;
+///     u64 offset;
+///     u64 reference;
+///     u32 idx;
+///     u32 tid;
+///     u32 cpu;
+///     u32 reserved;
+/// };
+/// // followed by `size` bytes of trace data
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuxtraceRecord<'a> {
+    /// The size of the trace data.
+    pub size: u64,
+    /// The offset of the trace data in the AUX area.
+    pub offset: u64,
+    /// A unique identifier for the trace data.
+    pub reference: u64,
+    /// The index of the mmap which the data came from.
+    pub idx: u32,
+    /// The thread which was traced, or -1 for per-CPU tracing.
+    pub tid: i32,
+    /// The CPU which was traced.
+    pub cpu: u32,
+    /// The trace data, which follows the record.
+    pub data: RawData<'a>,
+}
+
+impl<'a> AuxtraceRecord<'a> {
+    pub fn parse<T: ByteOrder>(data: RawData<'a>) -> Result<Self, io::Error> {
+        let mut cur = data;
+        let size = cur.read_u64::<T>()?;
+        let offset = cur.read_u64::<T>()?;
+        let reference = cur.read_u64::<T>()?;
+        let idx = cur.read_u32::<T>()?;
+        let tid = cur.read_i32::<T>()?;
+        let cpu = cur.read_u32::<T>()?;
+        let _reserved = cur.read_u32::<T>()?;
+        Ok(Self {
+            size,
+            offset,
+            reference,
+            idx,
+            tid,
+            cpu,
+            data: cur,
+        })
+    }
+}
+
+/// `PERF_RECORD_AUXTRACE_ERROR`: An error which occurred during hardware tracing.
+///
+/// ```pseudo-c
+/// struct {
+///     struct perf_event_header header;
+///     u32 type;
+///     u32 code;
+///     u32 cpu;
+///     u32 pid;
+///     u32 tid;
+///     u32 fmt;
+///     u64 ip;
+///     u64 time; // if fmt >= 1
+///     char msg[64];
+///     u32 machine_pid; // if fmt >= 2
+///     u32 vcpu; // if fmt >= 2
+/// };
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuxtraceErrorRecord<'a> {
+    /// The error type, `PERF_AUXTRACE_ERROR_ITRACE` (1) or `PERF_AUXTRACE_ERROR_MAX` (2).
+    pub type_: u32,
+    /// A decoder-specific error code.
+    pub code: u32,
+    /// The CPU on which the error occurred.
+    pub cpu: i32,
+    /// The process in which the error occurred.
+    pub pid: i32,
+    /// The thread in which the error occurred.
+    pub tid: i32,
+    /// The instruction address at which the error occurred.
+    pub ip: u64,
+    /// The timestamp of the error, if `fmt >= 1`.
+    pub time: Option<u64>,
+    /// The error message.
+    pub msg: RawData<'a>,
+    /// The pid of the virtual machine, if `fmt >= 2`.
+    pub machine_pid: Option<i32>,
+    /// The virtual CPU, if `fmt >= 2`.
+    pub vcpu: Option<i32>,
+}
+
+impl<'a> AuxtraceErrorRecord<'a> {
+    pub fn parse<T: ByteOrder>(data: RawData<'a>) -> Result<Self, io::Error> {
+        let mut cur = data;
+        let type_ = cur.read_u32::<T>()?;
+        let code = cur.read_u32::<T>()?;
+        let cpu = cur.read_i32::<T>()?;
+        let pid = cur.read_i32::<T>()?;
+        let tid = cur.read_i32::<T>()?;
+        let fmt = cur.read_u32::<T>()?;
+        let ip = cur.read_u64::<T>()?;
+        let time = if fmt >= 1 {
+            Some(cur.read_u64::<T>()?)
+        } else {
+            None
+        };
+        let mut msg = cur.split_off_prefix(64)?;
+        let msg = msg.read_string().unwrap_or(msg);
+        let (machine_pid, vcpu) = if fmt >= 2 {
+            (Some(cur.read_i32::<T>()?), Some(cur.read_i32::<T>()?))
+        } else {
+            (None, None)
+        };
+        Ok(Self {
+            type_,
+            code,
+            cpu,
+            pid,
+            tid,
+            ip,
+            time,
+            msg,
+            machine_pid,
+            vcpu,
+        })
+    }
+}
+
+/// `PERF_RECORD_THREAD_MAP`: The threads which `perf stat` counted.
+///
+/// ```pseudo-c
+/// struct {
+///     struct perf_event_header header;
+///     u64 nr;
+///     struct { u64 pid; char comm[16]; } entries[nr];
+/// };
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThreadMapRecord<'a> {
+    /// The pids (or tids) and their names.
+    pub entries: Vec<(i32, RawData<'a>)>,
+}
+
+impl<'a> ThreadMapRecord<'a> {
+    pub fn parse<T: ByteOrder>(data: RawData<'a>) -> Result<Self, io::Error> {
+        let mut cur = data;
+        let nr = cur.read_u64::<T>()?;
+        let mut entries = Vec::with_capacity((nr as usize).min(cur.len() / 24));
+        for _ in 0..nr {
+            let pid = cur.read_u64::<T>()? as i32;
+            let mut comm = cur.split_off_prefix(16)?;
+            let comm = comm.read_string().unwrap_or(comm);
+            entries.push((pid, comm));
+        }
+        Ok(Self { entries })
+    }
+}
+
+/// `PERF_RECORD_CPU_MAP`: The CPUs which `perf stat` counted.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CpuMapRecord {
+    /// The CPUs, in ascending order. -1 stands for "any CPU".
+    pub cpus: Vec<i32>,
+}
+
+impl CpuMapRecord {
+    pub fn parse<T: ByteOrder>(data: RawData) -> Result<Self, io::Error> {
+        let mut cur = data;
+        let cpus = parse_cpu_map_data::<T>(&mut cur)?;
+        Ok(Self { cpus })
+    }
+}
+
+/// Parse a `perf_record_cpu_map_data`, which is used in `PERF_RECORD_CPU_MAP`
+/// and `PERF_RECORD_EVENT_UPDATE`.
+///
+/// ```pseudo-c
+/// struct perf_record_cpu_map_data {
+///     u16 type;
+///     union {
+///         // PERF_CPU_MAP__CPUS
+///         struct { u16 nr; u16 cpu[]; } cpus_data;
+///         // PERF_CPU_MAP__MASK
+///         struct { u16 nr; u16 long_size; u32 mask[]; } mask32_data;
+///         struct { u16 nr; u16 long_size; u32 pad; u64 mask[]; } mask64_data;
+///         // PERF_CPU_MAP__RANGE_CPUS
+///         struct { u8 any_cpu; u8 pad; u16 start_cpu; u16 end_cpu; } range_cpu_data;
+///     };
+/// } __attribute__((packed));
+/// ```
+fn parse_cpu_map_data<T: ByteOrder>(cur: &mut RawData) -> Result<Vec<i32>, io::Error> {
+    const PERF_CPU_MAP_CPUS: u16 = 0;
+    const PERF_CPU_MAP_MASK: u16 = 1;
+    const PERF_CPU_MAP_RANGE_CPUS: u16 = 2;
+
+    let map_type = cur.read_u16::<T>()?;
+    let mut cpus = Vec::new();
+    match map_type {
+        PERF_CPU_MAP_CPUS => {
+            let nr = cur.read_u16::<T>()?;
+            for _ in 0..nr {
+                // (u16)-1 stands for "any CPU".
+                cpus.push(i32::from(cur.read_u16::<T>()? as i16));
+            }
+        }
+        PERF_CPU_MAP_MASK => {
+            let nr = cur.read_u16::<T>()?;
+            let long_size = cur.read_u16::<T>()?;
+            let bits_per_long = match long_size {
+                4 => 32,
+                8 => {
+                    let _pad = cur.read_u32::<T>()?;
+                    64
+                }
+                _ => return Err(io::ErrorKind::InvalidData.into()),
+            };
+            for i in 0..u32::from(nr) {
+                let mask = if long_size == 4 {
+                    u64::from(cur.read_u32::<T>()?)
+                } else {
+                    cur.read_u64::<T>()?
+                };
+                for bit in 0..bits_per_long {
+                    if mask & (1 << bit) != 0 {
+                        cpus.push((i * bits_per_long + bit) as i32);
+                    }
+                }
+            }
+        }
+        PERF_CPU_MAP_RANGE_CPUS => {
+            let any_cpu = cur.read_u8()?;
+            let _pad = cur.read_u8()?;
+            let start_cpu = cur.read_u16::<T>()?;
+            let end_cpu = cur.read_u16::<T>()?;
+            if any_cpu != 0 {
+                cpus.push(-1);
+            }
+            cpus.extend((start_cpu..=end_cpu).map(i32::from));
+        }
+        _ => return Err(io::ErrorKind::InvalidData.into()),
+    }
+    Ok(cpus)
+}
+
+/// `PERF_RECORD_STAT_CONFIG`: The configuration of `perf stat`, as tag-value pairs.
+///
+/// ```pseudo-c
+/// struct {
+///     struct perf_event_header header;
+///     u64 nr;
+///     struct { u64 tag; u64 val; } data[nr];
+/// };
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StatConfigRecord {
+    /// `(tag, value)` pairs. The tags are `PERF_STAT_CONFIG_TERM__AGGR_MODE` (1),
+    /// `PERF_STAT_CONFIG_TERM__INTERVAL` (2), `PERF_STAT_CONFIG_TERM__SCALE` (3)
+    /// and `PERF_STAT_CONFIG_TERM__AGGR_LEVEL` (4).
+    pub entries: Vec<(u64, u64)>,
+}
+
+impl StatConfigRecord {
+    pub fn parse<T: ByteOrder>(data: RawData) -> Result<Self, io::Error> {
+        let mut cur = data;
+        let nr = cur.read_u64::<T>()?;
+        let mut entries = Vec::with_capacity((nr as usize).min(cur.len() / 16));
+        for _ in 0..nr {
+            let tag = cur.read_u64::<T>()?;
+            let val = cur.read_u64::<T>()?;
+            entries.push((tag, val));
+        }
+        Ok(Self { entries })
+    }
+}
+
+/// `PERF_RECORD_STAT`: A counter value from `perf stat`.
+///
+/// ```pseudo-c
+/// struct {
+///     struct perf_event_header header;
+///     u64 id;
+///     u32 cpu;
+///     u32 thread;
+///     u64 val;
+///     u64 ena;
+///     u64 run;
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatRecord {
+    /// The event ID.
+    pub id: u64,
+    /// The index of the CPU in the [`CpuMapRecord`].
+    pub cpu: u32,
+    /// The index of the thread in the [`ThreadMapRecord`].
+    pub thread: u32,
+    /// The counter value.
+    pub value: u64,
+    /// The time during which the event was enabled.
+    pub time_enabled: u64,
+    /// The time during which the event was running on the PMU.
+    pub time_running: u64,
+}
+
+impl StatRecord {
+    pub fn parse<T: ByteOrder>(data: RawData) -> Result<Self, io::Error> {
+        let mut cur = data;
+        Ok(Self {
+            id: cur.read_u64::<T>()?,
+            cpu: cur.read_u32::<T>()?,
+            thread: cur.read_u32::<T>()?,
+            value: cur.read_u64::<T>()?,
+            time_enabled: cur.read_u64::<T>()?,
+            time_running: cur.read_u64::<T>()?,
+        })
+    }
+}
+
+/// `PERF_RECORD_STAT_ROUND`: The end of a round of `PERF_RECORD_STAT` records.
+///
+/// ```pseudo-c
+/// struct {
+///     struct perf_event_header header;
+///     u64 type; // PERF_STAT_ROUND_TYPE__INTERVAL (0) or PERF_STAT_ROUND_TYPE__FINAL (1)
+///     u64 time;
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct StatRoundRecord {
+    /// `PERF_STAT_ROUND_TYPE__INTERVAL` (0) or `PERF_STAT_ROUND_TYPE__FINAL` (1).
+    pub type_: u64,
+    /// The time since the start of `perf stat`, in nanoseconds.
+    pub time: u64,
+}
+
+impl StatRoundRecord {
+    pub fn parse<T: ByteOrder>(data: RawData) -> Result<Self, io::Error> {
+        let mut cur = data;
+        let type_ = cur.read_u64::<T>()?;
+        let time = cur.read_u64::<T>()?;
+        Ok(Self { type_, time })
+    }
+}
+
+/// The payload of an [`EventUpdateRecord`].
+#[derive(Debug, Clone, PartialEq)]
+pub enum EventUpdate<'a> {
+    /// `PERF_EVENT_UPDATE__UNIT`: The unit of the counter, e.g. `MiB`.
+    Unit(RawData<'a>),
+    /// `PERF_EVENT_UPDATE__SCALE`: The factor which the counter value is scaled by.
+    Scale(f64),
+    /// `PERF_EVENT_UPDATE__NAME`: The event name.
+    Name(RawData<'a>),
+    /// `PERF_EVENT_UPDATE__CPUS`: The CPUs which the event was opened on.
+    Cpus(Vec<i32>),
+    /// An unknown update type.
+    Unknown(u64, RawData<'a>),
+}
+
+/// `PERF_RECORD_EVENT_UPDATE`: Additional information about an event.
+///
+/// ```pseudo-c
+/// struct {
+///     struct perf_event_header header;
+///     u64 type;
+///     u64 id;
+///     union {
+///         double scale;
+///         char unit[];
+///         char name[];
+///         struct perf_record_cpu_map_data cpus;
+///     };
+/// };
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct EventUpdateRecord<'a> {
+    /// The event ID.
+    pub id: u64,
+    /// The information about the event.
+    pub update: EventUpdate<'a>,
+}
+
+impl<'a> EventUpdateRecord<'a> {
+    pub fn parse<T: ByteOrder>(data: RawData<'a>) -> Result<Self, io::Error> {
+        let mut cur = data;
+        let type_ = cur.read_u64::<T>()?;
+        let id = cur.read_u64::<T>()?;
+        let update = match type_ {
+            0 => EventUpdate::Unit(cur.read_string().unwrap_or(cur)),
+            1 => EventUpdate::Scale(f64::from_bits(cur.read_u64::<T>()?)),
+            2 => EventUpdate::Name(cur.read_string().unwrap_or(cur)),
+            3 => EventUpdate::Cpus(parse_cpu_map_data::<T>(&mut cur)?),
+            _ => EventUpdate::Unknown(type_, cur),
+        };
+        Ok(Self { id, update })
+    }
+}
+
+/// `PERF_RECORD_TIME_CONV`: The parameters for converting between TSC
+/// values and perf timestamps, from the [`PerfEventMmapPage`](crate::PerfEventMmapPage).
+///
+/// ```pseudo-c
+/// struct {
+///     struct perf_event_header header;
+///     u64 time_shift;
+///     u64 time_mult;
+///     u64 time_zero;
+///     // since perf 5.10:
+///     u64 time_cycles;
+///     u64 time_mask;
+///     u8 cap_user_time_zero;
+///     u8 cap_user_time_short;
+///     u8 reserved[6];
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeConvRecord {
+    /// See [`PerfEventMmapPage::time_shift`](crate::PerfEventMmapPage::time_shift).
+    pub time_shift: u64,
+    /// See [`PerfEventMmapPage::time_mult`](crate::PerfEventMmapPage::time_mult).
+    pub time_mult: u64,
+    /// See [`PerfEventMmapPage::time_zero`](crate::PerfEventMmapPage::time_zero).
+    pub time_zero: u64,
+    /// The extended fields, if present.
+    pub time_short: Option<TimeConvShort>,
+}
+
+/// The fields which were added to [`TimeConvRecord`] in perf 5.10.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TimeConvShort {
+    /// See [`PerfEventMmapPage::time_cycles`](crate::PerfEventMmapPage::time_cycles).
+    pub time_cycles: u64,
+    /// See [`PerfEventMmapPage::time_mask`](crate::PerfEventMmapPage::time_mask).
+    pub time_mask: u64,
+    /// Whether `time_zero` is valid.
+    pub cap_user_time_zero: bool,
+    /// Whether `time_cycles` and `time_mask` are valid.
+    pub cap_user_time_short: bool,
+}
+
+impl TimeConvRecord {
+    pub fn parse<T: ByteOrder>(data: RawData) -> Result<Self, io::Error> {
+        let mut cur = data;
+        let time_shift = cur.read_u64::<T>()?;
+        let time_mult = cur.read_u64::<T>()?;
+        let time_zero = cur.read_u64::<T>()?;
+        let time_short = if cur.len() >= 8 + 8 + 8 {
+            let time_cycles = cur.read_u64::<T>()?;
+            let time_mask = cur.read_u64::<T>()?;
+            let cap_user_time_zero = cur.read_u8()? != 0;
+            let cap_user_time_short = cur.read_u8()? != 0;
+            Some(TimeConvShort {
+                time_cycles,
+                time_mask,
+                cap_user_time_zero,
+                cap_user_time_short,
+            })
+        } else {
+            None
+        };
+        Ok(Self {
+            time_shift,
+            time_mult,
+            time_zero,
+            time_short,
+        })
+    }
+}
+
+/// `PERF_RECORD_HEADER_FEATURE`: A feature section, in pipe mode.
+///
+/// ```pseudo-c
+/// struct {
+///     struct perf_event_header header;
+///     u64 feat_id;
+///     char data[];
+/// };
+/// ```
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct HeaderFeatureRecord<'a> {
+    /// The feature which this record holds.
+    pub feature: PerfFeature,
+    /// The contents of the feature section.
+    pub data: RawData<'a>,
+}
+
+impl<'a> HeaderFeatureRecord<'a> {
+    pub fn parse<T: ByteOrder>(data: RawData<'a>) -> Result<Self, io::Error> {
+        let mut cur = data;
+        let feature = u32::try_from(cur.read_u64::<T>()?)
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid feature ID"))?;
+        Ok(Self {
+            feature: PerfFeature(feature),
+            data: cur,
+        })
+    }
+}
+
+/// `PERF_RECORD_COMPRESSED`: A batch of records, compressed with zstd.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompressedRecord<'a> {
+    /// The compressed data.
+    pub data: RawData<'a>,
+}
+
+#[cfg(test)]
+mod test {
+    use byteorder::LittleEndian;
+
+    use super::{EventUpdate, IdIndexEntry, UserRecord};
+    use crate::{
+        Endianness, PerfEventAttr, PerfEventType, RawData, RawUserRecord, RecordType,
+        SoftwareCounterType,
+    };
+
+    fn parse(record_type: RecordType, data: &[u8]) -> UserRecord<'_> {
+        RawUserRecord {
+            record_type,
+            misc: 0,
+            data: RawData::from(data),
+            endian: Endianness::LittleEndian,
+        }
+        .parse()
+        .unwrap()
+    }
+
+    #[test]
+    fn cpu_maps() {
+        // PERF_CPU_MAP__CPUS with cpus 0, 2 and -1
+        let data = [0, 0, 3, 0, 0, 0, 2, 0, 0xff, 0xff];
+        let UserRecord::CpuMap(map) = parse(RecordType::CPU_MAP, &data) else {
+            panic!("expected a CPU map");
+        };
+        assert_eq!(map.cpus, vec![0, 2, -1]);
+
+        // PERF_CPU_MAP__MASK with a 64-bit mask
+        let mut data = vec![1, 0, 1, 0, 8, 0, 0, 0, 0, 0];
+        data.extend_from_slice(&0b1010_0000_0000_0001u64.to_le_bytes());
+        let UserRecord::CpuMap(map) = parse(RecordType::CPU_MAP, &data) else {
+            panic!("expected a CPU map");
+        };
+        assert_eq!(map.cpus, vec![0, 13, 15]);
+
+        // PERF_CPU_MAP__MASK with two 32-bit masks
+        let mut data = vec![1, 0, 2, 0, 4, 0];
+        data.extend_from_slice(&0b110u32.to_le_bytes());
+        data.extend_from_slice(&0b1u32.to_le_bytes());
+        let UserRecord::CpuMap(map) = parse(RecordType::CPU_MAP, &data) else {
+            panic!("expected a CPU map");
+        };
+        assert_eq!(map.cpus, vec![1, 2, 32]);
+
+        // PERF_CPU_MAP__RANGE_CPUS with any_cpu set
+        let data = [2, 0, 1, 0, 2, 0, 3, 0];
+        let UserRecord::CpuMap(map) = parse(RecordType::CPU_MAP, &data) else {
+            panic!("expected a CPU map");
+        };
+        assert_eq!(map.cpus, vec![-1, 2, 3]);
+
+        // An unknown map type, and a mask with an unsupported long size
+        let raw = |data: &'static [u8]| RawUserRecord {
+            record_type: RecordType::CPU_MAP,
+            misc: 0,
+            data: RawData::from(data),
+            endian: Endianness::LittleEndian,
+        };
+        assert!(raw(&[3, 0, 0, 0]).parse().is_err());
+        assert!(raw(&[1, 0, 1, 0, 2, 0, 0, 0]).parse().is_err());
+
+        // An EVENT_UPDATE with a PERF_CPU_MAP__RANGE_CPUS map
+        let mut data = Vec::new();
+        data.extend_from_slice(&3u64.to_le_bytes());
+        data.extend_from_slice(&42u64.to_le_bytes());
+        data.extend_from_slice(&[2, 0, 0, 0, 4, 0, 6, 0]);
+        let UserRecord::EventUpdate(update) = parse(RecordType::EVENT_UPDATE, &data) else {
+            panic!("expected an event update");
+        };
+        assert_eq!(update.id, 42);
+        assert_eq!(update.update, EventUpdate::Cpus(vec![4, 5, 6]));
+    }
+
+    #[test]
+    fn id_index() {
+        let mut data = Vec::new();
+        for value in [2u64, 100, 0, 3, u64::MAX, 101, 1, u64::MAX, 1234] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        let UserRecord::IdIndex(index) = parse(RecordType::ID_INDEX, &data) else {
+            panic!("expected an ID index");
+        };
+        assert_eq!(
+            index.entries,
+            vec![
+                IdIndexEntry {
+                    id: 100,
+                    idx: 0,
+                    cpu: 3,
+                    tid: -1
+                },
+                IdIndexEntry {
+                    id: 101,
+                    idx: 1,
+                    cpu: -1,
+                    tid: 1234
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn thread_map() {
+        let mut data = Vec::new();
+        data.extend_from_slice(&2u64.to_le_bytes());
+        data.extend_from_slice(&1234u64.to_le_bytes());
+        data.extend_from_slice(b"bash\0\0\0\0\0\0\0\0\0\0\0\0");
+        data.extend_from_slice(&1235u64.to_le_bytes());
+        data.extend_from_slice(b"a-full-comm-name");
+        let UserRecord::ThreadMap(map) = parse(RecordType::THREAD_MAP, &data) else {
+            panic!("expected a thread map");
+        };
+        assert_eq!(
+            map.entries,
+            vec![
+                (1234, RawData::Single(b"bash")),
+                (1235, RawData::Single(b"a-full-comm-name"))
+            ]
+        );
+    }
+
+    #[test]
+    fn stat_config() {
+        let mut data = Vec::new();
+        for value in [2u64, 1, 3, 2, 1000] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        let UserRecord::StatConfig(config) = parse(RecordType::STAT_CONFIG, &data) else {
+            panic!("expected a stat config");
+        };
+        assert_eq!(config.entries, vec![(1, 3), (2, 1000)]);
+    }
+
+    #[test]
+    fn event_update() {
+        let update = |type_: u64, payload: &[u8]| {
+            let mut data = Vec::new();
+            data.extend_from_slice(&type_.to_le_bytes());
+            data.extend_from_slice(&7u64.to_le_bytes());
+            data.extend_from_slice(payload);
+            data
+        };
+
+        let data = update(0, b"MiB\0\0\0\0\0");
+        let UserRecord::EventUpdate(record) = parse(RecordType::EVENT_UPDATE, &data) else {
+            panic!("expected an event update");
+        };
+        assert_eq!(record.id, 7);
+        assert_eq!(record.update, EventUpdate::Unit(RawData::Single(b"MiB")));
+
+        let data = update(1, &0.5f64.to_bits().to_le_bytes());
+        let UserRecord::EventUpdate(record) = parse(RecordType::EVENT_UPDATE, &data) else {
+            panic!("expected an event update");
+        };
+        assert_eq!(record.update, EventUpdate::Scale(0.5));
+
+        let data = update(2, b"cycles:u\0\0\0\0\0\0\0\0");
+        let UserRecord::EventUpdate(record) = parse(RecordType::EVENT_UPDATE, &data) else {
+            panic!("expected an event update");
+        };
+        assert_eq!(
+            record.update,
+            EventUpdate::Name(RawData::Single(b"cycles:u"))
+        );
+
+        let data = update(9, &[1, 2, 3]);
+        let UserRecord::EventUpdate(record) = parse(RecordType::EVENT_UPDATE, &data) else {
+            panic!("expected an event update");
+        };
+        assert_eq!(
+            record.update,
+            EventUpdate::Unknown(9, RawData::Single(&[1, 2, 3]))
+        );
+    }
+
+    #[test]
+    fn auxtrace() {
+        let mut data = Vec::new();
+        for value in [4u64, 0x1000, 0xabcd] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        for value in [2u32, u32::MAX, 5, 0] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(&[0xde, 0xad, 0xbe, 0xef]);
+        let UserRecord::Auxtrace(record) = parse(RecordType::AUXTRACE, &data) else {
+            panic!("expected an auxtrace record");
+        };
+        assert_eq!(record.size, 4);
+        assert_eq!(record.offset, 0x1000);
+        assert_eq!(record.reference, 0xabcd);
+        assert_eq!(record.idx, 2);
+        assert_eq!(record.tid, -1);
+        assert_eq!(record.cpu, 5);
+        assert_eq!(record.data.as_slice(), &[0xde, 0xad, 0xbe, 0xef][..]);
+    }
+
+    #[test]
+    fn compressed() {
+        let data = [0x28, 0xb5, 0x2f, 0xfd, 1, 2, 3];
+        let UserRecord::Compressed(record) = parse(RecordType::COMPRESSED, &data) else {
+            panic!("expected a compressed record");
+        };
+        assert_eq!(record.data, RawData::Single(&data));
+    }
+
+    #[test]
+    fn header_attr_and_time_conv() {
+        let attr = PerfEventAttr::new(PerfEventType::Software(SoftwareCounterType::TaskClock));
+        let mut data = Vec::new();
+        attr.write::<_, LittleEndian>(&mut data).unwrap();
+        data.extend_from_slice(&11u64.to_le_bytes());
+        data.extend_from_slice(&12u64.to_le_bytes());
+        let UserRecord::HeaderAttr(record) = parse(RecordType::HEADER_ATTR, &data) else {
+            panic!("expected a header attr record");
+        };
+        assert_eq!(record.attr.type_, attr.type_);
+        assert_eq!(record.ids, vec![11, 12]);
+
+        // Without and with the fields which were added in perf 5.10
+        let mut data = Vec::new();
+        for value in [10u64, 500, 123] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        let UserRecord::TimeConv(record) = parse(RecordType::TIME_CONV, &data) else {
+            panic!("expected a time conv record");
+        };
+        assert_eq!((record.time_shift, record.time_mult), (10, 500));
+        assert_eq!(record.time_short, None);
+        for value in [456u64, u64::MAX] {
+            data.extend_from_slice(&value.to_le_bytes());
+        }
+        data.extend_from_slice(&[1, 0, 0, 0, 0, 0, 0, 0]);
+        let UserRecord::TimeConv(record) = parse(RecordType::TIME_CONV, &data) else {
+            panic!("expected a time conv record");
+        };
+        let time_short = record.time_short.unwrap();
+        assert_eq!(time_short.time_cycles, 456);
+        assert!(time_short.cap_user_time_zero);
+        assert!(!time_short.cap_user_time_short);
+    }
+
+    #[test]
+    fn unknown_and_finished_round() {
+        assert!(matches!(
+            parse(RecordType::FINISHED_ROUND, &[]),
+            UserRecord::FinishedRound
+        ));
+        assert!(matches!(
+            parse(RecordType(200), &[1, 2, 3]),
+            UserRecord::Raw(_)
+        ));
+        assert_eq!(format!("{:?}", RecordType::TIME_CONV), "TIME_CONV");
+    }
+}
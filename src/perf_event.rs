@@ -1,8 +1,8 @@
 use crate::constants::*;
 use crate::types::*;
-use byteorder::{ByteOrder, ReadBytesExt};
+use byteorder::{ByteOrder, ReadBytesExt, WriteBytesExt};
 use std::io;
-use std::io::Read;
+use std::io::{Read, Write};
 use std::num::NonZeroU64;
 
 /// `perf_event_header`
@@ -85,6 +85,50 @@ pub struct PerfEventAttr {
 }
 
 impl PerfEventAttr {
+    /// Create an attr for an event of type `type_`, with all other fields
+    /// zeroed: no sampling, no flags, no extra sample information.
+    pub fn new(type_: PerfEventType) -> Self {
+        Self {
+            type_,
+            sampling_policy: SamplingPolicy::NoSampling,
+            sample_format: SampleFormat::empty(),
+            read_format: ReadFormat::empty(),
+            flags: AttrFlags::empty(),
+            wakeup_policy: WakeupPolicy::EventCount(0),
+            branch_sample_format: BranchSampleFormat::empty(),
+            sample_regs_user: 0,
+            sample_stack_user: 0,
+            clock: PerfClock::Default,
+            sample_regs_intr: 0,
+            aux_watermark: 0,
+            sample_max_stack: 0,
+            aux_sample_size: 0,
+            sig_data: 0,
+        }
+    }
+
+    /// Create an attr for a watchpoint which traps writes to the `len` bytes
+    /// at `addr`: every write produces a sample with the instruction pointer,
+    /// thread, time and address, and wakes up the reader.
+    ///
+    /// Kernel and hypervisor writes are excluded, so that the watchpoint can
+    /// be opened without privileges. Returns an error if this machine's kernel
+    /// would reject the breakpoint, see [`PerfEventType::validate`].
+    pub fn watchpoint(addr: u64, len: HwBreakpointLen) -> Result<Self, BreakpointError> {
+        let type_ = PerfEventType::Breakpoint(HwBreakpointType::W, HwBreakpointAddr(addr), len);
+        type_.validate()?;
+        Ok(Self {
+            sampling_policy: SamplingPolicy::Period(NonZeroU64::MIN),
+            sample_format: SampleFormat::IP
+                | SampleFormat::TID
+                | SampleFormat::TIME
+                | SampleFormat::ADDR,
+            flags: AttrFlags::EXCLUDE_KERNEL | AttrFlags::EXCLUDE_HV,
+            wakeup_policy: WakeupPolicy::EventCount(1),
+            ..Self::new(type_)
+        })
+    }
+
     /// Parse from a reader. On success, this returns the parsed attribute and
     /// the number of bytes that were read from the reader. This matches the self-reported
     /// size in the attribute.
@@ -224,10 +268,68 @@ impl PerfEventAttr {
 
         Ok((attr, size.into()))
     }
+
+    /// Write the attr in the `PERF_ATTR_SIZE_VER7` layout, i.e. in the form
+    /// that is expected by `perf_event_open` and that can be read back with
+    /// [`PerfEventAttr::parse`].
+    ///
+    /// The `FREQ`, `WATERMARK` and `USE_CLOCKID` flags are derived from
+    /// `sampling_policy`, `wakeup_policy` and `clock`, respectively.
+    pub fn write<W: Write, T: ByteOrder>(&self, mut writer: W) -> Result<(), std::io::Error> {
+        let (type_, bp_type, config, config1, config2) = self.type_.to_raw();
+        let mut flags =
+            self.flags - (AttrFlags::FREQ | AttrFlags::WATERMARK | AttrFlags::USE_CLOCKID);
+        let sampling_period_or_frequency = match self.sampling_policy {
+            SamplingPolicy::NoSampling => 0,
+            SamplingPolicy::Period(period) => period.get(),
+            SamplingPolicy::Frequency(frequency) => {
+                flags |= AttrFlags::FREQ;
+                frequency
+            }
+        };
+        let wakeup_events_or_watermark = match self.wakeup_policy {
+            WakeupPolicy::EventCount(count) => count,
+            WakeupPolicy::Watermark(watermark) => {
+                flags |= AttrFlags::WATERMARK;
+                watermark
+            }
+        };
+        let clockid = match self.clock {
+            PerfClock::Default => 0,
+            PerfClock::ClockId(clockid) => {
+                flags |= AttrFlags::USE_CLOCKID;
+                clockid.to_u32()
+            }
+        };
+
+        writer.write_u32::<T>(type_)?;
+        writer.write_u32::<T>(PERF_ATTR_SIZE_VER7)?;
+        writer.write_u64::<T>(config)?;
+        writer.write_u64::<T>(sampling_period_or_frequency)?;
+        writer.write_u64::<T>(self.sample_format.bits())?;
+        writer.write_u64::<T>(self.read_format.bits())?;
+        writer.write_u64::<T>(flags.bits())?;
+        writer.write_u32::<T>(wakeup_events_or_watermark)?;
+        writer.write_u32::<T>(bp_type)?;
+        writer.write_u64::<T>(config1)?;
+        writer.write_u64::<T>(config2)?;
+        writer.write_u64::<T>(self.branch_sample_format.bits())?;
+        writer.write_u64::<T>(self.sample_regs_user)?;
+        writer.write_u32::<T>(self.sample_stack_user)?;
+        writer.write_u32::<T>(clockid)?;
+        writer.write_u64::<T>(self.sample_regs_intr)?;
+        writer.write_u32::<T>(self.aux_watermark)?;
+        writer.write_u16::<T>(self.sample_max_stack)?;
+        writer.write_u16::<T>(0)?; // __reserved_2
+        writer.write_u32::<T>(self.aux_sample_size)?;
+        writer.write_u32::<T>(0)?; // __reserved_3
+        writer.write_u64::<T>(self.sig_data)?;
+        Ok(())
+    }
 }
 
 /// The type of perf event
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum PerfEventType {
     /// A hardware perf event. (`PERF_TYPE_HARDWARE`)
     Hardware(HardwareEventId, PmuTypeId),
@@ -266,7 +368,8 @@ pub enum PerfEventType {
     ///
     /// Acceptable values for each of `config`, `config1` and `config2`
     /// parameters are defined by corresponding entries in
-    /// `/sys/bus/event_source/devices/<pmu>/format/*`.
+    /// `/sys/bus/event_source/devices/<pmu>/format/*`. [`PmuRegistry`](crate::PmuRegistry)
+    /// reads these and decodes the config into named fields.
     ///
     /// From the `perf_event_open` man page:
     /// > Since Linux 2.6.38, perf_event_open() can support multiple PMUs.  To
@@ -303,6 +406,8 @@ pub enum PerfEventType {
     ///     __u64 config2; /* extension of config1 */
     /// };
     /// ```
+    ///
+    /// [`ProbeEvent`](crate::ProbeEvent) decodes and creates these events.
     DynamicPmu(u32, u64, u64, u64),
 }
 
@@ -312,7 +417,10 @@ pub enum PerfEventType {
 /// or both. If the PMU type ID is zero, both "atom" and "core" are observed.
 /// To observe just one of them, the PMU type ID needs to be set to the value of
 /// `/sys/devices/cpu_atom/type` or of `/sys/devices/cpu_core/type`.
-#[derive(Debug, Clone, Copy)]
+///
+/// [`PmuRegistry::resolve_pmu_type`](crate::PmuRegistry::resolve_pmu_type)
+/// finds the PMU, and with it the CPUs, for a PMU type ID.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct PmuTypeId(pub u32);
 
 /// The address of the breakpoint.
@@ -320,16 +428,96 @@ pub struct PmuTypeId(pub u32);
 /// For execution breakpoints, this is the memory address of the instruction
 /// of interest; for read and write breakpoints, it is the memory address of
 /// the memory location of interest.
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub struct HwBreakpointAddr(pub u64);
 
 /// The length of the breakpoint being measured.
 ///
-/// Options are `HW_BREAKPOINT_LEN_1`, `HW_BREAKPOINT_LEN_2`,
-/// `HW_BREAKPOINT_LEN_4`, and `HW_BREAKPOINT_LEN_8`.  For an
-/// execution breakpoint, set this to sizeof(long).
-#[derive(Debug, Clone, Copy)]
-pub struct HwBreakpointLen(pub u64);
+/// For an execution breakpoint, this is sizeof(long), see
+/// [`HwBreakpointLen::LONG`]. For read and write breakpoints, the address
+/// needs to be aligned to the length on x86, which only supports lengths of
+/// 1, 2, 4 and 8. Other architectures, like arm64, support every length from
+/// 1 to 8.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum HwBreakpointLen {
+    /// `HW_BREAKPOINT_LEN_1`
+    Len1,
+    /// `HW_BREAKPOINT_LEN_2`
+    Len2,
+    /// `HW_BREAKPOINT_LEN_3`
+    Len3,
+    /// `HW_BREAKPOINT_LEN_4`
+    Len4,
+    /// `HW_BREAKPOINT_LEN_5`
+    Len5,
+    /// `HW_BREAKPOINT_LEN_6`
+    Len6,
+    /// `HW_BREAKPOINT_LEN_7`
+    Len7,
+    /// `HW_BREAKPOINT_LEN_8`
+    Len8,
+    /// A length which the kernel doesn't support.
+    Other(u64),
+}
+
+impl HwBreakpointLen {
+    /// The length for execution breakpoints on this machine: sizeof(long).
+    #[cfg(target_pointer_width = "64")]
+    pub const LONG: Self = Self::Len8;
+    /// The length for execution breakpoints on this machine: sizeof(long).
+    #[cfg(not(target_pointer_width = "64"))]
+    pub const LONG: Self = Self::Len4;
+
+    /// Parse the `bp_len` field.
+    pub fn parse(bp_len: u64) -> Self {
+        match bp_len {
+            HW_BREAKPOINT_LEN_1 => Self::Len1,
+            HW_BREAKPOINT_LEN_2 => Self::Len2,
+            HW_BREAKPOINT_LEN_3 => Self::Len3,
+            HW_BREAKPOINT_LEN_4 => Self::Len4,
+            HW_BREAKPOINT_LEN_5 => Self::Len5,
+            HW_BREAKPOINT_LEN_6 => Self::Len6,
+            HW_BREAKPOINT_LEN_7 => Self::Len7,
+            HW_BREAKPOINT_LEN_8 => Self::Len8,
+            other => Self::Other(other),
+        }
+    }
+
+    /// The length in bytes, i.e. the value of the `bp_len` field.
+    pub fn bytes(self) -> u64 {
+        match self {
+            Self::Len1 => HW_BREAKPOINT_LEN_1,
+            Self::Len2 => HW_BREAKPOINT_LEN_2,
+            Self::Len3 => HW_BREAKPOINT_LEN_3,
+            Self::Len4 => HW_BREAKPOINT_LEN_4,
+            Self::Len5 => HW_BREAKPOINT_LEN_5,
+            Self::Len6 => HW_BREAKPOINT_LEN_6,
+            Self::Len7 => HW_BREAKPOINT_LEN_7,
+            Self::Len8 => HW_BREAKPOINT_LEN_8,
+            Self::Other(len) => len,
+        }
+    }
+}
+
+/// A breakpoint which the kernel would refuse to open, see
+/// [`PerfEventType::validate`].
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum BreakpointError {
+    /// The type is empty, has unknown bits, or combines execution with read
+    /// or write access.
+    #[error("Invalid breakpoint type {0:#x}")]
+    InvalidType(u32),
+
+    /// The length isn't supported for this type on this architecture.
+    #[error("Invalid breakpoint length {0}")]
+    InvalidLength(u64),
+
+    /// The address of a read or write breakpoint isn't aligned to its length,
+    /// which is required on x86.
+    #[error("Breakpoint address {addr:#x} is not aligned to its length {len}")]
+    UnalignedAddress { addr: u64, len: u64 },
+}
 
 impl PerfEventType {
     pub fn parse(
@@ -370,17 +558,158 @@ impl PerfEventType {
                 )
             }
             PERF_TYPE_BREAKPOINT => {
-                let bp_type = HwBreakpointType::from_bits_truncate(bp_type);
-                Self::Breakpoint(bp_type, HwBreakpointAddr(config1), HwBreakpointLen(config2))
+                // Recorded attrs are kept as they are, even if this machine's
+                // kernel wouldn't accept them. See `validate`.
+                let bp_type = HwBreakpointType::from_bits_retain(bp_type);
+                let len = HwBreakpointLen::parse(config2);
+                Self::Breakpoint(bp_type, HwBreakpointAddr(config1), len)
             }
             _ => Self::DynamicPmu(type_, config, config1, config2),
             // PERF_TYPE_RAW is handled as part of DynamicPmu.
         };
         Some(t)
     }
+
+    /// Check that this machine's kernel would accept this event type. This
+    /// only checks breakpoints: their type has to be read, write, read and
+    /// write, or execute, and their length has to be between 1 and 8. On x86,
+    /// data breakpoints need a length of 1, 2, 4 or 8 and an address which is
+    /// aligned to it, and execution breakpoints need a length of
+    /// [`HwBreakpointLen::LONG`].
+    ///
+    /// [`PerfEventType::parse`] doesn't do these checks, so that attrs which
+    /// were recorded on other architectures can be read.
+    pub fn validate(&self) -> Result<(), BreakpointError> {
+        let Self::Breakpoint(bp_type, HwBreakpointAddr(addr), len) = *self else {
+            return Ok(());
+        };
+        if !matches!(
+            bp_type,
+            HwBreakpointType::R | HwBreakpointType::W | HwBreakpointType::RW | HwBreakpointType::X
+        ) {
+            return Err(BreakpointError::InvalidType(bp_type.bits()));
+        }
+        let len = len.bytes();
+        if !(HW_BREAKPOINT_LEN_1..=HW_BREAKPOINT_LEN_8).contains(&len) {
+            return Err(BreakpointError::InvalidLength(len));
+        }
+        if cfg!(any(target_arch = "x86", target_arch = "x86_64")) {
+            if bp_type == HwBreakpointType::X {
+                if len != HwBreakpointLen::LONG.bytes() {
+                    return Err(BreakpointError::InvalidLength(len));
+                }
+            } else if !len.is_power_of_two() {
+                return Err(BreakpointError::InvalidLength(len));
+            } else if addr % len != 0 {
+                return Err(BreakpointError::UnalignedAddress { addr, len });
+            }
+        }
+        Ok(())
+    }
+
+    /// The PMU type ID of hardware and hardware cache events.
+    pub fn pmu_type_id(&self) -> Option<PmuTypeId> {
+        match *self {
+            Self::Hardware(_, pmu_type) | Self::HwCache(.., pmu_type) => Some(pmu_type),
+            _ => None,
+        }
+    }
+
+    /// This event with a different PMU type ID. Other event types than
+    /// hardware and hardware cache events are returned unchanged.
+    pub fn with_pmu_type_id(&self, pmu_type_id: PmuTypeId) -> Self {
+        match *self {
+            Self::Hardware(id, _) => Self::Hardware(id, pmu_type_id),
+            Self::HwCache(cache_id, op, result, _) => {
+                Self::HwCache(cache_id, op, result, pmu_type_id)
+            }
+            other => other,
+        }
+    }
+
+    /// A key for merging the counts of the per-PMU variants of an event on
+    /// hybrid systems, e.g. of `cpu_core/cycles/` and `cpu_atom/cycles/`:
+    /// the event with `PmuTypeId(0)`.
+    pub fn hybrid_merge_key(&self) -> Self {
+        self.with_pmu_type_id(PmuTypeId(0))
+    }
+
+    /// The inverse of [`PerfEventType::parse`]: Encodes the event into the
+    /// `perf_event_attr` fields `(type, bp_type, config, config1, config2)`.
+    ///
+    /// For breakpoints, `config1` is the address and `config2` the length.
+    pub fn to_raw(&self) -> (u32, u32, u64, u64, u64) {
+        match *self {
+            Self::Hardware(id, pmu_type) => {
+                let id = match id {
+                    HardwareEventId::CpuCycles => PERF_COUNT_HW_CPU_CYCLES,
+                    HardwareEventId::Instructions => PERF_COUNT_HW_INSTRUCTIONS,
+                    HardwareEventId::CacheReferences => PERF_COUNT_HW_CACHE_REFERENCES,
+                    HardwareEventId::CacheMisses => PERF_COUNT_HW_CACHE_MISSES,
+                    HardwareEventId::BranchInstructions => PERF_COUNT_HW_BRANCH_INSTRUCTIONS,
+                    HardwareEventId::BranchMisses => PERF_COUNT_HW_BRANCH_MISSES,
+                    HardwareEventId::BusCycles => PERF_COUNT_HW_BUS_CYCLES,
+                    HardwareEventId::StalledCyclesFrontend => PERF_COUNT_HW_STALLED_CYCLES_FRONTEND,
+                    HardwareEventId::StalledCyclesBackend => PERF_COUNT_HW_STALLED_CYCLES_BACKEND,
+                    HardwareEventId::RefCpuCycles => PERF_COUNT_HW_REF_CPU_CYCLES,
+                };
+                let config = u64::from(id) | (u64::from(pmu_type.0) << 32);
+                (PERF_TYPE_HARDWARE, 0, config, 0, 0)
+            }
+            Self::Software(counter_type) => {
+                let config = match counter_type {
+                    SoftwareCounterType::CpuClock => PERF_COUNT_SW_CPU_CLOCK,
+                    SoftwareCounterType::TaskClock => PERF_COUNT_SW_TASK_CLOCK,
+                    SoftwareCounterType::PageFaults => PERF_COUNT_SW_PAGE_FAULTS,
+                    SoftwareCounterType::ContextSwitches => PERF_COUNT_SW_CONTEXT_SWITCHES,
+                    SoftwareCounterType::CpuMigrations => PERF_COUNT_SW_CPU_MIGRATIONS,
+                    SoftwareCounterType::PageFaultsMin => PERF_COUNT_SW_PAGE_FAULTS_MIN,
+                    SoftwareCounterType::PageFaultsMaj => PERF_COUNT_SW_PAGE_FAULTS_MAJ,
+                    SoftwareCounterType::AlignmentFaults => PERF_COUNT_SW_ALIGNMENT_FAULTS,
+                    SoftwareCounterType::EmulationFaults => PERF_COUNT_SW_EMULATION_FAULTS,
+                    SoftwareCounterType::Dummy => PERF_COUNT_SW_DUMMY,
+                    SoftwareCounterType::BpfOutput => PERF_COUNT_SW_BPF_OUTPUT,
+                    SoftwareCounterType::CgroupSwitches => PERF_COUNT_SW_CGROUP_SWITCHES,
+                };
+                (PERF_TYPE_SOFTWARE, 0, config, 0, 0)
+            }
+            Self::Tracepoint(id) => (PERF_TYPE_TRACEPOINT, 0, id, 0, 0),
+            Self::HwCache(cache_id, cache_op, cache_op_result, pmu_type) => {
+                let cache_id = match cache_id {
+                    HardwareCacheId::L1d => PERF_COUNT_HW_CACHE_L1D,
+                    HardwareCacheId::L1i => PERF_COUNT_HW_CACHE_L1I,
+                    HardwareCacheId::Ll => PERF_COUNT_HW_CACHE_LL,
+                    HardwareCacheId::Dtlb => PERF_COUNT_HW_CACHE_DTLB,
+                    HardwareCacheId::Itlb => PERF_COUNT_HW_CACHE_ITLB,
+                    HardwareCacheId::Bpu => PERF_COUNT_HW_CACHE_BPU,
+                    HardwareCacheId::Node => PERF_COUNT_HW_CACHE_NODE,
+                };
+                let cache_op = match cache_op {
+                    HardwareCacheOp::Read => PERF_COUNT_HW_CACHE_OP_READ,
+                    HardwareCacheOp::Write => PERF_COUNT_HW_CACHE_OP_WRITE,
+                    HardwareCacheOp::Prefetch => PERF_COUNT_HW_CACHE_OP_PREFETCH,
+                };
+                let cache_op_result = match cache_op_result {
+                    HardwareCacheOpResult::Access => PERF_COUNT_HW_CACHE_RESULT_ACCESS,
+                    HardwareCacheOpResult::Miss => PERF_COUNT_HW_CACHE_RESULT_MISS,
+                };
+                let config = u64::from(cache_id)
+                    | (u64::from(cache_op) << 8)
+                    | (u64::from(cache_op_result) << 16)
+                    | (u64::from(pmu_type.0) << 32);
+                (PERF_TYPE_HW_CACHE, 0, config, 0, 0)
+            }
+            Self::Breakpoint(bp_type, addr, len) => {
+                (PERF_TYPE_BREAKPOINT, bp_type.bits(), 0, addr.0, len.bytes())
+            }
+            Self::DynamicPmu(type_, config, config1, config2) => {
+                (type_, 0, config, config1, config2)
+            }
+        }
+    }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[non_exhaustive]
 pub enum HardwareEventId {
     /// `PERF_COUNT_HW_CPU_CYCLES`
@@ -424,7 +753,7 @@ impl HardwareEventId {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[non_exhaustive]
 pub enum SoftwareCounterType {
     /// `PERF_COUNT_SW_CPU_CLOCK`
@@ -474,7 +803,7 @@ impl SoftwareCounterType {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 #[non_exhaustive]
 pub enum HardwareCacheId {
     /// `PERF_COUNT_HW_CACHE_L1D`
@@ -509,7 +838,7 @@ impl HardwareCacheId {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum HardwareCacheOp {
     /// `PERF_COUNT_HW_CACHE_OP_READ`
     Read,
@@ -530,7 +859,7 @@ impl HardwareCacheOp {
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
 pub enum HardwareCacheOpResult {
     /// `PERF_COUNT_HW_CACHE_RESULT_ACCESS`
     Access,
@@ -627,3 +956,181 @@ pub enum PerfClock {
     /// A specific clock.
     ClockId(ClockId),
 }
+
+#[cfg(test)]
+mod test {
+    use super::*;
+    use byteorder::LittleEndian;
+
+    /// Check that `parse` and `to_raw` are inverses for these raw fields, and
+    /// return the parsed type.
+    fn round_trip(
+        type_: u32,
+        bp_type: u32,
+        config: u64,
+        config1: u64,
+        config2: u64,
+    ) -> Option<PerfEventType> {
+        let parsed = PerfEventType::parse(type_, bp_type, config, config1, config2)?;
+        assert_eq!(parsed.to_raw(), (type_, bp_type, config, config1, config2));
+        let (type_, bp_type, config, config1, config2) = parsed.to_raw();
+        assert_eq!(
+            PerfEventType::parse(type_, bp_type, config, config1, config2),
+            Some(parsed)
+        );
+        Some(parsed)
+    }
+
+    #[test]
+    fn to_raw_round_trips() {
+        // Every value which parses round-trips, so every variant of the ID
+        // enums is covered, including ones which are added later.
+        for pmu_type in [0u64, 8, 10] {
+            let hardware_count = (0..=u8::MAX)
+                .filter_map(|id| {
+                    round_trip(
+                        PERF_TYPE_HARDWARE,
+                        0,
+                        u64::from(id) | (pmu_type << 32),
+                        0,
+                        0,
+                    )
+                })
+                .count();
+            assert_eq!(hardware_count, 10);
+
+            let mut cache_count = 0;
+            for cache_id in 0..=u8::MAX {
+                for op in 0..=u8::MAX {
+                    for result in 0..4 {
+                        let config = u64::from(cache_id)
+                            | (u64::from(op) << 8)
+                            | (result << 16)
+                            | (pmu_type << 32);
+                        if round_trip(PERF_TYPE_HW_CACHE, 0, config, 0, 0).is_some() {
+                            cache_count += 1;
+                        }
+                    }
+                }
+            }
+            assert_eq!(cache_count, 7 * 3 * 2);
+        }
+
+        let software_count = (0..=u64::from(u8::MAX))
+            .filter_map(|config| round_trip(PERF_TYPE_SOFTWARE, 0, config, 0, 0))
+            .count();
+        assert_eq!(software_count, 12);
+
+        assert_eq!(
+            round_trip(PERF_TYPE_TRACEPOINT, 0, 316, 0, 0),
+            Some(PerfEventType::Tracepoint(316))
+        );
+        for bp_type in [
+            HwBreakpointType::R,
+            HwBreakpointType::W,
+            HwBreakpointType::RW,
+            HwBreakpointType::X,
+        ] {
+            assert!(round_trip(PERF_TYPE_BREAKPOINT, bp_type.bits(), 0, 0x1000, 8).is_some());
+        }
+        assert_eq!(
+            round_trip(PERF_TYPE_RAW, 0, 0x1a8, 1, 2),
+            Some(PerfEventType::DynamicPmu(PERF_TYPE_RAW, 0x1a8, 1, 2))
+        );
+        assert!(round_trip(9, 0, 0x1, 0, 0x10).is_some());
+    }
+
+    #[test]
+    fn breakpoints() {
+        let breakpoint = |bp_type: HwBreakpointType, addr, len| {
+            round_trip(PERF_TYPE_BREAKPOINT, bp_type.bits(), 0, addr, len).unwrap()
+        };
+        let is_x86 = cfg!(any(target_arch = "x86", target_arch = "x86_64"));
+
+        // Every type and length is decoded, and checked by `validate`.
+        for len in 1..=8 {
+            let type_ = breakpoint(HwBreakpointType::W, 0x1000, len);
+            let valid = !is_x86 || len.is_power_of_two();
+            assert_eq!(type_.validate().is_ok(), valid, "length {len}");
+        }
+        assert_eq!(
+            breakpoint(HwBreakpointType::W, 0x1000, 3),
+            PerfEventType::Breakpoint(
+                HwBreakpointType::W,
+                HwBreakpointAddr(0x1000),
+                HwBreakpointLen::Len3
+            )
+        );
+        for len in [0, 9, 16] {
+            let type_ = breakpoint(HwBreakpointType::RW, 0x1000, len);
+            assert_eq!(
+                type_,
+                PerfEventType::Breakpoint(
+                    HwBreakpointType::RW,
+                    HwBreakpointAddr(0x1000),
+                    HwBreakpointLen::Other(len)
+                )
+            );
+            assert_eq!(type_.validate(), Err(BreakpointError::InvalidLength(len)));
+        }
+        for bp_type in [
+            HwBreakpointType::EMPTY,
+            HwBreakpointType::R | HwBreakpointType::X,
+            HwBreakpointType::INVALID,
+            HwBreakpointType::from_bits_retain(8),
+        ] {
+            let type_ = breakpoint(bp_type, 0x1000, 8);
+            assert_eq!(
+                type_.validate(),
+                Err(BreakpointError::InvalidType(bp_type.bits()))
+            );
+        }
+        assert_eq!(
+            breakpoint(HwBreakpointType::X, 0x1002, HwBreakpointLen::LONG.bytes()).validate(),
+            Ok(())
+        );
+        assert_eq!(
+            breakpoint(HwBreakpointType::R, 0x1002, 4).validate(),
+            if is_x86 {
+                Err(BreakpointError::UnalignedAddress {
+                    addr: 0x1002,
+                    len: 4,
+                })
+            } else {
+                Ok(())
+            }
+        );
+        assert_eq!(PerfEventType::Tracepoint(316).validate(), Ok(()));
+
+        // An attr from a perf.data file with an arm64 watchpoint can be read.
+        let mut attr = PerfEventAttr::new(breakpoint(HwBreakpointType::W, 0x1001, 7));
+        attr.flags = AttrFlags::EXCLUDE_KERNEL;
+        let mut bytes = Vec::new();
+        attr.write::<_, LittleEndian>(&mut bytes).unwrap();
+        let (parsed, _) = PerfEventAttr::parse::<_, LittleEndian>(&bytes[..]).unwrap();
+        assert_eq!(parsed.type_, attr.type_);
+        assert_eq!(parsed.event_string(), "mem:0x1001/7:w:uh");
+    }
+
+    #[test]
+    fn watchpoint() {
+        let watchpoint = PerfEventAttr::watchpoint(0x7ffd_1000, HwBreakpointLen::Len4).unwrap();
+        let mut bytes = Vec::new();
+        watchpoint.write::<_, LittleEndian>(&mut bytes).unwrap();
+        let (parsed, _) = PerfEventAttr::parse::<_, LittleEndian>(&bytes[..]).unwrap();
+        assert_eq!(
+            parsed.type_,
+            PerfEventType::Breakpoint(
+                HwBreakpointType::W,
+                HwBreakpointAddr(0x7ffd_1000),
+                HwBreakpointLen::Len4
+            )
+        );
+        assert_eq!(parsed.event_string(), "mem:0x7ffd1000/4:w:u");
+
+        assert_eq!(
+            PerfEventAttr::watchpoint(0x7ffd_1000, HwBreakpointLen::Other(16)).err(),
+            Some(BreakpointError::InvalidLength(16))
+        );
+    }
+}
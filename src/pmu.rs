@@ -0,0 +1,580 @@
+use std::fs;
+use std::io;
+use std::ops::RangeInclusive;
+use std::path::Path;
+
+use crate::{EventStringError, PerfEventAttr, PerfEventType, PmuTypeId};
+
+/// The attr field which a [`PmuFormatField`] is stored in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum PmuConfigWord {
+    Config,
+    Config1,
+    Config2,
+}
+
+impl PmuConfigWord {
+    fn index(self) -> usize {
+        match self {
+            Self::Config => 0,
+            Self::Config1 => 1,
+            Self::Config2 => 2,
+        }
+    }
+}
+
+/// A named bitfield in the config of a PMU's events, from a file in
+/// `/sys/bus/event_source/devices/<pmu>/format/`.
+///
+/// The file contains a spec like `config:0-7,32-35`: The low 8 bits of the
+/// value are stored in bits 0 to 7 of `config`, the next 4 bits in bits 32
+/// to 35.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PmuFormatField {
+    pub name: String,
+    pub word: PmuConfigWord,
+    /// The bit ranges, from the least significant bits of the value.
+    pub bits: Vec<RangeInclusive<u32>>,
+}
+
+impl PmuFormatField {
+    /// Parse a spec like `config:0-7,32-35`. Returns `Ok(None)` for fields in
+    /// words which [`PerfEventAttr`] doesn't have, like `config3`.
+    pub fn parse(name: &str, spec: &str) -> Result<Option<Self>, io::Error> {
+        let invalid = || {
+            io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("Invalid PMU format {name}: {spec}"),
+            )
+        };
+        let (word, ranges) = spec.trim().split_once(':').ok_or_else(invalid)?;
+        let word = match word {
+            "config" => PmuConfigWord::Config,
+            "config1" => PmuConfigWord::Config1,
+            "config2" => PmuConfigWord::Config2,
+            _ => return Ok(None),
+        };
+        let bits = ranges
+            .split(',')
+            .map(|range| {
+                let (start, end) = range.split_once('-').unwrap_or((range, range));
+                let start: u32 = start.parse().map_err(|_| invalid())?;
+                let end: u32 = end.parse().map_err(|_| invalid())?;
+                if start > end || end > 63 {
+                    return Err(invalid());
+                }
+                Ok(start..=end)
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Some(Self {
+            name: name.to_owned(),
+            word,
+            bits,
+        }))
+    }
+
+    /// The mask of the bits of this field in its word.
+    fn mask(&self) -> u64 {
+        self.bits
+            .iter()
+            .map(|range| low_bits(range.end() - range.start() + 1) << range.start())
+            .fold(0, |mask, bits| mask | bits)
+    }
+
+    /// Extract this field's value from `[config, config1, config2]`.
+    pub fn decode(&self, configs: &[u64; 3]) -> u64 {
+        let word = configs[self.word.index()];
+        let mut value = 0;
+        let mut shift = 0;
+        for range in &self.bits {
+            let width = range.end() - range.start() + 1;
+            value |= ((word >> range.start()) & low_bits(width)) << shift;
+            shift += width;
+        }
+        value
+    }
+
+    /// Store `value` in this field of `[config, config1, config2]`. Returns
+    /// `false` if the value doesn't fit.
+    pub fn encode(&self, value: u64, configs: &mut [u64; 3]) -> bool {
+        let word = &mut configs[self.word.index()];
+        let mut remaining = value;
+        for range in &self.bits {
+            let width = range.end() - range.start() + 1;
+            let mask = low_bits(width) << range.start();
+            *word = (*word & !mask) | ((remaining << range.start()) & mask);
+            remaining = remaining.checked_shr(width).unwrap_or(0);
+        }
+        remaining == 0
+    }
+}
+
+fn low_bits(count: u32) -> u64 {
+    u64::MAX.checked_shr(64 - count).unwrap_or(0)
+}
+
+/// A named event of a PMU, from a file in
+/// `/sys/bus/event_source/devices/<pmu>/events/`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct PmuEventAlias {
+    pub name: String,
+    /// The terms of the event, e.g. `event=0x3c,umask=0x0`.
+    pub terms: String,
+    /// The factor for converting counts into `unit`, from `<name>.scale`.
+    pub scale: Option<f64>,
+    /// The unit of the scaled count, e.g. `MiB`, from `<name>.unit`.
+    pub unit: Option<String>,
+}
+
+/// A PMU from `/sys/bus/event_source/devices/<name>`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Pmu {
+    pub name: String,
+    /// The value for the attr's `type` field, i.e. the `u32` in
+    /// [`PerfEventType::DynamicPmu`].
+    pub type_: u32,
+    pub formats: Vec<PmuFormatField>,
+    pub events: Vec<PmuEventAlias>,
+    /// The CPUs which this PMU covers, from the `cpus` file of core PMUs or
+    /// from the `cpumask` file of uncore PMUs. `None` if the PMU has neither.
+    pub cpus: Option<Vec<u32>>,
+    /// Whether this is a core PMU, i.e. one which counts the hardware events
+    /// of the CPUs it covers. This is `cpu` on most systems, and one PMU per
+    /// core type, like `cpu_core` and `cpu_atom`, on hybrid systems.
+    pub is_core: bool,
+}
+
+/// The type of the cores covered by a core PMU of a hybrid CPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum CoreType {
+    /// A performance core (P-core), covered by `cpu_core` on Intel.
+    Performance,
+    /// An efficiency core (E-core), covered by `cpu_atom` on Intel.
+    Efficiency,
+}
+
+impl Pmu {
+    /// Read a PMU from its sysfs directory.
+    pub fn from_dir(dir: &Path) -> Result<Self, io::Error> {
+        let name = dir
+            .file_name()
+            .ok_or(io::ErrorKind::InvalidInput)?
+            .to_string_lossy()
+            .into_owned();
+        let type_ = read_trimmed(&dir.join("type"))?
+            .parse()
+            .map_err(|_| io::Error::new(io::ErrorKind::InvalidData, "Invalid PMU type"))?;
+
+        let mut formats = Vec::new();
+        for (file_name, contents) in read_dir_files(&dir.join("format"))? {
+            formats.extend(PmuFormatField::parse(&file_name, &contents)?);
+        }
+
+        let event_files = read_dir_files(&dir.join("events"))?;
+        let events = event_files
+            .iter()
+            .filter(|(file_name, _)| !file_name.contains('.'))
+            .map(|(name, terms)| {
+                let attribute = |suffix: &str| {
+                    let file_name = format!("{name}.{suffix}");
+                    event_files
+                        .iter()
+                        .find(|(other, _)| *other == file_name)
+                        .map(|(_, contents)| contents.trim().to_owned())
+                };
+                PmuEventAlias {
+                    name: name.clone(),
+                    terms: terms.trim().to_owned(),
+                    scale: attribute("scale").and_then(|scale| scale.parse().ok()),
+                    unit: attribute("unit"),
+                }
+            })
+            .collect();
+
+        let cpus = ["cpus", "cpumask"]
+            .iter()
+            .map(|file_name| dir.join(file_name))
+            .find(|path| path.is_file())
+            .map(|path| parse_cpu_list(&read_trimmed(&path)?))
+            .transpose()?;
+        // Only core PMUs have a `cpus` file, but on systems with a single
+        // core PMU, it doesn't have one either.
+        let is_core = name == "cpu" || dir.join("cpus").is_file();
+
+        Ok(Self {
+            name,
+            type_,
+            formats,
+            events,
+            cpus,
+            is_core,
+        })
+    }
+
+    /// The core type for the core PMUs of hybrid Intel CPUs. `None` for other
+    /// PMUs.
+    pub fn core_type(&self) -> Option<CoreType> {
+        match self.name.as_str() {
+            "cpu_core" => Some(CoreType::Performance),
+            "cpu_atom" => Some(CoreType::Efficiency),
+            _ => None,
+        }
+    }
+
+    /// Whether this PMU covers `cpu`. PMUs without a CPU list cover all CPUs.
+    pub fn covers_cpu(&self, cpu: u32) -> bool {
+        self.cpus.as_ref().is_none_or(|cpus| cpus.contains(&cpu))
+    }
+
+    /// The format field with this name.
+    pub fn format(&self, name: &str) -> Option<&PmuFormatField> {
+        self.formats.iter().find(|field| field.name == name)
+    }
+
+    /// The event alias with this name.
+    pub fn event(&self, name: &str) -> Option<&PmuEventAlias> {
+        self.events.iter().find(|event| event.name == name)
+    }
+
+    /// Split `(config, config1, config2)` into the named format fields with
+    /// nonzero values, in the order of [`Pmu::formats`]. Bits which aren't
+    /// covered by any format field come first, as `config`, `config1` or
+    /// `config2`, so that [`Pmu::encode`] gives back the same values.
+    pub fn decode(&self, config: u64, config1: u64, config2: u64) -> Vec<(&str, u64)> {
+        let configs = [config, config1, config2];
+        let mut covered = [0u64; 3];
+        for field in &self.formats {
+            covered[field.word.index()] |= field.mask();
+        }
+        let uncovered = ["config", "config1", "config2"]
+            .into_iter()
+            .zip(configs.into_iter().zip(covered))
+            .map(|(name, (value, covered))| (name, value & !covered))
+            .filter(|&(_, value)| value != 0);
+        let fields = self
+            .formats
+            .iter()
+            .map(|field| (field.name.as_str(), field.decode(&configs)))
+            .filter(|&(_, value)| value != 0);
+        uncovered.chain(fields).collect()
+    }
+
+    /// Encode named fields into `(config, config1, config2)`. The names can be
+    /// format fields or `config`, `config1` and `config2`.
+    pub fn encode(&self, fields: &[(&str, u64)]) -> Result<(u64, u64, u64), EventStringError> {
+        let mut configs = [0u64; 3];
+        for &(name, value) in fields {
+            if !self.encode_term(name, value, &mut configs)? {
+                return Err(EventStringError::InvalidTerm(format!("{name}={value:#x}")));
+            }
+        }
+        let [config, config1, config2] = configs;
+        Ok((config, config1, config2))
+    }
+
+    /// Store a term in `configs`. Returns `Ok(false)` if this PMU doesn't have
+    /// a field with this name.
+    pub(crate) fn encode_term(
+        &self,
+        name: &str,
+        value: u64,
+        configs: &mut [u64; 3],
+    ) -> Result<bool, EventStringError> {
+        let invalid = || EventStringError::InvalidTerm(format!("{name}={value:#x}"));
+        match name {
+            "config" => configs[0] = value,
+            "config1" => configs[1] = value,
+            "config2" => configs[2] = value,
+            _ => match self.format(name) {
+                Some(field) if field.encode(value, configs) => {}
+                Some(_) => return Err(invalid()),
+                None => return Ok(false),
+            },
+        }
+        Ok(true)
+    }
+
+    /// The event string for an event of this PMU, e.g.
+    /// `cpu/event=0x3c,umask=0x1/`.
+    pub fn event_string(&self, config: u64, config1: u64, config2: u64) -> String {
+        let terms: Vec<String> = self
+            .decode(config, config1, config2)
+            .into_iter()
+            .map(|(name, value)| format!("{name}={value:#x}"))
+            .collect();
+        format!("{}/{}/", self.name, terms.join(","))
+    }
+}
+
+/// The PMUs of a system, read from a sysfs-style directory tree like
+/// `/sys/bus/event_source/devices`.
+///
+/// This gives names to the numbers in [`PerfEventType::DynamicPmu`], and
+/// allows parsing event strings like `cpu_core/mem-loads,ldlat=30/` with the
+/// PMUs' format fields and event aliases.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct PmuRegistry {
+    pmus: Vec<Pmu>,
+}
+
+impl PmuRegistry {
+    /// The directory in which the kernel lists the PMUs.
+    pub const SYSFS_DIR: &'static str = "/sys/bus/event_source/devices";
+
+    /// Read the PMUs of the running system.
+    pub fn from_sysfs() -> Result<Self, io::Error> {
+        Self::from_dir(Path::new(Self::SYSFS_DIR))
+    }
+
+    /// Read the PMUs from the subdirectories of `dir` which have a `type`
+    /// file. The PMUs are sorted by name.
+    pub fn from_dir(dir: &Path) -> Result<Self, io::Error> {
+        let mut pmus = Vec::new();
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            if path.join("type").is_file() {
+                pmus.push(Pmu::from_dir(&path)?);
+            }
+        }
+        pmus.sort_by(|a, b| a.name.cmp(&b.name));
+        Ok(Self { pmus })
+    }
+
+    /// Create a registry from PMUs which were read or built elsewhere.
+    pub fn from_pmus(pmus: Vec<Pmu>) -> Self {
+        Self { pmus }
+    }
+
+    pub fn pmus(&self) -> &[Pmu] {
+        &self.pmus
+    }
+
+    /// The PMU with this name.
+    pub fn by_name(&self, name: &str) -> Option<&Pmu> {
+        self.pmus.iter().find(|pmu| pmu.name == name)
+    }
+
+    /// The PMU with this type, i.e. the value of the attr's `type` field.
+    pub fn by_type(&self, type_: u32) -> Option<&Pmu> {
+        self.pmus.iter().find(|pmu| pmu.type_ == type_)
+    }
+
+    /// Decode the config of a [`PerfEventType::DynamicPmu`] event into the
+    /// named fields of its PMU. Returns `None` for other event types and for
+    /// unknown PMUs.
+    pub fn decode(&self, event_type: &PerfEventType) -> Option<(&Pmu, Vec<(&str, u64)>)> {
+        let PerfEventType::DynamicPmu(type_, config, config1, config2) = *event_type else {
+            return None;
+        };
+        let pmu = self.by_type(type_)?;
+        Some((pmu, pmu.decode(config, config1, config2)))
+    }
+
+    /// The event string for an event type. Like the [`Display`](std::fmt::Display)
+    /// impl of [`PerfEventType`], but with PMU names and format fields, e.g.
+    /// `uncore_imc_0/event=0x4,umask=0xf/` instead of `14/config=0xf04/`.
+    /// Hardware events for one PMU of a hybrid system get the PMU name, e.g.
+    /// `cpu_atom/cycles/`.
+    pub fn event_type_string(&self, event_type: &PerfEventType) -> String {
+        if let Some((pmu, _)) = self.decode(event_type) {
+            let (_, _, config, config1, config2) = event_type.to_raw();
+            return pmu.event_string(config, config1, config2);
+        }
+        match event_type
+            .pmu_type_id()
+            .and_then(|id| self.resolve_pmu_type(id))
+        {
+            Some(pmu) => {
+                let name = event_type.with_pmu_type_id(PmuTypeId(0));
+                format!("{}/{name}/", pmu.name)
+            }
+            None => event_type.to_string(),
+        }
+    }
+
+    /// The core PMUs, e.g. `cpu`, or `cpu_atom` and `cpu_core`.
+    pub fn core_pmus(&self) -> impl Iterator<Item = &Pmu> {
+        self.pmus.iter().filter(|pmu| pmu.is_core)
+    }
+
+    /// Whether the system has more than one core PMU, i.e. different types
+    /// of cores.
+    pub fn is_hybrid(&self) -> bool {
+        self.core_pmus().count() > 1
+    }
+
+    /// The PMU which a [`PmuTypeId`] selects. Returns `None` for
+    /// `PmuTypeId(0)`, which selects all core PMUs, and for unknown PMUs.
+    pub fn resolve_pmu_type(&self, pmu_type_id: PmuTypeId) -> Option<&Pmu> {
+        match pmu_type_id {
+            PmuTypeId(0) => None,
+            PmuTypeId(type_) => self.by_type(type_),
+        }
+    }
+
+    /// The PMUs which count an event. Hardware and hardware cache events with
+    /// `PmuTypeId(0)` are counted by all core PMUs; on hybrid systems, perf
+    /// opens one event per core PMU instead. The CPUs which the event covers
+    /// are the union of the PMUs' [`Pmu::cpus`].
+    pub fn event_pmus(&self, event_type: &PerfEventType) -> Vec<&Pmu> {
+        match event_type.pmu_type_id() {
+            Some(PmuTypeId(0)) => self.core_pmus().collect(),
+            Some(pmu_type_id) => self.resolve_pmu_type(pmu_type_id).into_iter().collect(),
+            None => self.by_type(event_type.to_raw().0).into_iter().collect(),
+        }
+    }
+
+    /// The core PMU which covers `cpu`.
+    pub fn core_pmu_for_cpu(&self, cpu: u32) -> Option<&Pmu> {
+        self.core_pmus().find(|pmu| pmu.covers_cpu(cpu))
+    }
+
+    /// The core type of `cpu` on hybrid Intel systems, e.g. for tagging
+    /// samples by their [`CommonData::cpu`](crate::CommonData::cpu).
+    pub fn core_type_for_cpu(&self, cpu: u32) -> Option<CoreType> {
+        self.core_pmu_for_cpu(cpu)?.core_type()
+    }
+
+    /// Like [`PerfEventAttr::from_event_string`], but PMU events can use the
+    /// PMU names, format fields and event aliases of this registry. Event
+    /// aliases take precedence over hardware event names of the same name.
+    pub fn parse_event_string(&self, s: &str) -> Result<PerfEventAttr, EventStringError> {
+        crate::event_string::parse_event_string(s, Some(self))
+    }
+}
+
+fn read_trimmed(path: &Path) -> Result<String, io::Error> {
+    Ok(fs::read_to_string(path)?.trim().to_owned())
+}
+
+/// The names and contents of the files in `dir`, sorted by name. Returns an
+/// empty list if the directory doesn't exist.
+fn read_dir_files(dir: &Path) -> Result<Vec<(String, String)>, io::Error> {
+    let entries = match fs::read_dir(dir) {
+        Ok(entries) => entries,
+        Err(e) if e.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(e) => return Err(e),
+    };
+    let mut files = Vec::new();
+    for entry in entries {
+        let path = entry?.path();
+        if path.is_file() {
+            let name = path
+                .file_name()
+                .unwrap_or_default()
+                .to_string_lossy()
+                .into_owned();
+            files.push((name, fs::read_to_string(&path)?));
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+/// Parse a CPU list like `0-3,8,10-11`.
+pub(crate) fn parse_cpu_list(list: &str) -> Result<Vec<u32>, io::Error> {
+    let invalid = || {
+        io::Error::new(
+            io::ErrorKind::InvalidData,
+            format!("Invalid CPU list {list}"),
+        )
+    };
+    let mut cpus = Vec::new();
+    for range in list.trim().split(',').filter(|range| !range.is_empty()) {
+        let (start, end) = range.split_once('-').unwrap_or((range, range));
+        let start: u32 = start.parse().map_err(|_| invalid())?;
+        let end: u32 = end.parse().map_err(|_| invalid())?;
+        cpus.extend(start..=end);
+    }
+    Ok(cpus)
+}
+
+#[cfg(test)]
+mod test {
+    use super::{CoreType, PmuConfigWord, PmuRegistry};
+    use crate::{EventStringError, HardwareEventId, PerfEventType, PmuTypeId};
+    use std::path::Path;
+
+    #[test]
+    fn registry_from_fixture() {
+        let registry = PmuRegistry::from_dir(Path::new("tests/fixtures/sysfs_pmu")).unwrap();
+        assert_eq!(registry.pmus().len(), 6);
+        let cpu_core = registry.by_name("cpu_core").unwrap();
+        assert_eq!(cpu_core.type_, 4);
+        assert_eq!(cpu_core.cpus.as_ref().map(Vec::len), Some(16));
+        assert_eq!(
+            cpu_core.format("ldlat").unwrap().word,
+            PmuConfigWord::Config1
+        );
+        let imc = registry.by_type(14).unwrap();
+        assert_eq!(imc.cpus, Some(vec![0]));
+        let alias = imc.event("cas_count_read").unwrap();
+        assert_eq!(
+            (alias.scale, alias.unit.as_deref()),
+            (Some(6.103515625e-5), Some("MiB"))
+        );
+
+        // Split bitfields.
+        let amd_df = registry.by_name("amd_df").unwrap();
+        let (config, config1, config2) =
+            amd_df.encode(&[("event", 0x1ff), ("umask", 0x2)]).unwrap();
+        assert_eq!(config, 0xff | (0x1 << 32) | (0x2 << 8));
+        assert_eq!(
+            amd_df.decode(config, config1, config2),
+            [("event", 0x1ff), ("umask", 0x2)]
+        );
+        assert_eq!(
+            amd_df.encode(&[("event", 1 << 14)]),
+            Err(EventStringError::InvalidTerm("event=0x4000".to_owned()))
+        );
+        // Bits without a format field are kept.
+        let fields = amd_df.decode(config | (1 << 20), 0, 0);
+        assert_eq!(fields[0], ("config", 1 << 20));
+        assert_eq!(amd_df.encode(&fields), Ok((config | (1 << 20), 0, 0)));
+
+        let event_type = PerfEventType::DynamicPmu(14, 0xf04, 0, 0);
+        assert_eq!(
+            registry.event_type_string(&event_type),
+            "uncore_imc_0/event=0x4,umask=0xf/"
+        );
+
+        let attr = registry
+            .parse_event_string("uncore_imc_0/cas_count_read/")
+            .unwrap();
+        assert_eq!(attr.type_, event_type);
+        let attr = registry
+            .parse_event_string("cpu_core/mem-loads,ldlat=30/pp")
+            .unwrap();
+        assert_eq!(attr.type_, PerfEventType::DynamicPmu(4, 0x1cd, 30, 0));
+        assert_eq!(attr.event_string(), "cpu/config=0x1cd,config1=0x1e/pp");
+        assert!(registry.parse_event_string("cpu_core/foo=1/").is_err());
+    }
+
+    #[test]
+    fn hybrid() {
+        let registry = PmuRegistry::from_dir(Path::new("tests/fixtures/sysfs_pmu")).unwrap();
+        assert!(registry.is_hybrid());
+        let core_pmus: Vec<_> = registry.core_pmus().map(|pmu| pmu.name.as_str()).collect();
+        assert_eq!(core_pmus, ["cpu_atom", "cpu_core"]);
+        assert_eq!(registry.core_type_for_cpu(3), Some(CoreType::Performance));
+        assert_eq!(registry.core_type_for_cpu(20), Some(CoreType::Efficiency));
+        assert_eq!(registry.core_type_for_cpu(99), None);
+
+        let atom_cycles = PerfEventType::Hardware(HardwareEventId::CpuCycles, PmuTypeId(10));
+        let pmus = registry.event_pmus(&atom_cycles);
+        assert_eq!(pmus.len(), 1);
+        assert_eq!(pmus[0].cpus.as_ref().unwrap().first(), Some(&16));
+        assert_eq!(registry.event_type_string(&atom_cycles), "cpu_atom/cycles/");
+        let attr = registry.parse_event_string("cpu_atom/cycles/").unwrap();
+        assert_eq!(attr.type_, atom_cycles);
+
+        let any_cycles = PerfEventType::Hardware(HardwareEventId::CpuCycles, PmuTypeId(0));
+        assert_eq!(registry.event_pmus(&any_cycles).len(), 2);
+        let core_cycles = atom_cycles.with_pmu_type_id(PmuTypeId(4));
+        assert_eq!(
+            core_cycles.hybrid_merge_key(),
+            atom_cycles.hybrid_merge_key()
+        );
+        assert_eq!(core_cycles.hybrid_merge_key(), any_cycles);
+    }
+}
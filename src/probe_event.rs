@@ -0,0 +1,389 @@
+use std::ffi::CStr;
+use std::marker::PhantomData;
+
+use crate::constants::*;
+use crate::{PerfEventAttr, PerfEventType, Pmu, PmuRegistry};
+
+/// The dynamic PMU which a [`ProbeEvent`] is created with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProbeKind {
+    /// The `kprobe` PMU, for probes in the kernel.
+    Kprobe,
+    /// The `uprobe` PMU, for probes in user-space executables.
+    Uprobe,
+}
+
+impl ProbeKind {
+    /// The kind for the PMU with this name, i.e. `kprobe` or `uprobe`.
+    pub fn from_pmu_name(name: &str) -> Option<Self> {
+        match name {
+            "kprobe" => Some(Self::Kprobe),
+            "uprobe" => Some(Self::Uprobe),
+            _ => None,
+        }
+    }
+
+    /// The name of the PMU, for looking up its type in sysfs or in the
+    /// `PMU_MAPPINGS` section of a perf.data file.
+    pub fn pmu_name(self) -> &'static str {
+        match self {
+            Self::Kprobe => "kprobe",
+            Self::Uprobe => "uprobe",
+        }
+    }
+}
+
+/// A string which the attr refers to by pointer: `kprobe_func` or
+/// `uprobe_path`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProbeString<'a> {
+    /// The address of the string in the process which created the attr. This
+    /// is what a decoded attr contains; the string itself is not recorded in
+    /// the attr.
+    Pointer(u64),
+    /// The string itself, e.g. for creating an attr, or resolved from the
+    /// event name in the `EVENT_DESC` section of a perf.data file.
+    Str(&'a CStr),
+}
+
+impl ProbeString<'_> {
+    /// The value for the attr field. The pointer of a [`ProbeString::Str`] is
+    /// only valid as long as the string is alive.
+    fn to_raw(self) -> u64 {
+        match self {
+            Self::Pointer(ptr) => ptr,
+            Self::Str(s) => s.as_ptr() as u64,
+        }
+    }
+}
+
+/// The location which a [`ProbeEvent`] is attached to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ProbeTarget<'a> {
+    /// A kernel function plus an offset. (`kprobe_func` and `probe_offset`)
+    KernelFunction { func: ProbeString<'a>, offset: u64 },
+    /// A kernel address. (`kprobe_addr`, when `kprobe_func` is NULL)
+    KernelAddress(u64),
+    /// A file offset in an executable. (`uprobe_path` and `probe_offset`)
+    ///
+    /// `ref_ctr_offset` is the file offset of the reference counter, the
+    /// "semaphore" of an SDT probe, or 0 if there is none.
+    UserFile {
+        path: ProbeString<'a>,
+        offset: u64,
+        ref_ctr_offset: u64,
+    },
+}
+
+/// A kprobe or uprobe created via the `kprobe` or `uprobe` dynamic PMU, i.e.
+/// the interpretation of a [`PerfEventType::DynamicPmu`] of these PMUs.
+///
+/// Unlike probes created in tracefs, which are opened as
+/// [`PerfEventType::Tracepoint`], these probes are described fully by the
+/// attr, and are removed when the event is closed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub struct ProbeEvent<'a> {
+    /// Whether this probe fires on the return from the function, rather than
+    /// on entry. (The `retprobe` format bit)
+    pub is_retprobe: bool,
+    pub target: ProbeTarget<'a>,
+}
+
+impl<'a> ProbeEvent<'a> {
+    /// A kprobe at `func+offset`.
+    pub fn kprobe(func: &'a CStr, offset: u64) -> Self {
+        Self {
+            is_retprobe: false,
+            target: ProbeTarget::KernelFunction {
+                func: ProbeString::Str(func),
+                offset,
+            },
+        }
+    }
+
+    /// A kprobe at a kernel address.
+    pub fn kprobe_addr(addr: u64) -> Self {
+        Self {
+            is_retprobe: false,
+            target: ProbeTarget::KernelAddress(addr),
+        }
+    }
+
+    /// A uprobe at the file offset `offset` in the executable at `path`.
+    pub fn uprobe(path: &'a CStr, offset: u64) -> Self {
+        Self {
+            is_retprobe: false,
+            target: ProbeTarget::UserFile {
+                path: ProbeString::Str(path),
+                offset,
+                ref_ctr_offset: 0,
+            },
+        }
+    }
+
+    /// Make this a kretprobe or uretprobe.
+    pub fn retprobe(mut self) -> Self {
+        self.is_retprobe = true;
+        self
+    }
+
+    /// Set the file offset of the SDT reference counter of a uprobe. Has no
+    /// effect on kprobes.
+    pub fn ref_ctr_offset(mut self, new_ref_ctr_offset: u64) -> Self {
+        if let ProbeTarget::UserFile { ref_ctr_offset, .. } = &mut self.target {
+            *ref_ctr_offset = new_ref_ctr_offset;
+        }
+        self
+    }
+
+    /// Which PMU this probe is created with.
+    pub fn kind(&self) -> ProbeKind {
+        match self.target {
+            ProbeTarget::KernelFunction { .. } | ProbeTarget::KernelAddress(_) => ProbeKind::Kprobe,
+            ProbeTarget::UserFile { .. } => ProbeKind::Uprobe,
+        }
+    }
+
+    /// Replace the pointer to the function name or path with the string, e.g.
+    /// one taken from the event name in a perf.data file.
+    pub fn with_string<'b>(self, s: &'b CStr) -> ProbeEvent<'b>
+    where
+        'a: 'b,
+    {
+        let target = match self.target {
+            ProbeTarget::KernelFunction { offset, .. } => ProbeTarget::KernelFunction {
+                func: ProbeString::Str(s),
+                offset,
+            },
+            ProbeTarget::UserFile {
+                offset,
+                ref_ctr_offset,
+                ..
+            } => ProbeTarget::UserFile {
+                path: ProbeString::Str(s),
+                offset,
+                ref_ctr_offset,
+            },
+            target => target,
+        };
+        ProbeEvent {
+            is_retprobe: self.is_retprobe,
+            target,
+        }
+    }
+
+    /// Decode the config of an event of the `kind` PMU, with the format
+    /// which the kernel uses for these PMUs. Use [`ProbeEvent::from_pmu`] if
+    /// the PMU's format is known.
+    pub fn decode(kind: ProbeKind, config: u64, config1: u64, config2: u64) -> Self {
+        let is_retprobe = config & PERF_PROBE_CONFIG_IS_RETPROBE != 0;
+        let ref_ctr_offset = config >> PERF_UPROBE_REF_CTR_OFFSET_SHIFT;
+        Self::from_fields(kind, is_retprobe, ref_ctr_offset, config1, config2)
+    }
+
+    /// Decode the config of an event of `pmu`, using the `retprobe` and
+    /// `ref_ctr_offset` fields from its format directory. Returns `None` if
+    /// `pmu` is not the `kprobe` or `uprobe` PMU.
+    pub fn from_pmu(pmu: &Pmu, config: u64, config1: u64, config2: u64) -> Option<Self> {
+        let kind = ProbeKind::from_pmu_name(&pmu.name)?;
+        let configs = [config, config1, config2];
+        let is_retprobe = match pmu.format("retprobe") {
+            Some(field) => field.decode(&configs) != 0,
+            None => config & PERF_PROBE_CONFIG_IS_RETPROBE != 0,
+        };
+        let ref_ctr_offset = match pmu.format("ref_ctr_offset") {
+            Some(field) => field.decode(&configs),
+            None => config >> PERF_UPROBE_REF_CTR_OFFSET_SHIFT,
+        };
+        Some(Self::from_fields(
+            kind,
+            is_retprobe,
+            ref_ctr_offset,
+            config1,
+            config2,
+        ))
+    }
+
+    fn from_fields(
+        kind: ProbeKind,
+        is_retprobe: bool,
+        ref_ctr_offset: u64,
+        config1: u64,
+        config2: u64,
+    ) -> Self {
+        let target = match kind {
+            ProbeKind::Kprobe if config1 == 0 => ProbeTarget::KernelAddress(config2),
+            ProbeKind::Kprobe => ProbeTarget::KernelFunction {
+                func: ProbeString::Pointer(config1),
+                offset: config2,
+            },
+            ProbeKind::Uprobe => ProbeTarget::UserFile {
+                path: ProbeString::Pointer(config1),
+                offset: config2,
+                ref_ctr_offset,
+            },
+        };
+        Self {
+            is_retprobe,
+            target,
+        }
+    }
+
+    /// The attr for this probe, for the PMU with the type `pmu_type`, with
+    /// the format which the kernel uses for these PMUs.
+    pub fn to_attr_for_type(&self, pmu_type: u32) -> ProbeAttr<'a> {
+        let mut config = 0;
+        if self.is_retprobe {
+            config |= PERF_PROBE_CONFIG_IS_RETPROBE;
+        }
+        if let ProbeTarget::UserFile { ref_ctr_offset, .. } = self.target {
+            config |= ref_ctr_offset << PERF_UPROBE_REF_CTR_OFFSET_SHIFT;
+        }
+        let (config1, config2) = self.raw_config1_config2();
+        ProbeAttr::new(PerfEventType::DynamicPmu(
+            pmu_type, config, config1, config2,
+        ))
+    }
+
+    /// The attr for this probe on `pmu`, using the fields from its format
+    /// directory. Returns `None` if `pmu` is the wrong PMU for this probe, or
+    /// if it has no field for a flag or offset which needs to be set.
+    pub fn to_attr(&self, pmu: &Pmu) -> Option<ProbeAttr<'a>> {
+        if ProbeKind::from_pmu_name(&pmu.name) != Some(self.kind()) {
+            return None;
+        }
+        let mut configs = [0; 3];
+        let mut set = |name: &str, value: u64| match pmu.format(name) {
+            Some(field) => field.encode(value, &mut configs),
+            None => value == 0,
+        };
+        if !set("retprobe", u64::from(self.is_retprobe)) {
+            return None;
+        }
+        if let ProbeTarget::UserFile { ref_ctr_offset, .. } = self.target {
+            if !set("ref_ctr_offset", ref_ctr_offset) {
+                return None;
+            }
+        }
+        let (config1, config2) = self.raw_config1_config2();
+        Some(ProbeAttr::new(PerfEventType::DynamicPmu(
+            pmu.type_, configs[0], config1, config2,
+        )))
+    }
+
+    fn raw_config1_config2(&self) -> (u64, u64) {
+        match self.target {
+            ProbeTarget::KernelFunction { func, offset } => (func.to_raw(), offset),
+            ProbeTarget::KernelAddress(addr) => (0, addr),
+            ProbeTarget::UserFile { path, offset, .. } => (path.to_raw(), offset),
+        }
+    }
+}
+
+/// The attr for a [`ProbeEvent`].
+///
+/// For a [`ProbeString::Str`], the attr contains a pointer to the string, so
+/// it must only be passed to `perf_event_open` while the string is alive.
+/// This type borrows the string for that reason; copying the attr out of it
+/// with [`ProbeAttr::attr`] gives up that protection.
+///
+/// ```compile_fail
+/// use linux_perf_event_reader::ProbeEvent;
+///
+/// let attr = {
+///     let func = std::ffi::CString::new("do_sys_openat2").unwrap();
+///     ProbeEvent::kprobe(&func, 0).to_attr_for_type(6)
+/// };
+/// # drop(attr);
+/// ```
+#[derive(Debug, Clone)]
+pub struct ProbeAttr<'a> {
+    attr: PerfEventAttr,
+    string: PhantomData<&'a CStr>,
+}
+
+impl ProbeAttr<'_> {
+    fn new(type_: PerfEventType) -> Self {
+        Self {
+            attr: PerfEventAttr::new(type_),
+            string: PhantomData,
+        }
+    }
+
+    /// The attr, e.g. for `perf_event_open`.
+    pub fn attr(&self) -> &PerfEventAttr {
+        &self.attr
+    }
+
+    /// The attr, e.g. for setting the sampling policy and sample format.
+    pub fn attr_mut(&mut self) -> &mut PerfEventAttr {
+        &mut self.attr
+    }
+}
+
+impl PmuRegistry {
+    /// Decode a [`PerfEventType::DynamicPmu`] of the `kprobe` or `uprobe` PMU.
+    pub fn probe_event(&self, event_type: &PerfEventType) -> Option<ProbeEvent<'static>> {
+        match *event_type {
+            PerfEventType::DynamicPmu(type_, config, config1, config2) => {
+                ProbeEvent::from_pmu(self.by_type(type_)?, config, config1, config2)
+            }
+            _ => None,
+        }
+    }
+
+    /// The attr for `probe`, on the `kprobe` or `uprobe` PMU of this registry.
+    pub fn probe_attr<'a>(&self, probe: &ProbeEvent<'a>) -> Option<ProbeAttr<'a>> {
+        probe.to_attr(self.by_name(probe.kind().pmu_name())?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::path::Path;
+
+    use super::{ProbeEvent, ProbeKind, ProbeString, ProbeTarget};
+    use crate::{PerfEventType, PmuRegistry};
+
+    #[test]
+    fn probe_events() {
+        let registry = PmuRegistry::from_dir(Path::new("tests/fixtures/sysfs_probe_pmu")).unwrap();
+
+        let func = c"do_sys_openat2";
+        let probe = ProbeEvent::kprobe(func, 0x10).retprobe();
+        let probe_attr = registry.probe_attr(&probe).unwrap();
+        let attr = probe_attr.attr();
+        let func_ptr = func.as_ptr() as u64;
+        assert_eq!(attr.type_, PerfEventType::DynamicPmu(6, 1, func_ptr, 0x10));
+        assert_eq!(attr.type_, probe.to_attr_for_type(6).attr().type_);
+        let decoded = registry.probe_event(&attr.type_).unwrap();
+        assert!(decoded.is_retprobe);
+        assert_eq!(
+            decoded.target,
+            ProbeTarget::KernelFunction {
+                func: ProbeString::Pointer(func_ptr),
+                offset: 0x10
+            }
+        );
+        assert_eq!(decoded.with_string(func), probe);
+
+        let probe = ProbeEvent::kprobe_addr(0xffffffff81000000);
+        let probe_attr = registry.probe_attr(&probe).unwrap();
+        assert_eq!(registry.probe_event(&probe_attr.attr().type_), Some(probe));
+
+        let path = c"/usr/lib/libc.so.6";
+        let probe = ProbeEvent::uprobe(path, 0x2a0f0).ref_ctr_offset(0x1234);
+        let probe_attr = registry.probe_attr(&probe).unwrap();
+        let path_ptr = path.as_ptr() as u64;
+        assert_eq!(
+            probe_attr.attr().type_,
+            PerfEventType::DynamicPmu(7, 0x1234 << 32, path_ptr, 0x2a0f0)
+        );
+        let decoded = ProbeEvent::decode(ProbeKind::Uprobe, 0x1234 << 32, path_ptr, 0x2a0f0);
+        assert_eq!(decoded.with_string(path), probe);
+        assert!(!decoded.is_retprobe);
+
+        let cycles = registry.parse_event_string("cycles").unwrap();
+        assert_eq!(registry.probe_event(&cycles.type_), None);
+    }
+}
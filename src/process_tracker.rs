@@ -0,0 +1,273 @@
+use std::collections::HashMap;
+
+use crate::{CommOrExecRecord, CommonData, EventRecord, ForkOrExitRecord};
+
+/// A name that a thread had, starting at a certain time.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThreadName {
+    /// The time from which on the thread had this name. Names which were
+    /// reported without a timestamp, e.g. the names of threads that already
+    /// existed when the recording started, have a start time of 0.
+    pub start_time: u64,
+    /// The name, without the nul terminator.
+    pub name: Vec<u8>,
+    /// Whether the name was set by an `execve`, as opposed to `prctl(PR_SET_NAME)`
+    /// or inheritance from the parent thread.
+    pub is_exec: bool,
+}
+
+/// One thread, from its creation to its exit.
+///
+/// Thread IDs are reused by the kernel, so a single tid can map to multiple
+/// `ThreadInfo`s over the course of a recording.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ThreadInfo {
+    /// The process ID, i.e. the tid of the thread group leader.
+    pub pid: i32,
+    /// The thread ID.
+    pub tid: i32,
+    /// The pid of the process which created this thread, if it was created
+    /// during the recording.
+    pub parent_pid: Option<i32>,
+    /// The tid of the thread which created this thread, if it was created
+    /// during the recording.
+    pub parent_tid: Option<i32>,
+    /// The time of the FORK record, or `None` if the thread already existed
+    /// when the recording started.
+    pub start_time: Option<u64>,
+    /// The time of the EXIT record, or `None` if the thread hadn't exited by
+    /// the end of the recording.
+    pub end_time: Option<u64>,
+    /// The names of the thread, ordered by start time.
+    pub names: Vec<ThreadName>,
+}
+
+impl ThreadInfo {
+    fn new(pid: i32, tid: i32, start_time: Option<u64>) -> Self {
+        Self {
+            pid,
+            tid,
+            parent_pid: None,
+            parent_tid: None,
+            start_time,
+            end_time: None,
+            names: Vec::new(),
+        }
+    }
+
+    /// Whether the thread existed at `timestamp`.
+    pub fn is_alive_at(&self, timestamp: u64) -> bool {
+        self.start_time.is_none_or(|start| start <= timestamp)
+            && self.end_time.is_none_or(|end| timestamp <= end)
+    }
+
+    /// The name of the thread at `timestamp`.
+    ///
+    /// If `timestamp` is before the first name change, the first name is
+    /// returned, because the first name is usually only reported after the
+    /// thread was created.
+    pub fn name_at(&self, timestamp: u64) -> Option<&[u8]> {
+        let index = self
+            .names
+            .partition_point(|name| name.start_time <= timestamp);
+        let name = self.names.get(index.saturating_sub(1))?;
+        Some(&name.name)
+    }
+
+    /// The name which was set by the most recent `execve` before `timestamp`,
+    /// i.e. the name of the executable, even if the thread has renamed itself
+    /// since. Falls back to [`ThreadInfo::name_at`] if there was no exec.
+    pub fn exec_name_at(&self, timestamp: u64) -> Option<&[u8]> {
+        let index = self
+            .names
+            .partition_point(|name| name.start_time <= timestamp);
+        match self.names[..index].iter().rev().find(|name| name.is_exec) {
+            Some(name) => Some(&name.name),
+            None => self.name_at(timestamp),
+        }
+    }
+
+    fn set_name(&mut self, name: &[u8], timestamp: u64, is_exec: bool) {
+        // Keep the names sorted; records with equal timestamps keep their order.
+        let index = self
+            .names
+            .partition_point(|name| name.start_time <= timestamp);
+        self.names.insert(
+            index,
+            ThreadName {
+                start_time: timestamp,
+                name: name.to_owned(),
+                is_exec,
+            },
+        );
+    }
+}
+
+/// Tracks the lifetime and names of processes and threads, from COMM, FORK
+/// and EXIT records.
+///
+/// Feed it all records with [`ProcessTracker::handle_record`], in timestamp
+/// order, and then ask for the name of a thread at a certain time with
+/// [`ProcessTracker::thread_name_at`].
+#[derive(Debug, Clone, Default)]
+pub struct ProcessTracker {
+    /// All threads which have used a tid, ordered by creation.
+    threads: HashMap<i32, Vec<ThreadInfo>>,
+}
+
+impl ProcessTracker {
+    /// Create an empty tracker.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply a COMM, FORK or EXIT record. Other records are ignored.
+    ///
+    /// `common_data` supplies the timestamp of COMM records, which is only
+    /// available if the attr has [`AttrFlags::SAMPLE_ID_ALL`](crate::AttrFlags::SAMPLE_ID_ALL)
+    /// and [`SampleFormat::TIME`](crate::SampleFormat::TIME).
+    pub fn handle_record(&mut self, record: &EventRecord, common_data: &CommonData) {
+        match record {
+            EventRecord::Comm(record) => {
+                self.handle_comm(record, common_data.timestamp.unwrap_or(0))
+            }
+            EventRecord::Fork(record) => self.handle_fork(record),
+            EventRecord::Exit(record) => self.handle_exit(record),
+            _ => {}
+        }
+    }
+
+    /// Apply a COMM record. `timestamp` is the record's time.
+    pub fn handle_comm(&mut self, record: &CommOrExecRecord, timestamp: u64) {
+        let name = record.name.as_slice();
+        let thread = self.current_thread_mut(record.pid, record.tid, timestamp);
+        thread.set_name(&name, timestamp, record.is_execve);
+    }
+
+    /// Apply a FORK record. This always starts a new thread, even if a thread
+    /// with the same tid is still known to be alive, because that means its
+    /// EXIT record got lost.
+    pub fn handle_fork(&mut self, record: &ForkOrExitRecord) {
+        let timestamp = record.timestamp;
+        let mut thread = ThreadInfo::new(record.pid, record.tid, Some(timestamp));
+        thread.parent_pid = Some(record.ppid);
+        thread.parent_tid = Some(record.ptid);
+        // The child starts out with the name of its parent.
+        if let Some(name) = self
+            .thread_at(record.ptid, timestamp)
+            .and_then(|parent| parent.name_at(timestamp))
+        {
+            thread.set_name(name, timestamp, false);
+        }
+        let incarnations = self.threads.entry(record.tid).or_default();
+        if let Some(previous) = incarnations.last_mut() {
+            previous.end_time.get_or_insert(timestamp);
+        }
+        incarnations.push(thread);
+    }
+
+    /// Apply an EXIT record.
+    pub fn handle_exit(&mut self, record: &ForkOrExitRecord) {
+        let thread = self.current_thread_mut(record.pid, record.tid, record.timestamp);
+        thread.end_time = Some(record.timestamp);
+    }
+
+    /// The thread which used `tid` at `timestamp`.
+    ///
+    /// If no thread with this tid was alive at that time, the closest
+    /// earlier one is returned, or the first one if there are only later ones.
+    pub fn thread_at(&self, tid: i32, timestamp: u64) -> Option<&ThreadInfo> {
+        let incarnations = self.threads.get(&tid)?;
+        let index = incarnations
+            .partition_point(|thread| thread.start_time.is_none_or(|start| start <= timestamp));
+        incarnations.get(index.saturating_sub(1))
+    }
+
+    /// The name of the thread `tid` at `timestamp`.
+    pub fn thread_name_at(&self, tid: i32, timestamp: u64) -> Option<&[u8]> {
+        self.thread_at(tid, timestamp)?.name_at(timestamp)
+    }
+
+    /// All threads which have used `tid`, ordered by creation.
+    pub fn threads_for_tid(&self, tid: i32) -> &[ThreadInfo] {
+        self.threads.get(&tid).map_or(&[], |threads| &threads[..])
+    }
+
+    /// All known threads.
+    pub fn threads(&self) -> impl Iterator<Item = &ThreadInfo> {
+        self.threads.values().flatten()
+    }
+
+    /// The thread which currently uses `tid`. Creates a new thread if there is
+    /// none, or if the current one belongs to a different process or has
+    /// already exited.
+    fn current_thread_mut(&mut self, pid: i32, tid: i32, timestamp: u64) -> &mut ThreadInfo {
+        let incarnations = self.threads.entry(tid).or_default();
+        let needs_new_thread = match incarnations.last() {
+            Some(thread) => thread.pid != pid || thread.end_time.is_some_and(|end| end < timestamp),
+            None => true,
+        };
+        if needs_new_thread {
+            let start_time = if incarnations.is_empty() {
+                None
+            } else {
+                Some(timestamp)
+            };
+            incarnations.push(ThreadInfo::new(pid, tid, start_time));
+        }
+        incarnations.last_mut().unwrap()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::ProcessTracker;
+    use crate::{CommOrExecRecord, ForkOrExitRecord, RawData};
+
+    fn comm(pid: i32, tid: i32, name: &[u8], is_execve: bool) -> CommOrExecRecord<'_> {
+        CommOrExecRecord {
+            pid,
+            tid,
+            name: RawData::from(name),
+            is_execve,
+        }
+    }
+
+    fn fork_or_exit(pid: i32, tid: i32, parent: i32, timestamp: u64) -> ForkOrExitRecord {
+        ForkOrExitRecord {
+            pid,
+            ppid: parent,
+            tid,
+            ptid: parent,
+            timestamp,
+        }
+    }
+
+    #[test]
+    fn names_over_time_and_pid_reuse() {
+        let mut tracker = ProcessTracker::new();
+        tracker.handle_comm(&comm(10, 10, b"bash", true), 0);
+        tracker.handle_fork(&fork_or_exit(20, 20, 10, 100));
+        tracker.handle_comm(&comm(20, 20, b"ls", true), 150);
+        tracker.handle_comm(&comm(20, 20, b"worker", false), 170);
+        tracker.handle_exit(&fork_or_exit(20, 20, 10, 200));
+        // tid 20 is reused by a new process.
+        tracker.handle_fork(&fork_or_exit(20, 20, 10, 300));
+        tracker.handle_comm(&comm(20, 20, b"cat", true), 310);
+
+        assert_eq!(tracker.thread_name_at(20, 120), Some(&b"bash"[..]));
+        assert_eq!(tracker.thread_name_at(20, 160), Some(&b"ls"[..]));
+        assert_eq!(tracker.thread_name_at(20, 180), Some(&b"worker"[..]));
+        let thread = tracker.thread_at(20, 180).unwrap();
+        assert_eq!(thread.exec_name_at(180), Some(&b"ls"[..]));
+        assert_eq!(thread.parent_pid, Some(10));
+        assert_eq!((thread.start_time, thread.end_time), (Some(100), Some(200)));
+        assert!(!thread.is_alive_at(250));
+
+        assert_eq!(tracker.thread_name_at(20, 305), Some(&b"bash"[..]));
+        assert_eq!(tracker.thread_name_at(20, 400), Some(&b"cat"[..]));
+        assert_eq!(tracker.threads_for_tid(20).len(), 2);
+        assert_eq!(tracker.thread_name_at(10, 1000), Some(&b"bash"[..]));
+        assert_eq!(tracker.thread_name_at(30, 1000), None);
+    }
+}
@@ -0,0 +1,196 @@
+use byteorder::ByteOrder;
+
+use crate::{RawData, ReadFormat};
+
+/// The value of a single counter, as part of [`ReadFormatValues`].
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ReadFormatValue {
+    /// The counter value.
+    pub value: u64,
+    /// The event ID, if `ReadFormat::ID` was set.
+    pub id: Option<u64>,
+    /// The number of lost samples, if `ReadFormat::LOST` was set.
+    pub lost: Option<u64>,
+}
+
+/// The decoded `read_format` data which is returned by read() on a perf event
+/// fd, and which is included in samples with `SampleFormat::READ`.
+///
+/// See [`ReadFormat`] for the layout.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ReadFormatValues {
+    /// The time the event was enabled, in nanoseconds, if
+    /// `ReadFormat::TOTAL_TIME_ENABLED` was set.
+    pub time_enabled: Option<u64>,
+    /// The time the event was actually counting on the PMU, in nanoseconds,
+    /// if `ReadFormat::TOTAL_TIME_RUNNING` was set.
+    pub time_running: Option<u64>,
+    /// The counter values. Without `ReadFormat::GROUP`, this has exactly one
+    /// element. With `ReadFormat::GROUP`, it has one element per group member,
+    /// starting with the group leader.
+    pub values: Vec<ReadFormatValue>,
+}
+
+impl ReadFormatValues {
+    /// Parse the data in the layout described by `read_format`.
+    pub fn parse<T: ByteOrder>(
+        data: RawData,
+        read_format: ReadFormat,
+    ) -> Result<Self, std::io::Error> {
+        let mut cur = data;
+        Self::parse_from_cursor::<T>(&mut cur, read_format)
+    }
+
+    /// Parse from the start of `cur` and advance `cur` past the parsed data.
+    pub(crate) fn parse_from_cursor<T: ByteOrder>(
+        cur: &mut RawData,
+        read_format: ReadFormat,
+    ) -> Result<Self, std::io::Error> {
+        if read_format.contains(ReadFormat::GROUP) {
+            let nr = cur.read_u64::<T>()?;
+            let (time_enabled, time_running) = Self::parse_times::<T>(cur, read_format)?;
+            // Every counter has at least one u64. Don't trust nr for the
+            // allocation size before we know that the data is there.
+            let capacity = (nr as usize).min(cur.len() / 8);
+            let mut values = Vec::with_capacity(capacity);
+            for _ in 0..nr {
+                let value = cur.read_u64::<T>()?;
+                values.push(Self::parse_value_tail::<T>(cur, value, read_format)?);
+            }
+            Ok(Self {
+                time_enabled,
+                time_running,
+                values,
+            })
+        } else {
+            let value = cur.read_u64::<T>()?;
+            let (time_enabled, time_running) = Self::parse_times::<T>(cur, read_format)?;
+            let value = Self::parse_value_tail::<T>(cur, value, read_format)?;
+            Ok(Self {
+                time_enabled,
+                time_running,
+                values: vec![value],
+            })
+        }
+    }
+
+    fn parse_times<T: ByteOrder>(
+        cur: &mut RawData,
+        read_format: ReadFormat,
+    ) -> Result<(Option<u64>, Option<u64>), std::io::Error> {
+        let time_enabled = if read_format.contains(ReadFormat::TOTAL_TIME_ENABLED) {
+            Some(cur.read_u64::<T>()?)
+        } else {
+            None
+        };
+        let time_running = if read_format.contains(ReadFormat::TOTAL_TIME_RUNNING) {
+            Some(cur.read_u64::<T>()?)
+        } else {
+            None
+        };
+        Ok((time_enabled, time_running))
+    }
+
+    fn parse_value_tail<T: ByteOrder>(
+        cur: &mut RawData,
+        value: u64,
+        read_format: ReadFormat,
+    ) -> Result<ReadFormatValue, std::io::Error> {
+        let id = if read_format.contains(ReadFormat::ID) {
+            Some(cur.read_u64::<T>()?)
+        } else {
+            None
+        };
+        let lost = if read_format.contains(ReadFormat::LOST) {
+            Some(cur.read_u64::<T>()?)
+        } else {
+            None
+        };
+        Ok(ReadFormatValue { value, id, lost })
+    }
+
+    /// Whether the counters were multiplexed, i.e. they didn't count for the
+    /// entire time they were enabled, because there were more events than
+    /// hardware counters.
+    ///
+    /// Returns false if the times weren't requested.
+    pub fn is_multiplexed(&self) -> bool {
+        match (self.time_enabled, self.time_running) {
+            (Some(enabled), Some(running)) => running < enabled,
+            _ => false,
+        }
+    }
+
+    /// Extrapolate a counter value to the entire enabled time, i.e.
+    /// `value * time_enabled / time_running`, like `perf stat` does.
+    ///
+    /// If the times weren't requested, `value` is returned unchanged. Returns
+    /// `None` if the counter was enabled but never ran, because then there is
+    /// nothing to extrapolate from.
+    pub fn scale(&self, value: u64) -> Option<u64> {
+        match (self.time_enabled, self.time_running) {
+            (Some(_), Some(0)) => None,
+            (Some(enabled), Some(running)) => {
+                let scaled = u128::from(value) * u128::from(enabled) / u128::from(running);
+                Some(u64::try_from(scaled).unwrap_or(u64::MAX))
+            }
+            _ => Some(value),
+        }
+    }
+
+    /// The scaled values of all counters, see [`ReadFormatValues::scale`].
+    pub fn scaled_values(&self) -> impl Iterator<Item = Option<u64>> + '_ {
+        self.values.iter().map(|v| self.scale(v.value))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{ReadFormatValue, ReadFormatValues};
+    use crate::{RawData, ReadFormat};
+    use byteorder::LittleEndian;
+
+    fn to_bytes(values: &[u64]) -> Vec<u8> {
+        values.iter().flat_map(|v| v.to_le_bytes()).collect()
+    }
+
+    #[test]
+    fn single_and_group() {
+        let format = ReadFormat::TOTAL_TIME_ENABLED | ReadFormat::TOTAL_TIME_RUNNING;
+        let data = to_bytes(&[1000, 400, 100]);
+        let values =
+            ReadFormatValues::parse::<LittleEndian>(RawData::from(&data[..]), format).unwrap();
+        assert_eq!(values.time_enabled, Some(400));
+        assert_eq!(values.time_running, Some(100));
+        assert!(values.is_multiplexed());
+        assert_eq!(values.scaled_values().collect::<Vec<_>>(), vec![Some(4000)]);
+
+        let format = ReadFormat::GROUP
+            | ReadFormat::TOTAL_TIME_ENABLED
+            | ReadFormat::TOTAL_TIME_RUNNING
+            | ReadFormat::ID
+            | ReadFormat::LOST;
+        let data = to_bytes(&[2, 50, 0, 7, 1, 0, 8, 2, 3]);
+        let values =
+            ReadFormatValues::parse::<LittleEndian>(RawData::from(&data[..]), format).unwrap();
+        assert_eq!(
+            values.values,
+            vec![
+                ReadFormatValue {
+                    value: 7,
+                    id: Some(1),
+                    lost: Some(0)
+                },
+                ReadFormatValue {
+                    value: 8,
+                    id: Some(2),
+                    lost: Some(3)
+                },
+            ]
+        );
+        assert_eq!(values.scale(7), None);
+
+        let truncated = RawData::from(&data[..data.len() - 8]);
+        assert!(ReadFormatValues::parse::<LittleEndian>(truncated, format).is_err());
+    }
+}
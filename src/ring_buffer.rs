@@ -0,0 +1,797 @@
+use crate::constants::*;
+use crate::{AttrFlags, RawData, RawEventRecord, RecordParseInfo, RecordType};
+use byteorder::{ByteOrder, NativeEndian};
+use std::marker::PhantomData;
+use std::ptr::NonNull;
+use std::sync::atomic::{fence, AtomicU64, Ordering};
+use std::{io, ptr};
+
+// Byte offsets of the fields in `struct perf_event_mmap_page`.
+const LOCK_OFFSET: usize = 8;
+const DATA_HEAD_OFFSET: usize = 1024;
+const DATA_TAIL_OFFSET: usize = 1032;
+const DATA_OFFSET_OFFSET: usize = 1040;
+const DATA_SIZE_OFFSET: usize = 1048;
+const AUX_HEAD_OFFSET: usize = 1056;
+const AUX_TAIL_OFFSET: usize = 1064;
+
+/// `perf_event_mmap_page`
+///
+/// The first page of the mmap'd region of a perf event fd. It contains
+/// information about the event's counter and about the clock, as well as the
+/// read and write positions of the ring buffer which follows this page.
+///
+/// ```pseudo-c
+/// struct perf_event_mmap_page {
+///     __u32 version;          /* version number of this structure */
+///     __u32 compat_version;   /* lowest version this is compat with */
+///     __u32 lock;             /* seqlock for synchronization */
+///     __u32 index;            /* hardware counter identifier */
+///     __s64 offset;           /* add to hardware counter value */
+///     __u64 time_enabled;     /* time event active */
+///     __u64 time_running;     /* time event on CPU */
+///     __u64 capabilities;
+///     __u16 pmc_width;
+///     __u16 time_shift;
+///     __u32 time_mult;
+///     __u64 time_offset;
+///     __u64 time_zero;
+///     __u32 size;             /* Header size up to __reserved[] fields. */
+///     __u32 __reserved_1;
+///     __u64 time_cycles;
+///     __u64 time_mask;
+///     __u8  __reserved[116*8]; /* align to 1k. */
+///     __u64 data_head;        /* head in the data section */
+///     __u64 data_tail;        /* user-space written tail */
+///     __u64 data_offset;      /* where the buffer starts */
+///     __u64 data_size;        /* data buffer size */
+///     __u64 aux_head;
+///     __u64 aux_tail;
+///     __u64 aux_offset;
+///     __u64 aux_size;
+/// };
+/// ```
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct PerfEventMmapPage {
+    /// Version number of this structure.
+    pub version: u32,
+    /// Lowest version this is compatible with.
+    pub compat_version: u32,
+    /// The hardware counter identifier for `rdpmc`, plus one. Zero if the
+    /// counter cannot be read from user space.
+    pub index: u32,
+    /// Add this to the hardware counter value.
+    pub offset: i64,
+    /// Time the event was active.
+    pub time_enabled: u64,
+    /// Time the event was running on a CPU.
+    pub time_running: u64,
+    /// Bitset of `PERF_MMAP_CAP_*` values.
+    pub capabilities: u64,
+    /// The bit width of the hardware counter, if `PERF_MMAP_CAP_USER_RDPMC` is set.
+    pub pmc_width: u16,
+    /// Shift for converting cycles to nanoseconds.
+    pub time_shift: u16,
+    /// Multiplier for converting cycles to nanoseconds.
+    pub time_mult: u32,
+    /// Offset for converting cycles to the event's time.
+    pub time_offset: u64,
+    /// Offset for converting cycles to perf timestamps, if
+    /// `PERF_MMAP_CAP_USER_TIME_ZERO` is set.
+    pub time_zero: u64,
+    /// Header size up to the reserved fields.
+    pub size: u32,
+    /// The cycle value at which `time_zero` was taken, if
+    /// `PERF_MMAP_CAP_USER_TIME_SHORT` is set.
+    pub time_cycles: u64,
+    /// The mask for the cycle counter, if `PERF_MMAP_CAP_USER_TIME_SHORT` is set.
+    pub time_mask: u64,
+    /// The write position of the kernel in the data area.
+    pub data_head: u64,
+    /// The read position of user space in the data area.
+    pub data_tail: u64,
+    /// The byte offset of the data area from the start of the mmap.
+    pub data_offset: u64,
+    /// The size of the data area.
+    pub data_size: u64,
+    /// The write position of the kernel in the AUX area.
+    pub aux_head: u64,
+    /// The read position of user space in the AUX area.
+    pub aux_tail: u64,
+    /// The offset at which the AUX area needs to be mmap'd.
+    pub aux_offset: u64,
+    /// The size of the AUX area.
+    pub aux_size: u64,
+}
+
+impl PerfEventMmapPage {
+    /// The number of bytes which are parsed by [`PerfEventMmapPage::parse`].
+    pub const STRUCT_SIZE: usize = 1088;
+
+    /// Parse the struct from its raw bytes. The mmap page is always in the
+    /// native endianness of the machine which produced it.
+    pub fn parse<T: ByteOrder>(data: RawData) -> Result<Self, std::io::Error> {
+        let mut cur = data;
+        let version = cur.read_u32::<T>()?;
+        let compat_version = cur.read_u32::<T>()?;
+        let _lock = cur.read_u32::<T>()?;
+        let index = cur.read_u32::<T>()?;
+        let offset = cur.read_u64::<T>()? as i64;
+        let time_enabled = cur.read_u64::<T>()?;
+        let time_running = cur.read_u64::<T>()?;
+        let capabilities = cur.read_u64::<T>()?;
+        let pmc_width = cur.read_u16::<T>()?;
+        let time_shift = cur.read_u16::<T>()?;
+        let time_mult = cur.read_u32::<T>()?;
+        let time_offset = cur.read_u64::<T>()?;
+        let time_zero = cur.read_u64::<T>()?;
+        let size = cur.read_u32::<T>()?;
+        let _reserved_1 = cur.read_u32::<T>()?;
+        let time_cycles = cur.read_u64::<T>()?;
+        let time_mask = cur.read_u64::<T>()?;
+        cur.skip(116 * 8)?;
+        let data_head = cur.read_u64::<T>()?;
+        let data_tail = cur.read_u64::<T>()?;
+        let data_offset = cur.read_u64::<T>()?;
+        let data_size = cur.read_u64::<T>()?;
+        let aux_head = cur.read_u64::<T>()?;
+        let aux_tail = cur.read_u64::<T>()?;
+        let aux_offset = cur.read_u64::<T>()?;
+        let aux_size = cur.read_u64::<T>()?;
+        Ok(Self {
+            version,
+            compat_version,
+            index,
+            offset,
+            time_enabled,
+            time_running,
+            capabilities,
+            pmc_width,
+            time_shift,
+            time_mult,
+            time_offset,
+            time_zero,
+            size,
+            time_cycles,
+            time_mask,
+            data_head,
+            data_tail,
+            data_offset,
+            data_size,
+            aux_head,
+            aux_tail,
+            aux_offset,
+            aux_size,
+        })
+    }
+
+    /// Whether `time_shift`, `time_mult` and `time_offset` are valid.
+    pub fn cap_user_time(&self) -> bool {
+        self.capabilities & PERF_MMAP_CAP_USER_TIME != 0
+    }
+
+    /// Whether `time_zero` is valid.
+    pub fn cap_user_time_zero(&self) -> bool {
+        self.capabilities & PERF_MMAP_CAP_USER_TIME_ZERO != 0
+    }
+
+    /// Whether `time_cycles` and `time_mask` are valid.
+    pub fn cap_user_time_short(&self) -> bool {
+        self.capabilities & PERF_MMAP_CAP_USER_TIME_SHORT != 0
+    }
+}
+
+/// The direction in which the kernel writes records into the ring buffer.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum RingBufferDirection {
+    /// The kernel writes from lower to higher addresses, and user space
+    /// tells the kernel how far it has read by updating `data_tail`.
+    Forward,
+    /// The kernel writes from higher to lower addresses and overwrites old
+    /// records once the buffer is full. This is used for events with
+    /// [`AttrFlags::WRITE_BACKWARD`]. `data_tail` is not used.
+    Backward,
+}
+
+impl RingBufferDirection {
+    /// The direction used for an event with these attr flags.
+    pub fn from_attr_flags(flags: AttrFlags) -> Self {
+        if flags.contains(AttrFlags::WRITE_BACKWARD) {
+            Self::Backward
+        } else {
+            Self::Forward
+        }
+    }
+}
+
+/// A record in the ring buffer, with its header fields and its body.
+///
+/// The body is in the native endianness.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RingBufferRecord<'a> {
+    /// The record type.
+    pub record_type: RecordType,
+    /// The `misc` value from the record header.
+    pub misc: u16,
+    /// The record body, i.e. everything after the header. For a
+    /// [`BackwardSnapshot`], this is split if the record wraps around the end
+    /// of the ring buffer. [`MmapRingBuffer`] copies records, so they are
+    /// never split.
+    pub data: RawData<'a>,
+}
+
+impl<'a> RingBufferRecord<'a> {
+    /// Wrap this record into a [`RawEventRecord`] so that it can be parsed.
+    ///
+    /// The parse info must have been created with [`Endianness::NATIVE`](crate::Endianness::NATIVE).
+    pub fn to_raw_event_record(&self, parse_info: RecordParseInfo) -> RawEventRecord<'a> {
+        RawEventRecord::new(self.record_type, self.misc, self.data, parse_info)
+    }
+}
+
+/// A reader for the ring buffer of a perf event fd.
+///
+/// The memory consists of a [`PerfEventMmapPage`] followed by the data
+/// area, whose size is a power of two. The kernel appends records to the data
+/// area and publishes its write position in `data_head`. The reader consumes
+/// records and, in forward mode, releases the space they used by writing
+/// `data_tail`.
+///
+/// `data_head` is read with acquire ordering, so that the record bytes
+/// which the kernel wrote before updating it are visible, and `data_tail` is
+/// written with release ordering, so that all reads of a record are complete
+/// before the kernel can overwrite it.
+///
+/// The kernel can write to the data area at any time, so the reader never
+/// creates references to it. Each record is copied out of the data area, with
+/// raw pointer reads, into a buffer owned by the reader, and stays valid
+/// until the next call to [`MmapRingBuffer::next_record`]. Records which wrap
+/// around the end of the data area are copied into one piece as well, so
+/// their data is always [`RawData::Single`] and never [`RawData::Split`].
+///
+/// In backward mode, the kernel can overwrite records at any time, so the
+/// event should be paused with `PERF_EVENT_IOC_PAUSE_OUTPUT` while reading.
+#[derive(Debug)]
+pub struct MmapRingBuffer<'a> {
+    base: NonNull<u8>,
+    data_offset: usize,
+    data_size: u64,
+    direction: RingBufferDirection,
+    /// The position of the next record.
+    position: u64,
+    /// The end of the records which are known to be available. In forward
+    /// mode this is the last value read from `data_head`.
+    end: u64,
+    /// Forward mode: the last value written to `data_tail`.
+    /// Backward mode: the value of `data_head` at the start of the current batch.
+    last_published: u64,
+    /// The copy of the current record, or of the data area for
+    /// [`MmapRingBuffer::backward_snapshot`].
+    buffer: Vec<u8>,
+    _phantom: PhantomData<&'a mut [u8]>,
+}
+
+impl<'a> MmapRingBuffer<'a> {
+    /// Create a reader for a buffer which contains the mmap page and the data area.
+    ///
+    /// This is useful for reading a simulated or copied buffer. For a live
+    /// mmap which the kernel writes to, use [`MmapRingBuffer::from_raw_parts`].
+    pub fn new(buffer: &'a mut [u8], direction: RingBufferDirection) -> Result<Self, io::Error> {
+        let len = buffer.len();
+        let base = NonNull::from(buffer).cast::<u8>();
+        // Safety: The buffer is exclusively borrowed for 'a.
+        unsafe { Self::from_raw_parts(base, len, direction) }
+    }
+
+    /// Create a reader for the mmap'd memory of a perf event fd.
+    ///
+    /// In forward mode, reading starts at the current `data_tail`. In backward
+    /// mode, reading starts with the records that were written since the buffer
+    /// was created.
+    ///
+    /// # Safety
+    ///
+    /// `base` must point to `len` bytes which stay valid for `'a`, are aligned
+    /// to 8 bytes, and which are only written by the kernel (or by this reader)
+    /// while the `MmapRingBuffer` exists.
+    pub unsafe fn from_raw_parts(
+        base: NonNull<u8>,
+        len: usize,
+        direction: RingBufferDirection,
+    ) -> Result<Self, io::Error> {
+        if len < PerfEventMmapPage::STRUCT_SIZE || !base.cast::<u64>().as_ptr().is_aligned() {
+            return Err(io::ErrorKind::InvalidInput.into());
+        }
+        let data_offset = ptr::read_volatile(base.as_ptr().add(DATA_OFFSET_OFFSET) as *const u64);
+        let data_size = ptr::read_volatile(base.as_ptr().add(DATA_SIZE_OFFSET) as *const u64);
+        if !data_size.is_power_of_two()
+            || data_offset < PerfEventMmapPage::STRUCT_SIZE as u64
+            || data_offset
+                .checked_add(data_size)
+                .is_none_or(|end| end > len as u64)
+        {
+            return Err(io::ErrorKind::InvalidData.into());
+        }
+        let mut rb = Self {
+            base,
+            data_offset: data_offset as usize,
+            data_size,
+            direction,
+            position: 0,
+            end: 0,
+            last_published: 0,
+            buffer: Vec::new(),
+            _phantom: PhantomData,
+        };
+        if direction == RingBufferDirection::Forward {
+            let tail = rb.atomic_u64(DATA_TAIL_OFFSET).load(Ordering::Relaxed);
+            rb.position = tail;
+            rb.end = tail;
+            rb.last_published = tail;
+        }
+        Ok(rb)
+    }
+
+    /// The direction in which this buffer is written.
+    pub fn direction(&self) -> RingBufferDirection {
+        self.direction
+    }
+
+    /// The size of the data area, in bytes.
+    pub fn data_size(&self) -> u64 {
+        self.data_size
+    }
+
+    /// Read the current values of the mmap page.
+    ///
+    /// The `lock` seqlock is used to get a consistent snapshot of the
+    /// counter and time fields.
+    pub fn mmap_page(&self) -> PerfEventMmapPage {
+        let mut bytes = [0u8; PerfEventMmapPage::STRUCT_SIZE];
+        loop {
+            let seq = self.read_u32_volatile(LOCK_OFFSET);
+            fence(Ordering::Acquire);
+            for (i, chunk) in bytes.chunks_exact_mut(8).enumerate() {
+                // Safety: The offset is within the mmap page and aligned.
+                let word =
+                    unsafe { ptr::read_volatile(self.base.as_ptr().add(i * 8) as *const u64) };
+                chunk.copy_from_slice(&word.to_ne_bytes());
+            }
+            fence(Ordering::Acquire);
+            if self.read_u32_volatile(LOCK_OFFSET) == seq {
+                break;
+            }
+        }
+        PerfEventMmapPage::parse::<NativeEndian>(RawData::Single(&bytes)).unwrap()
+    }
+
+    /// Read `data_head` with acquire ordering.
+    pub fn data_head(&self) -> u64 {
+        self.atomic_u64(DATA_HEAD_OFFSET).load(Ordering::Acquire)
+    }
+
+    /// Read `data_tail`.
+    pub fn data_tail(&self) -> u64 {
+        self.atomic_u64(DATA_TAIL_OFFSET).load(Ordering::Relaxed)
+    }
+
+    /// Read `aux_head`, the kernel's write position in the AUX area, with
+    /// acquire ordering.
+    pub fn aux_head(&self) -> u64 {
+        self.atomic_u64(AUX_HEAD_OFFSET).load(Ordering::Acquire)
+    }
+
+    /// Read `aux_tail`.
+    pub fn aux_tail(&self) -> u64 {
+        self.atomic_u64(AUX_TAIL_OFFSET).load(Ordering::Relaxed)
+    }
+
+    /// Write `aux_tail` with release ordering, to tell the kernel that all
+    /// AUX data before this position has been consumed.
+    ///
+    /// This is only needed if the AUX area was mapped writable, i.e. if it is
+    /// not in overwrite mode.
+    pub fn set_aux_tail(&mut self, aux_tail: u64) {
+        self.atomic_u64(AUX_TAIL_OFFSET)
+            .store(aux_tail, Ordering::Release);
+    }
+
+    /// Take a snapshot of a buffer which is written in backward (overwrite)
+    /// mode, and recover all complete records from it, newest first.
+    ///
+    /// The data area is copied, so the event's output should be paused with
+    /// `PERF_EVENT_IOC_PAUSE_OUTPUT` while the snapshot is taken, otherwise
+    /// the kernel can overwrite records while they're being copied.
+    pub fn backward_snapshot(&mut self) -> BackwardSnapshot<'_> {
+        let data_head = self.data_head();
+        let mut buffer = std::mem::take(&mut self.buffer);
+        buffer.resize(self.data_size as usize, 0);
+        self.read_data(0, &mut buffer);
+        self.buffer = buffer;
+        BackwardSnapshot::new(&self.buffer, data_head)
+            .expect("data size is checked in the constructor")
+    }
+
+    /// Return the next record, or `None` if no complete record is available.
+    ///
+    /// In forward mode, the space of the previously returned record is
+    /// released to the kernel when this method is called. If a record header
+    /// is malformed, the remaining available data is skipped.
+    ///
+    /// In backward mode, each time the reader catches up, it picks up the
+    /// records which the kernel wrote since the last batch, newest first. If
+    /// more than the buffer size was written, the oldest records of the batch
+    /// have been overwritten and reading stops at the first incomplete record.
+    pub fn next_record(&mut self) -> Option<RingBufferRecord<'_>> {
+        match self.direction {
+            RingBufferDirection::Forward => {
+                if self.last_published != self.position {
+                    self.atomic_u64(DATA_TAIL_OFFSET)
+                        .store(self.position, Ordering::Release);
+                    self.last_published = self.position;
+                }
+                if self.position == self.end {
+                    self.end = self.data_head();
+                }
+            }
+            RingBufferDirection::Backward => {
+                if self.position == self.end {
+                    let head = self.data_head();
+                    let new_bytes = self.last_published.wrapping_sub(head);
+                    self.position = head;
+                    self.end = head.wrapping_add(new_bytes.min(self.data_size));
+                    self.last_published = head;
+                }
+            }
+        }
+        if self.position == self.end {
+            return None;
+        }
+
+        let available = self.end.wrapping_sub(self.position);
+        match self.record_size_at(self.position, available) {
+            Some(size) => {
+                let position = self.position;
+                self.position = position.wrapping_add(size);
+                Some(self.record_at(position, size))
+            }
+            None => {
+                self.position = self.end;
+                None
+            }
+        }
+    }
+
+    /// The size of the record at `position`, if its header is complete and
+    /// the record fits into `available` bytes.
+    fn record_size_at(&self, position: u64, available: u64) -> Option<u64> {
+        let available = available.min(self.data_size);
+        if available < RECORD_HEADER_SIZE as u64 {
+            return None;
+        }
+        let mut header = [0; RECORD_HEADER_SIZE];
+        self.read_data(position, &mut header);
+        record_size_at(&header, 0, available)
+    }
+
+    /// Copy the record at `position` into `self.buffer`.
+    fn record_at(&mut self, position: u64, size: u64) -> RingBufferRecord<'_> {
+        let mut buffer = std::mem::take(&mut self.buffer);
+        buffer.resize(size as usize, 0);
+        self.read_data(position, &mut buffer);
+        self.buffer = buffer;
+        record_at(&self.buffer, 0, size)
+    }
+
+    /// Copy `dest.len()` bytes, starting at the ring buffer position
+    /// `position`, out of the data area. `dest` must not be larger than the
+    /// data area.
+    fn read_data(&self, position: u64, dest: &mut [u8]) {
+        let data_size = self.data_size as usize;
+        assert!(dest.len() <= data_size);
+        let start = (position & (self.data_size - 1)) as usize;
+        let first_len = dest.len().min(data_size - start);
+        // Safety: The data area is within the buffer, as checked in the
+        // constructor, and both copies stay within it. Only raw pointer
+        // reads are used, because the kernel can write to the data area
+        // concurrently. In forward mode, the kernel doesn't write to the
+        // part between data_tail and data_head, which is the only part that
+        // is read.
+        unsafe {
+            let data = self.base.as_ptr().add(self.data_offset);
+            ptr::copy_nonoverlapping(data.add(start), dest.as_mut_ptr(), first_len);
+            ptr::copy_nonoverlapping(
+                data,
+                dest.as_mut_ptr().add(first_len),
+                dest.len() - first_len,
+            );
+        }
+    }
+
+    fn atomic_u64(&self, offset: usize) -> &AtomicU64 {
+        // Safety: The offset is within the mmap page and 8-byte aligned.
+        unsafe { AtomicU64::from_ptr(self.base.as_ptr().add(offset) as *mut u64) }
+    }
+
+    fn read_u32_volatile(&self, offset: usize) -> u32 {
+        // Safety: The offset is within the mmap page and 4-byte aligned.
+        unsafe { ptr::read_volatile(self.base.as_ptr().add(offset) as *const u32) }
+    }
+}
+
+/// The records in a snapshot of a ring buffer which was written in backward
+/// (overwrite) mode, i.e. for an event with [`AttrFlags::WRITE_BACKWARD`].
+///
+/// This is the typical setup for a "flight recorder": The kernel keeps
+/// overwriting the oldest records, and the buffer is only read when something
+/// interesting happens. Since the kernel writes backwards, the newest record
+/// starts at `data_head`, and walking forward from there visits older and
+/// older records. The walk ends when it has covered the entire buffer, or
+/// when it reaches the zeroed part of a buffer which hasn't filled up yet.
+///
+/// Once the buffer has wrapped around, the oldest record in the walk usually
+/// has its end overwritten by the newest record. The size of this partial
+/// record is reported by [`BackwardSnapshot::clobbered_bytes`].
+///
+/// The iterator yields the complete records, newest first.
+#[derive(Debug, Clone)]
+pub struct BackwardSnapshot<'a> {
+    data: &'a [u8],
+    position: u64,
+    end: u64,
+    clobbered_bytes: u64,
+}
+
+impl<'a> BackwardSnapshot<'a> {
+    /// Create the snapshot from a copy of the data area and the value of
+    /// `data_head` at the time the copy was taken.
+    ///
+    /// Fails if the size of `data` is not a power of two.
+    pub fn new(data: &'a [u8], data_head: u64) -> Result<Self, io::Error> {
+        if !data.len().is_power_of_two() {
+            return Err(io::ErrorKind::InvalidInput.into());
+        }
+
+        // The kernel's write position starts at zero and moves down, so it
+        // tells us how many bytes were written in total.
+        let limit = data_head.wrapping_neg().min(data.len() as u64);
+        let mut end = data_head;
+        let mut clobbered_bytes = 0;
+        loop {
+            let remaining = limit - end.wrapping_sub(data_head);
+            if remaining == 0 {
+                break;
+            }
+            if remaining >= 8 && ring_slice(data, end, 8).as_slice().iter().all(|&b| b == 0) {
+                // Never written.
+                break;
+            }
+            match record_size_at(data, end, remaining) {
+                Some(size) => end = end.wrapping_add(size),
+                None => {
+                    clobbered_bytes = remaining;
+                    break;
+                }
+            }
+        }
+
+        Ok(Self {
+            data,
+            position: data_head,
+            end,
+            clobbered_bytes,
+        })
+    }
+
+    /// The number of bytes at the end of the walk which belong to a record
+    /// that was partially overwritten, and which could not be recovered.
+    pub fn clobbered_bytes(&self) -> u64 {
+        self.clobbered_bytes
+    }
+
+    /// The number of bytes that the remaining complete records take up.
+    pub fn recoverable_bytes(&self) -> u64 {
+        self.end.wrapping_sub(self.position)
+    }
+}
+
+impl<'a> Iterator for BackwardSnapshot<'a> {
+    type Item = RingBufferRecord<'a>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.position == self.end {
+            return None;
+        }
+        let available = self.end.wrapping_sub(self.position);
+        let size = record_size_at(self.data, self.position, available)?;
+        let record = record_at(self.data, self.position, size);
+        self.position = self.position.wrapping_add(size);
+        Some(record)
+    }
+}
+
+/// Get `len` bytes starting at the ring buffer position `position`, which
+/// may wrap around the end of `data`. `data.len()` must be a power of two
+/// and not smaller than `len`.
+pub(crate) fn ring_slice(data: &[u8], position: u64, len: usize) -> RawData<'_> {
+    let start = (position & (data.len() as u64 - 1)) as usize;
+    if start + len <= data.len() {
+        RawData::Single(&data[start..start + len])
+    } else {
+        let first_len = data.len() - start;
+        RawData::Split(&data[start..], &data[..len - first_len])
+    }
+}
+
+/// The size of `perf_event_header`.
+const RECORD_HEADER_SIZE: usize = 8;
+
+pub(crate) fn record_size_at(data: &[u8], position: u64, available: u64) -> Option<u64> {
+    const HEADER_SIZE: u64 = RECORD_HEADER_SIZE as u64;
+    if available < HEADER_SIZE {
+        return None;
+    }
+    let mut header = ring_slice(data, position, RECORD_HEADER_SIZE);
+    let _type = header.read_u32::<NativeEndian>().ok()?;
+    let _misc = header.read_u16::<NativeEndian>().ok()?;
+    let size = u64::from(header.read_u16::<NativeEndian>().ok()?);
+    if size < HEADER_SIZE || size > available {
+        return None;
+    }
+    Some(size)
+}
+
+pub(crate) fn record_at(data: &[u8], position: u64, size: u64) -> RingBufferRecord<'_> {
+    let mut record = ring_slice(data, position, size as usize);
+    let record_type = record.read_u32::<NativeEndian>().unwrap();
+    let misc = record.read_u16::<NativeEndian>().unwrap();
+    let _size = record.read_u16::<NativeEndian>().unwrap();
+    RingBufferRecord {
+        record_type: RecordType(record_type),
+        misc,
+        data: record,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        BackwardSnapshot, MmapRingBuffer, RingBufferDirection, DATA_HEAD_OFFSET,
+        DATA_OFFSET_OFFSET, DATA_SIZE_OFFSET, DATA_TAIL_OFFSET,
+    };
+    use crate::{RawData, RecordType};
+    use std::ptr::NonNull;
+
+    const PAGE_SIZE: usize = 4096;
+    const DATA_SIZE: usize = 256;
+
+    /// A simulated mmap: one metadata page followed by the data area.
+    /// The "kernel" side writes through raw pointers, like the real kernel would.
+    struct SimulatedMmap {
+        buffer: Vec<u64>,
+    }
+
+    impl SimulatedMmap {
+        fn new() -> Self {
+            let mut sim = Self {
+                buffer: vec![0; (PAGE_SIZE + DATA_SIZE) / 8],
+            };
+            sim.write_u64(DATA_OFFSET_OFFSET, PAGE_SIZE as u64);
+            sim.write_u64(DATA_SIZE_OFFSET, DATA_SIZE as u64);
+            sim
+        }
+
+        fn base(&mut self) -> NonNull<u8> {
+            NonNull::new(self.buffer.as_mut_ptr() as *mut u8).unwrap()
+        }
+
+        fn write_u64(&mut self, offset: usize, value: u64) {
+            unsafe { (self.base().as_ptr().add(offset) as *mut u64).write_volatile(value) }
+        }
+
+        fn read_u64(&mut self, offset: usize) -> u64 {
+            unsafe { (self.base().as_ptr().add(offset) as *const u64).read_volatile() }
+        }
+
+        fn write_record(&mut self, position: u64, record_type: u32, body: &[u8]) -> u64 {
+            let size = 8 + body.len();
+            let mut bytes = Vec::with_capacity(size);
+            bytes.extend_from_slice(&record_type.to_ne_bytes());
+            bytes.extend_from_slice(&0u16.to_ne_bytes());
+            bytes.extend_from_slice(&(size as u16).to_ne_bytes());
+            bytes.extend_from_slice(body);
+            let base = self.base().as_ptr();
+            for (i, b) in bytes.into_iter().enumerate() {
+                let offset = (position as usize + i) % DATA_SIZE;
+                unsafe { base.add(PAGE_SIZE + offset).write(b) };
+            }
+            size as u64
+        }
+    }
+
+    #[test]
+    fn forward_with_wraparound() {
+        let mut sim = SimulatedMmap::new();
+        let start = 200u64;
+        sim.write_u64(DATA_HEAD_OFFSET, start);
+        sim.write_u64(DATA_TAIL_OFFSET, start);
+        let base = sim.base();
+        let len = PAGE_SIZE + DATA_SIZE;
+        let mut rb =
+            unsafe { MmapRingBuffer::from_raw_parts(base, len, RingBufferDirection::Forward) }
+                .unwrap();
+        assert!(rb.next_record().is_none());
+
+        let mut head = start;
+        head += sim.write_record(head, 3, &[1; 24]);
+        head += sim.write_record(head, 9, &[2; 40]);
+        sim.write_u64(DATA_HEAD_OFFSET, head);
+
+        let first = rb.next_record().unwrap();
+        assert_eq!(first.record_type, RecordType::COMM);
+        assert_eq!(first.data, RawData::Single(&[1; 24]));
+        let second = rb.next_record().unwrap();
+        assert_eq!(second.record_type, RecordType::SAMPLE);
+        // The record wraps around the end of the data area, but is copied.
+        assert_eq!(second.data, RawData::Single(&[2; 40]));
+        assert_eq!(sim.read_u64(DATA_TAIL_OFFSET), start + 32);
+        assert!(rb.next_record().is_none());
+        assert_eq!(sim.read_u64(DATA_TAIL_OFFSET), head);
+    }
+
+    #[test]
+    fn backward_reads_newest_first() {
+        let mut sim = SimulatedMmap::new();
+        let base = sim.base();
+        let len = PAGE_SIZE + DATA_SIZE;
+        let mut rb =
+            unsafe { MmapRingBuffer::from_raw_parts(base, len, RingBufferDirection::Backward) }
+                .unwrap();
+
+        // The kernel writes each record below the previous one.
+        let mut head = 0u64;
+        head = head.wrapping_sub(16);
+        sim.write_record(head, 3, &[1; 8]);
+        head = head.wrapping_sub(24);
+        sim.write_record(head, 4, &[2; 16]);
+        sim.write_u64(DATA_HEAD_OFFSET, head);
+
+        assert_eq!(rb.next_record().unwrap().record_type, RecordType::EXIT);
+        assert_eq!(rb.next_record().unwrap().record_type, RecordType::COMM);
+        assert!(rb.next_record().is_none());
+        assert_eq!(sim.read_u64(DATA_TAIL_OFFSET), 0);
+
+        head = head.wrapping_sub(16);
+        sim.write_record(head, 7, &[3; 8]);
+        sim.write_u64(DATA_HEAD_OFFSET, head);
+        assert_eq!(rb.next_record().unwrap().record_type, RecordType::FORK);
+        assert!(rb.next_record().is_none());
+    }
+
+    #[test]
+    fn backward_snapshot_after_wraparound() {
+        let mut data = vec![0u8; 64];
+        let mut head = 0u64;
+        for (record_type, size) in [(3u32, 16u16), (4, 24), (7, 32)] {
+            head = head.wrapping_sub(size as u64);
+            let mut bytes = Vec::new();
+            bytes.extend_from_slice(&record_type.to_ne_bytes());
+            bytes.extend_from_slice(&0u16.to_ne_bytes());
+            bytes.extend_from_slice(&size.to_ne_bytes());
+            bytes.resize(size as usize, record_type as u8);
+            for (i, b) in bytes.into_iter().enumerate() {
+                data[(head as usize + i) % 64] = b;
+            }
+        }
+
+        let snapshot = BackwardSnapshot::new(&data, head).unwrap();
+        // The end of the oldest record (COMM) was overwritten by the newest record (FORK).
+        assert_eq!(snapshot.clobbered_bytes(), 8);
+        assert_eq!(snapshot.recoverable_bytes(), 56);
+        let records: Vec<_> = snapshot.collect();
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].record_type, RecordType::FORK);
+        assert_eq!(records[0].data, RawData::Single(&[7; 24]));
+        assert_eq!(records[1].record_type, RecordType::EXIT);
+    }
+}
@@ -1,6 +1,6 @@
 use byteorder::ByteOrder;
 
-use crate::{BranchSampleFormat, CpuMode, RawData, RawDataU64, ReadFormat, SampleFormat};
+use crate::{BranchSampleFormat, CpuMode, RawData, RawDataU64, ReadFormatValues, SampleFormat};
 
 use super::{RecordParseInfo, Regs};
 
@@ -16,6 +16,8 @@ pub struct SampleRecord<'a> {
     pub tid: Option<i32>,
     pub cpu: Option<u32>,
     pub period: Option<u64>,
+    /// The counter values, if `SampleFormat::READ` was set.
+    pub read: Option<ReadFormatValues>,
     pub user_regs: Option<Regs<'a>>,
     pub user_stack: Option<(RawData<'a>, u64)>,
     pub callchain: Option<RawDataU64<'a>>,
@@ -102,34 +104,14 @@ impl<'a> SampleRecord<'a> {
             None
         };
 
-        if sample_format.contains(SampleFormat::READ) {
-            if read_format.contains(ReadFormat::GROUP) {
-                let _value = cur.read_u64::<T>()?;
-                if read_format.contains(ReadFormat::TOTAL_TIME_ENABLED) {
-                    let _time_enabled = cur.read_u64::<T>()?;
-                }
-                if read_format.contains(ReadFormat::TOTAL_TIME_RUNNING) {
-                    let _time_running = cur.read_u64::<T>()?;
-                }
-                if read_format.contains(ReadFormat::ID) {
-                    let _id = cur.read_u64::<T>()?;
-                }
-            } else {
-                let nr = cur.read_u64::<T>()?;
-                if read_format.contains(ReadFormat::TOTAL_TIME_ENABLED) {
-                    let _time_enabled = cur.read_u64::<T>()?;
-                }
-                if read_format.contains(ReadFormat::TOTAL_TIME_RUNNING) {
-                    let _time_running = cur.read_u64::<T>()?;
-                }
-                for _ in 0..nr {
-                    let _value = cur.read_u64::<T>()?;
-                    if read_format.contains(ReadFormat::ID) {
-                        let _id = cur.read_u64::<T>()?;
-                    }
-                }
-            }
-        }
+        let read = if sample_format.contains(SampleFormat::READ) {
+            Some(ReadFormatValues::parse_from_cursor::<T>(
+                &mut cur,
+                read_format,
+            )?)
+        } else {
+            None
+        };
 
         let callchain = if sample_format.contains(SampleFormat::CALLCHAIN) {
             let callchain_length = cur.read_u64::<T>()?;
@@ -252,6 +234,7 @@ impl<'a> SampleRecord<'a> {
             pid,
             tid,
             period,
+            read,
             intr_regs,
             phys_addr,
             data_page_size,
@@ -260,3 +243,51 @@ impl<'a> SampleRecord<'a> {
         })
     }
 }
+
+#[cfg(test)]
+mod test {
+    use super::SampleRecord;
+    use crate::{
+        Endianness, HardwareEventId, PerfEventAttr, PerfEventType, PmuTypeId, RawData, ReadFormat,
+        ReadFormatValue, RecordParseInfo, SampleFormat,
+    };
+    use byteorder::LittleEndian;
+
+    #[test]
+    fn group_read_values() {
+        let mut attr = PerfEventAttr::new(PerfEventType::Hardware(
+            HardwareEventId::CpuCycles,
+            PmuTypeId(0),
+        ));
+        attr.sample_format = SampleFormat::IP | SampleFormat::READ | SampleFormat::CALLCHAIN;
+        attr.read_format = ReadFormat::GROUP | ReadFormat::TOTAL_TIME_ENABLED | ReadFormat::ID;
+        let parse_info = RecordParseInfo::new(&attr, Endianness::LittleEndian);
+
+        // ip, then { nr, time_enabled, { value, id }[nr] }, then the callchain.
+        let words: [u64; 10] = [0x1234, 2, 500, 7, 1, 8, 2, 2, 0x10, 0x20];
+        let data: Vec<u8> = words.iter().flat_map(|w| w.to_le_bytes()).collect();
+        let sample =
+            SampleRecord::parse::<LittleEndian>(RawData::from(&data[..]), 0, &parse_info).unwrap();
+        let read = sample.read.unwrap();
+        assert_eq!(read.time_enabled, Some(500));
+        assert_eq!(read.time_running, None);
+        assert_eq!(
+            read.values,
+            vec![
+                ReadFormatValue {
+                    value: 7,
+                    id: Some(1),
+                    lost: None
+                },
+                ReadFormatValue {
+                    value: 8,
+                    id: Some(2),
+                    lost: None
+                },
+            ]
+        );
+        let callchain = sample.callchain.unwrap();
+        assert_eq!(callchain.len(), 2);
+        assert_eq!(callchain.get(1), Some(0x20));
+    }
+}
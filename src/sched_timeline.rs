@@ -0,0 +1,341 @@
+use std::collections::{HashMap, HashSet};
+
+use crate::{CommonData, ContextSwitchRecord, EventRecord, ForkOrExitRecord, TaskWasPreempted};
+
+/// A period during which a thread was running on a CPU.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RunningInterval {
+    pub pid: Option<i32>,
+    pub tid: i32,
+    /// The CPU, if the records have [`SampleFormat::CPU`](crate::SampleFormat::CPU).
+    pub cpu: Option<u32>,
+    pub start: u64,
+    pub end: u64,
+}
+
+/// Why a thread was not running.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum OffCpuReason {
+    /// The thread was still runnable when it was switched out, and was
+    /// waiting for a CPU.
+    Preempted,
+    /// The thread was sleeping, e.g. waiting for I/O or a lock.
+    Blocked,
+}
+
+impl From<TaskWasPreempted> for OffCpuReason {
+    fn from(preempted: TaskWasPreempted) -> Self {
+        match preempted {
+            TaskWasPreempted::Yes => Self::Preempted,
+            TaskWasPreempted::No => Self::Blocked,
+        }
+    }
+}
+
+/// A period between a thread being switched out and being switched back in.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct OffCpuInterval {
+    pub pid: Option<i32>,
+    pub tid: i32,
+    pub start: u64,
+    pub end: u64,
+    pub reason: OffCpuReason,
+}
+
+/// The total time a thread spent in each state.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ThreadSchedTimes {
+    pub running: u64,
+    pub preempted: u64,
+    pub blocked: u64,
+}
+
+/// Builds a [`SchedTimeline`] from SWITCH and SWITCH_CPU_WIDE records.
+///
+/// The records need [`SampleFormat::TID`](crate::SampleFormat::TID) and
+/// [`SampleFormat::TIME`](crate::SampleFormat::TIME), and should have
+/// [`SampleFormat::CPU`](crate::SampleFormat::CPU) for per-CPU occupancy.
+/// They must be fed in timestamp order, e.g. from an
+/// [`OrderedEventQueue`](crate::OrderedEventQueue).
+///
+/// Both kinds of switch records are emitted in the context of the thread
+/// which is switched, so only the direction, the preemption state and the
+/// [`CommonData`] of each record are used. The idle task (tid 0) is not
+/// tracked; gaps in a CPU's occupancy are idle time.
+///
+/// EXIT records should be fed in as well: a thread's final switch-out
+/// doesn't start an off-CPU interval, so that a later thread which reuses
+/// the tid doesn't inherit it.
+#[derive(Debug, Clone, Default)]
+pub struct SchedTimelineBuilder {
+    /// Threads which are running, with their pid, CPU and the time they were
+    /// switched in.
+    running: HashMap<i32, (Option<i32>, Option<u32>, u64)>,
+    /// Threads which are switched out, with their pid, the time they were
+    /// switched out and why.
+    off_cpu: HashMap<i32, (Option<i32>, u64, OffCpuReason)>,
+    /// Threads which have exited but haven't been switched out for the last
+    /// time yet.
+    exited: HashSet<i32>,
+    timeline: SchedTimeline,
+}
+
+impl SchedTimelineBuilder {
+    /// Create an empty builder.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Apply a context switch or EXIT record. Other records are ignored.
+    pub fn handle_record(&mut self, record: &EventRecord, common_data: &CommonData) {
+        match record {
+            EventRecord::ContextSwitch(record) => self.handle_context_switch(record, common_data),
+            EventRecord::Exit(record) => self.handle_exit(record),
+            _ => {}
+        }
+    }
+
+    /// Apply an EXIT record. The thread doesn't get an off-CPU interval for
+    /// the time after its last switch-out.
+    pub fn handle_exit(&mut self, record: &ForkOrExitRecord) {
+        // The kernel emits the EXIT record before the final switch-out, but
+        // be prepared for either order.
+        if self.off_cpu.remove(&record.tid).is_none() {
+            self.exited.insert(record.tid);
+        }
+    }
+
+    /// Apply a context switch record. Records without a tid or a timestamp
+    /// are ignored.
+    pub fn handle_context_switch(
+        &mut self,
+        record: &ContextSwitchRecord,
+        common_data: &CommonData,
+    ) {
+        let (Some(tid), Some(timestamp)) = (common_data.tid, common_data.timestamp) else {
+            return;
+        };
+        if tid == 0 {
+            return;
+        }
+        let pid = common_data.pid;
+        match record {
+            ContextSwitchRecord::In { .. } => {
+                if self.running.contains_key(&tid) {
+                    // A duplicate, e.g. from a per-thread and a CPU-wide event.
+                    return;
+                }
+                self.exited.remove(&tid);
+                if let Some((pid, start, reason)) = self.off_cpu.remove(&tid) {
+                    self.timeline.off_cpu_intervals.push(OffCpuInterval {
+                        pid,
+                        tid,
+                        start,
+                        end: timestamp,
+                        reason,
+                    });
+                }
+                self.running.insert(tid, (pid, common_data.cpu, timestamp));
+            }
+            ContextSwitchRecord::Out { preempted, .. } => {
+                if let Some((pid, cpu, start)) = self.running.remove(&tid) {
+                    self.timeline.running_intervals.push(RunningInterval {
+                        pid,
+                        tid,
+                        cpu,
+                        start,
+                        end: timestamp,
+                    });
+                }
+                if self.exited.remove(&tid) {
+                    return;
+                }
+                self.off_cpu
+                    .entry(tid)
+                    .or_insert((pid, timestamp, (*preempted).into()));
+            }
+        }
+    }
+
+    /// Finish the timeline. Threads which are still running are considered to
+    /// run until `end_time`. Threads which are still switched out are left
+    /// out, because they may have exited.
+    pub fn finish(mut self, end_time: u64) -> SchedTimeline {
+        for (tid, (pid, cpu, start)) in self.running {
+            self.timeline.running_intervals.push(RunningInterval {
+                pid,
+                tid,
+                cpu,
+                start,
+                end: end_time.max(start),
+            });
+        }
+        self.timeline
+            .running_intervals
+            .sort_by_key(|interval| (interval.start, interval.tid));
+        self.timeline
+    }
+}
+
+/// Per-thread running and off-CPU intervals, built by [`SchedTimelineBuilder`].
+///
+/// The off-CPU intervals, together with the stack of the sample taken at the
+/// start of each interval, are the input for off-CPU flame graphs.
+#[derive(Debug, Clone, Default)]
+pub struct SchedTimeline {
+    running_intervals: Vec<RunningInterval>,
+    off_cpu_intervals: Vec<OffCpuInterval>,
+}
+
+impl SchedTimeline {
+    /// All running intervals, ordered by start time.
+    pub fn running_intervals(&self) -> &[RunningInterval] {
+        &self.running_intervals
+    }
+
+    /// All off-CPU intervals, ordered by end time.
+    pub fn off_cpu_intervals(&self) -> &[OffCpuInterval] {
+        &self.off_cpu_intervals
+    }
+
+    /// The intervals during which threads were running on `cpu`, ordered by
+    /// start time.
+    pub fn cpu_occupancy(&self, cpu: u32) -> impl Iterator<Item = &RunningInterval> {
+        self.running_intervals
+            .iter()
+            .filter(move |interval| interval.cpu == Some(cpu))
+    }
+
+    /// The total time spent running, preempted and blocked, per thread.
+    pub fn thread_times(&self) -> HashMap<i32, ThreadSchedTimes> {
+        let mut times: HashMap<i32, ThreadSchedTimes> = HashMap::new();
+        for interval in &self.running_intervals {
+            times.entry(interval.tid).or_default().running +=
+                interval.end.saturating_sub(interval.start);
+        }
+        for interval in &self.off_cpu_intervals {
+            let entry = times.entry(interval.tid).or_default();
+            let duration = interval.end.saturating_sub(interval.start);
+            match interval.reason {
+                OffCpuReason::Preempted => entry.preempted += duration,
+                OffCpuReason::Blocked => entry.blocked += duration,
+            }
+        }
+        times
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{OffCpuReason, SchedTimelineBuilder, ThreadSchedTimes};
+    use crate::{CommonData, ContextSwitchRecord, ForkOrExitRecord, TaskWasPreempted};
+
+    fn switch_in(builder: &mut SchedTimelineBuilder, tid: i32, cpu: u32, time: u64) {
+        let record = ContextSwitchRecord::In {
+            prev_pid: None,
+            prev_tid: None,
+        };
+        switch(builder, &record, tid, cpu, time);
+    }
+
+    fn switch_out(
+        builder: &mut SchedTimelineBuilder,
+        tid: i32,
+        cpu: u32,
+        time: u64,
+        preempted: TaskWasPreempted,
+    ) {
+        let record = ContextSwitchRecord::Out {
+            next_pid: None,
+            next_tid: None,
+            preempted,
+        };
+        switch(builder, &record, tid, cpu, time);
+    }
+
+    fn switch(
+        builder: &mut SchedTimelineBuilder,
+        record: &ContextSwitchRecord,
+        tid: i32,
+        cpu: u32,
+        time: u64,
+    ) {
+        let common_data = CommonData {
+            pid: Some(tid),
+            tid: Some(tid),
+            timestamp: Some(time),
+            cpu: Some(cpu),
+            ..Default::default()
+        };
+        builder.handle_context_switch(record, &common_data);
+    }
+
+    #[test]
+    fn intervals_and_times() {
+        let mut builder = SchedTimelineBuilder::new();
+        switch_in(&mut builder, 1, 0, 100);
+        switch_in(&mut builder, 2, 1, 100);
+        switch_out(&mut builder, 1, 0, 150, TaskWasPreempted::Yes);
+        switch_out(&mut builder, 2, 1, 160, TaskWasPreempted::No);
+        switch_in(&mut builder, 1, 1, 170);
+        switch_in(&mut builder, 2, 0, 200);
+        let timeline = builder.finish(300);
+
+        assert_eq!(timeline.running_intervals().len(), 4);
+        let cpu1: Vec<_> = timeline
+            .cpu_occupancy(1)
+            .map(|interval| (interval.tid, interval.start, interval.end))
+            .collect();
+        assert_eq!(cpu1, [(2, 100, 160), (1, 170, 300)]);
+        assert_eq!(
+            timeline.off_cpu_intervals()[0].reason,
+            OffCpuReason::Preempted
+        );
+
+        let times = timeline.thread_times();
+        assert_eq!(
+            times[&1],
+            ThreadSchedTimes {
+                running: 180,
+                preempted: 20,
+                blocked: 0
+            }
+        );
+        assert_eq!(
+            times[&2],
+            ThreadSchedTimes {
+                running: 160,
+                preempted: 0,
+                blocked: 40
+            }
+        );
+    }
+
+    #[test]
+    fn reused_tid_after_exit() {
+        let exit = ForkOrExitRecord {
+            pid: 5,
+            ppid: 1,
+            tid: 5,
+            ptid: 1,
+            timestamp: 120,
+        };
+        let mut builder = SchedTimelineBuilder::new();
+        switch_in(&mut builder, 5, 0, 100);
+        builder.handle_exit(&exit);
+        switch_out(&mut builder, 5, 0, 130, TaskWasPreempted::No);
+        // A new thread with the same tid.
+        switch_in(&mut builder, 5, 1, 500);
+        switch_out(&mut builder, 5, 1, 600, TaskWasPreempted::No);
+
+        // The other order: switched out first, then the EXIT record.
+        switch_in(&mut builder, 6, 0, 100);
+        switch_out(&mut builder, 6, 0, 130, TaskWasPreempted::No);
+        builder.handle_exit(&ForkOrExitRecord { tid: 6, ..exit });
+        switch_in(&mut builder, 6, 1, 500);
+
+        let timeline = builder.finish(700);
+        assert_eq!(timeline.off_cpu_intervals(), []);
+        assert_eq!(timeline.running_intervals().len(), 4);
+    }
+}
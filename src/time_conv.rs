@@ -0,0 +1,257 @@
+use crate::constants::{PERF_MMAP_CAP_USER_TIME_SHORT, PERF_MMAP_CAP_USER_TIME_ZERO};
+use crate::{ClockData, ClockId, PerfEventMmapPage, TimeConvRecord};
+
+/// The parameters for converting between raw TSC values and perf timestamps,
+/// from a `PERF_RECORD_TIME_CONV` record or from the mmap page of an event.
+///
+/// This is needed for hardware trace data like Intel PT, whose timestamps are
+/// TSC values, and for correlating perf timestamps with TSC values which were
+/// read in user space.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct TscConversion {
+    pub time_shift: u16,
+    pub time_mult: u32,
+    pub time_zero: u64,
+    /// The TSC value at which `time_zero` was taken, if `cap_user_time_short` is set.
+    pub time_cycles: u64,
+    /// The mask for the TSC value, if `cap_user_time_short` is set.
+    pub time_mask: u64,
+    /// Whether the hardware counter is narrower than 64 bits, so that TSC
+    /// values need to be extended with `time_cycles` and `time_mask`.
+    pub cap_user_time_short: bool,
+}
+
+impl TscConversion {
+    /// Get the parameters from a TIME_CONV record. Returns `None` if the
+    /// record says that `time_zero` is not valid.
+    pub fn from_time_conv(record: &TimeConvRecord) -> Option<Self> {
+        let (time_cycles, time_mask, cap_user_time_short) = match record.time_short {
+            Some(short) if !short.cap_user_time_zero => return None,
+            Some(short) => (
+                short.time_cycles,
+                short.time_mask,
+                short.cap_user_time_short,
+            ),
+            None => (0, 0, false),
+        };
+        Self::new(
+            record.time_shift as u16,
+            record.time_mult as u32,
+            record.time_zero,
+            time_cycles,
+            time_mask,
+            cap_user_time_short,
+        )
+    }
+
+    /// Get the parameters from the mmap page of an event. Returns `None` if
+    /// the kernel doesn't provide `time_zero`.
+    pub fn from_mmap_page(page: &PerfEventMmapPage) -> Option<Self> {
+        if page.capabilities & PERF_MMAP_CAP_USER_TIME_ZERO == 0 {
+            return None;
+        }
+        Self::new(
+            page.time_shift,
+            page.time_mult,
+            page.time_zero,
+            page.time_cycles,
+            page.time_mask,
+            page.capabilities & PERF_MMAP_CAP_USER_TIME_SHORT != 0,
+        )
+    }
+
+    fn new(
+        time_shift: u16,
+        time_mult: u32,
+        time_zero: u64,
+        time_cycles: u64,
+        time_mask: u64,
+        cap_user_time_short: bool,
+    ) -> Option<Self> {
+        if time_mult == 0 || time_shift >= 64 {
+            return None;
+        }
+        Some(Self {
+            time_shift,
+            time_mult,
+            time_zero,
+            time_cycles,
+            time_mask,
+            cap_user_time_short,
+        })
+    }
+
+    /// Convert a TSC value into a perf timestamp, in nanoseconds.
+    ///
+    /// This uses the same calculation as the kernel, so the results match
+    /// the timestamps in the records exactly.
+    pub fn tsc_to_perf_time(&self, tsc: u64) -> u64 {
+        let tsc = if self.cap_user_time_short {
+            self.time_cycles
+                .wrapping_add(tsc.wrapping_sub(self.time_cycles) & self.time_mask)
+        } else {
+            tsc
+        };
+        let shift = u32::from(self.time_shift);
+        let mult = u64::from(self.time_mult);
+        let quot = tsc >> shift;
+        let rem = tsc & ((1u64 << shift) - 1);
+        self.time_zero
+            .wrapping_add(quot.wrapping_mul(mult))
+            .wrapping_add(rem.wrapping_mul(mult) >> shift)
+    }
+
+    /// Convert a perf timestamp, in nanoseconds, into a TSC value.
+    pub fn perf_time_to_tsc(&self, time: u64) -> u64 {
+        let shift = u32::from(self.time_shift);
+        let mult = u64::from(self.time_mult);
+        let t = time.wrapping_sub(self.time_zero);
+        let quot = t / mult;
+        let rem = t % mult;
+        (quot << shift).wrapping_add((rem << shift) / mult)
+    }
+}
+
+/// A reference point for converting perf timestamps into Unix time, from the
+/// `CLOCK_DATA` feature section.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct WallClockReference {
+    /// The clock which was used for the perf timestamps, if it is known.
+    pub clock_id: Option<ClockId>,
+    /// The perf timestamp at the reference point, in nanoseconds.
+    pub perf_time_ns: u64,
+    /// The Unix time at the reference point, in nanoseconds.
+    pub unix_time_ns: u64,
+}
+
+impl WallClockReference {
+    /// Get the reference point from the `CLOCK_DATA` feature section.
+    pub fn from_clock_data(clock_data: &ClockData) -> Self {
+        Self {
+            clock_id: ClockId::from_u32(clock_data.clockid),
+            perf_time_ns: clock_data.clockid_time_ns,
+            unix_time_ns: clock_data.wall_clock_ns,
+        }
+    }
+
+    /// Convert a perf timestamp into Unix time, in nanoseconds. Returns
+    /// `None` if the result is before 1970 or out of range.
+    pub fn perf_time_to_unix_ns(&self, time: u64) -> Option<u64> {
+        let delta = i128::from(time) - i128::from(self.perf_time_ns);
+        u64::try_from(i128::from(self.unix_time_ns) + delta).ok()
+    }
+
+    /// Convert a Unix time, in nanoseconds, into a perf timestamp. Returns
+    /// `None` if the time is before the start of the perf clock.
+    pub fn unix_ns_to_perf_time(&self, unix_ns: u64) -> Option<u64> {
+        let delta = i128::from(unix_ns) - i128::from(self.unix_time_ns);
+        u64::try_from(i128::from(self.perf_time_ns) + delta).ok()
+    }
+}
+
+/// Converts between TSC values, perf timestamps and Unix time.
+///
+/// Both parts are optional: TSC conversion needs a TIME_CONV record (or an
+/// mmap page), and Unix time needs the `CLOCK_DATA` feature section, which
+/// `perf record` writes when a clock is selected with `-k`.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct TimeConverter {
+    pub tsc: Option<TscConversion>,
+    pub wall_clock: Option<WallClockReference>,
+}
+
+impl TimeConverter {
+    /// Create a converter from the TSC parameters and the wall clock
+    /// reference, if they are known.
+    pub fn new(tsc: Option<TscConversion>, wall_clock: Option<WallClockReference>) -> Self {
+        Self { tsc, wall_clock }
+    }
+
+    /// Convert a TSC value into a perf timestamp, in nanoseconds. Returns
+    /// `None` without TSC parameters.
+    pub fn tsc_to_perf_time(&self, tsc: u64) -> Option<u64> {
+        Some(self.tsc?.tsc_to_perf_time(tsc))
+    }
+
+    /// Convert a perf timestamp, in nanoseconds, into a TSC value. Returns
+    /// `None` without TSC parameters.
+    pub fn perf_time_to_tsc(&self, time: u64) -> Option<u64> {
+        Some(self.tsc?.perf_time_to_tsc(time))
+    }
+
+    /// Convert a perf timestamp into Unix time, both in nanoseconds. Returns
+    /// `None` without a wall clock reference, or if the result is out of range.
+    pub fn perf_time_to_unix_ns(&self, time: u64) -> Option<u64> {
+        self.wall_clock?.perf_time_to_unix_ns(time)
+    }
+
+    /// Convert a Unix time into a perf timestamp, both in nanoseconds. Returns
+    /// `None` without a wall clock reference, or if the result is out of range.
+    pub fn unix_ns_to_perf_time(&self, unix_ns: u64) -> Option<u64> {
+        self.wall_clock?.unix_ns_to_perf_time(unix_ns)
+    }
+
+    /// Convert a TSC value into Unix time, in nanoseconds, via the perf
+    /// timestamp. Returns `None` unless both conversions are available.
+    pub fn tsc_to_unix_ns(&self, tsc: u64) -> Option<u64> {
+        self.perf_time_to_unix_ns(self.tsc_to_perf_time(tsc)?)
+    }
+
+    /// Convert a Unix time, in nanoseconds, into a TSC value, via the perf
+    /// timestamp. Returns `None` unless both conversions are available.
+    pub fn unix_ns_to_tsc(&self, unix_ns: u64) -> Option<u64> {
+        self.perf_time_to_tsc(self.unix_ns_to_perf_time(unix_ns)?)
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{TimeConverter, TscConversion, WallClockReference};
+    use crate::{ClockData, ClockId, TimeConvRecord, TimeConvShort};
+
+    #[test]
+    fn round_trips() {
+        // 2 cycles per nanosecond.
+        let record = TimeConvRecord {
+            time_shift: 10,
+            time_mult: 512,
+            time_zero: 1000,
+            time_short: Some(TimeConvShort {
+                time_cycles: 0x1_0000_0000,
+                time_mask: 0xffff_ffff,
+                cap_user_time_zero: true,
+                cap_user_time_short: false,
+            }),
+        };
+        let tsc = TscConversion::from_time_conv(&record).unwrap();
+        assert_eq!(tsc.tsc_to_perf_time(2048 + 3), 2025);
+        assert_eq!(tsc.perf_time_to_tsc(2025), 2050);
+
+        let short = TscConversion {
+            cap_user_time_short: true,
+            ..tsc
+        };
+        // The upper bits come from time_cycles.
+        assert_eq!(
+            short.tsc_to_perf_time(0x10),
+            tsc.tsc_to_perf_time(0x1_0000_0010)
+        );
+
+        let wall_clock = WallClockReference::from_clock_data(&ClockData {
+            clockid: 1,
+            wall_clock_ns: 1_700_000_000_000_000_000,
+            clockid_time_ns: 5000,
+        });
+        assert_eq!(wall_clock.clock_id, Some(ClockId::Monotonic));
+        let converter = TimeConverter::new(Some(tsc), Some(wall_clock));
+        assert_eq!(
+            converter.tsc_to_unix_ns(2048 * 4),
+            Some(1_700_000_000_000_000_000 + 1000 + 4096 - 5000)
+        );
+        assert_eq!(
+            converter.unix_ns_to_tsc(1_700_000_000_000_000_000 + 1000 + 4096 - 5000),
+            Some(2048 * 4)
+        );
+        assert_eq!(wall_clock.unix_ns_to_perf_time(0), None);
+    }
+}
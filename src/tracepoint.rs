@@ -0,0 +1,690 @@
+use std::io;
+
+use crate::Endianness;
+
+/// An error that can occur when parsing a tracepoint format description or
+/// decoding a tracepoint payload with it.
+#[derive(thiserror::Error, Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum TracepointFormatError {
+    /// The format doesn't have a `name:` line.
+    #[error("The format has no name")]
+    MissingName,
+
+    /// The format doesn't have a valid `ID:` line.
+    #[error("The format has no valid ID")]
+    MissingId,
+
+    /// A `field:` line couldn't be parsed.
+    #[error("Invalid field description: {0}")]
+    InvalidField(String),
+
+    /// The payload is too short for this field, or a dynamic field points
+    /// outside of the payload.
+    #[error("Field {0} is out of bounds")]
+    FieldOutOfBounds(String),
+
+    /// The format is for a different tracepoint than the one being decoded.
+    #[error("Expected the format of {expected}, got {actual}")]
+    WrongTracepoint {
+        expected: &'static str,
+        actual: String,
+    },
+
+    /// The format doesn't have a field which is needed for decoding.
+    #[error("The format has no field {0}")]
+    MissingField(String),
+
+    /// A field has a different type or size than expected, e.g. because its
+    /// definition changed in a newer kernel.
+    #[error("Field {0} has an unexpected layout")]
+    IncompatibleField(String),
+
+    /// The `common_type` of the payload doesn't match the ID of the format,
+    /// i.e. the payload belongs to a different tracepoint, or the format was
+    /// read from a different kernel than the one which recorded the payload.
+    #[error("Expected tracepoint ID {expected}, got {actual}")]
+    WrongTracepointId { expected: u64, actual: u64 },
+}
+
+/// How the value of a [`TracepointField`] is stored.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TracepointFieldKind {
+    /// An integer (or pointer) of `size` bytes.
+    Integer,
+    /// A fixed-size array with `len` elements of `size / len` bytes each,
+    /// e.g. `char comm[16]`.
+    Array { len: usize },
+    /// `__data_loc`: A 32-bit value whose lower 16 bits are the offset of the
+    /// data from the start of the payload, and whose upper 16 bits are the
+    /// length of the data. Used for strings and dynamic arrays.
+    DataLoc,
+    /// `__rel_loc`: Like `__data_loc`, but the offset is relative to the end
+    /// of this field.
+    RelLoc,
+    /// A field with a size that doesn't fit any of the above, e.g. a struct.
+    Other,
+}
+
+/// A field in a tracepoint payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TracepointField {
+    pub name: String,
+    /// The C type, without the field name and without array dimensions, e.g.
+    /// `unsigned short`, `char` for `char comm[16]`, or `__data_loc char[]`.
+    pub type_name: String,
+    /// The offset of the field in the payload, in bytes.
+    pub offset: usize,
+    /// The size of the field in the payload, in bytes.
+    pub size: usize,
+    pub is_signed: bool,
+    pub kind: TracepointFieldKind,
+}
+
+/// A decoded tracepoint field value.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TracepointValue<'a> {
+    Signed(i64),
+    Unsigned(u64),
+    /// A `char` array or a `char` `__data_loc` / `__rel_loc` field, up to the
+    /// first nul byte.
+    Str(&'a [u8]),
+    SignedArray(Vec<i64>),
+    UnsignedArray(Vec<u64>),
+    /// The bytes of a field that can't be decoded into one of the above.
+    Bytes(&'a [u8]),
+}
+
+impl TracepointValue<'_> {
+    /// The value as an integer, if it is one. Unsigned values are
+    /// reinterpreted.
+    pub fn as_i64(&self) -> Option<i64> {
+        match *self {
+            Self::Signed(value) => Some(value),
+            Self::Unsigned(value) => Some(value as i64),
+            _ => None,
+        }
+    }
+
+    /// The value as an integer, if it is one. Signed values are
+    /// reinterpreted.
+    pub fn as_u64(&self) -> Option<u64> {
+        match *self {
+            Self::Signed(value) => Some(value as u64),
+            Self::Unsigned(value) => Some(value),
+            _ => None,
+        }
+    }
+
+    /// The string value, if this is a string.
+    pub fn as_str(&self) -> Option<&[u8]> {
+        match *self {
+            Self::Str(s) => Some(s),
+            _ => None,
+        }
+    }
+}
+
+impl TracepointField {
+    /// Parse a line like
+    /// `field:unsigned short common_type; offset:0; size:2; signed:0;`.
+    fn parse(line: &str) -> Result<Self, TracepointFormatError> {
+        let invalid = || TracepointFormatError::InvalidField(line.trim().to_owned());
+        let mut declaration = None;
+        let mut offset = None;
+        let mut size = None;
+        let mut is_signed = false;
+        for part in line.split(';') {
+            let Some((key, value)) = part.trim().split_once(':') else {
+                continue;
+            };
+            match key {
+                "field" => declaration = Some(value),
+                "offset" => offset = value.parse().ok(),
+                "size" => size = value.parse().ok(),
+                "signed" => is_signed = value == "1",
+                _ => {}
+            }
+        }
+        let (declaration, offset, size) = (
+            declaration.ok_or_else(invalid)?,
+            offset.ok_or_else(invalid)?,
+            size.ok_or_else(invalid)?,
+        );
+
+        // Split off the array dimension, then the name.
+        let declaration = declaration.trim();
+        let (declaration, array_len) = match declaration.strip_suffix(']') {
+            Some(rest) => {
+                let open = rest.rfind('[').ok_or_else(invalid)?;
+                (&rest[..open], Some(&rest[open + 1..]))
+            }
+            None => (declaration, None),
+        };
+        let name_start = declaration
+            .rfind(|c: char| !(c.is_ascii_alphanumeric() || c == '_'))
+            .map_or(0, |i| i + 1);
+        let name = &declaration[name_start..];
+        if name.is_empty() {
+            return Err(invalid());
+        }
+        let type_name = declaration[..name_start].trim();
+
+        let kind = if type_name.starts_with("__data_loc") {
+            TracepointFieldKind::DataLoc
+        } else if type_name.starts_with("__rel_loc") {
+            TracepointFieldKind::RelLoc
+        } else if let Some(array_len) = array_len {
+            match array_len.parse() {
+                Ok(len) if len > 0 && size % len == 0 => TracepointFieldKind::Array { len },
+                _ => TracepointFieldKind::Other,
+            }
+        } else if matches!(size, 1 | 2 | 4 | 8) {
+            TracepointFieldKind::Integer
+        } else {
+            TracepointFieldKind::Other
+        };
+
+        Ok(Self {
+            name: name.to_owned(),
+            type_name: type_name.to_owned(),
+            offset,
+            size,
+            is_signed,
+            kind,
+        })
+    }
+
+    /// Whether the elements of this field are `char`s, i.e. whether it's a string.
+    pub(crate) fn is_char(&self) -> bool {
+        let element_type = self
+            .type_name
+            .trim_start_matches("__data_loc")
+            .trim_start_matches("__rel_loc")
+            .trim_end_matches("[]")
+            .trim();
+        matches!(element_type, "char" | "const char")
+    }
+
+    /// Decode this field from a tracepoint payload, i.e. from the
+    /// `SampleRecord::raw` data of a tracepoint sample.
+    pub fn decode<'a>(
+        &self,
+        data: &'a [u8],
+        endian: Endianness,
+    ) -> Result<TracepointValue<'a>, TracepointFormatError> {
+        let out_of_bounds = || TracepointFormatError::FieldOutOfBounds(self.name.clone());
+        // The offset and the size can come from untrusted format text.
+        let end = self
+            .offset
+            .checked_add(self.size)
+            .ok_or_else(out_of_bounds)?;
+        let bytes = data.get(self.offset..end).ok_or_else(out_of_bounds)?;
+        let value = match self.kind {
+            TracepointFieldKind::Integer => {
+                let value = read_uint(bytes, endian);
+                if self.is_signed {
+                    TracepointValue::Signed(sign_extend(value, self.size))
+                } else {
+                    TracepointValue::Unsigned(value)
+                }
+            }
+            TracepointFieldKind::Array { .. } if self.is_char() => {
+                TracepointValue::Str(until_nul(bytes))
+            }
+            TracepointFieldKind::Array { len } => {
+                let element_size = self.size / len;
+                if !matches!(element_size, 1 | 2 | 4 | 8) {
+                    return Ok(TracepointValue::Bytes(bytes));
+                }
+                let elements = bytes
+                    .chunks_exact(element_size)
+                    .map(|element| read_uint(element, endian));
+                if self.is_signed {
+                    let elements = elements.map(|value| sign_extend(value, element_size));
+                    TracepointValue::SignedArray(elements.collect())
+                } else {
+                    TracepointValue::UnsignedArray(elements.collect())
+                }
+            }
+            TracepointFieldKind::DataLoc | TracepointFieldKind::RelLoc => {
+                if self.size != 4 {
+                    return Ok(TracepointValue::Bytes(bytes));
+                }
+                let location = read_uint(bytes, endian);
+                let mut start = (location & 0xffff) as usize;
+                let len = (location >> 16) as usize;
+                if self.kind == TracepointFieldKind::RelLoc {
+                    start += end;
+                }
+                let dynamic = data.get(start..start + len).ok_or_else(out_of_bounds)?;
+                if self.is_char() {
+                    TracepointValue::Str(until_nul(dynamic))
+                } else {
+                    TracepointValue::Bytes(dynamic)
+                }
+            }
+            TracepointFieldKind::Other => TracepointValue::Bytes(bytes),
+        };
+        Ok(value)
+    }
+}
+
+/// The format of a tracepoint's payload, parsed from the text in
+/// `/sys/kernel/tracing/events/<system>/<name>/format` or from the event
+/// formats in the `TRACING_DATA` section of a perf.data file.
+///
+/// The payload of a tracepoint sample is in `SampleRecord::raw`; its first
+/// field, `common_type`, is the tracepoint's ID, which is also the
+/// [`PerfEventType::Tracepoint`](crate::PerfEventType::Tracepoint) value in
+/// the attr.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TracepointFormat {
+    /// The subsystem, e.g. `sched`, if known. The format text doesn't contain
+    /// it; it comes from the directory name or from the tracing data.
+    pub system: Option<String>,
+    /// The tracepoint name, e.g. `sched_switch`.
+    pub name: String,
+    /// The tracepoint ID.
+    pub id: u64,
+    /// All fields, including the `common_` fields, in payload order.
+    pub fields: Vec<TracepointField>,
+    /// The format string and arguments which the kernel uses to print the event.
+    pub print_fmt: String,
+}
+
+impl TracepointFormat {
+    /// Parse the text of a `format` file.
+    pub fn parse(text: &str) -> Result<Self, TracepointFormatError> {
+        let mut name = None;
+        let mut id = None;
+        let mut fields = Vec::new();
+        let mut print_fmt = String::new();
+        for line in text.lines() {
+            let trimmed = line.trim();
+            if let Some(value) = trimmed.strip_prefix("name:") {
+                name = Some(value.trim().to_owned());
+            } else if let Some(value) = trimmed.strip_prefix("ID:") {
+                id = Some(
+                    value
+                        .trim()
+                        .parse()
+                        .map_err(|_| TracepointFormatError::MissingId)?,
+                );
+            } else if trimmed.starts_with("field:") {
+                fields.push(TracepointField::parse(trimmed)?);
+            } else if let Some(value) = trimmed.strip_prefix("print fmt:") {
+                print_fmt = value.trim().to_owned();
+            }
+        }
+        Ok(Self {
+            system: None,
+            name: name.ok_or(TracepointFormatError::MissingName)?,
+            id: id.ok_or(TracepointFormatError::MissingId)?,
+            fields,
+            print_fmt,
+        })
+    }
+
+    /// The field with this name.
+    pub fn field(&self, name: &str) -> Option<&TracepointField> {
+        self.fields.iter().find(|field| field.name == name)
+    }
+
+    /// The fields which are specific to this tracepoint, i.e. all fields
+    /// except the `common_` ones.
+    pub fn event_fields(&self) -> impl Iterator<Item = &TracepointField> {
+        self.fields
+            .iter()
+            .filter(|field| !field.name.starts_with("common_"))
+    }
+
+    /// Decode all fields of a payload.
+    pub fn decode<'a>(
+        &self,
+        data: &'a [u8],
+        endian: Endianness,
+    ) -> Result<Vec<(&str, TracepointValue<'a>)>, TracepointFormatError> {
+        self.fields
+            .iter()
+            .map(|field| Ok((field.name.as_str(), field.decode(data, endian)?)))
+            .collect()
+    }
+
+    /// Decode a single field of a payload. Returns `Ok(None)` if the format
+    /// has no field with this name.
+    pub fn decode_field<'a>(
+        &self,
+        name: &str,
+        data: &'a [u8],
+        endian: Endianness,
+    ) -> Result<Option<TracepointValue<'a>>, TracepointFormatError> {
+        self.field(name)
+            .map(|field| field.decode(data, endian))
+            .transpose()
+    }
+}
+
+/// The contents of the `TRACING_DATA` feature section, or of a
+/// `PERF_RECORD_HEADER_TRACING_DATA` record in pipe mode.
+///
+/// This is the format which perf shares with trace-cmd: a header, followed
+/// by the format files of the ftrace events and of the events which were
+/// recorded, followed by kernel symbols, printk formats and saved command
+/// lines.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TracingData<'a> {
+    /// The format version, e.g. `0.6`.
+    pub version: &'a [u8],
+    /// The endianness of the recording machine.
+    pub endian: Endianness,
+    /// The size of a `long` on the recording machine.
+    pub long_size: u8,
+    pub page_size: u32,
+    /// The `events/header_page` file.
+    pub header_page: &'a [u8],
+    /// The `events/header_event` file.
+    pub header_event: &'a [u8],
+    /// The format files of the `ftrace` system.
+    pub ftrace_formats: Vec<&'a [u8]>,
+    /// The format files of the recorded events, with their system name.
+    pub event_formats: Vec<(&'a [u8], &'a [u8])>,
+    /// The contents of `/proc/kallsyms`, if it was included.
+    pub kallsyms: &'a [u8],
+    /// The `printk_formats` file.
+    pub printk_formats: &'a [u8],
+    /// The `saved_cmdlines` file, for version 0.6 and above.
+    pub saved_cmdlines: Option<&'a [u8]>,
+}
+
+impl<'a> TracingData<'a> {
+    const MAGIC: &'static [u8] = b"\x17\x08\x44tracing";
+
+    pub fn parse(data: &'a [u8]) -> Result<Self, io::Error> {
+        let mut cur = SliceCursor {
+            data,
+            endian: Endianness::LittleEndian,
+        };
+        if cur.bytes(Self::MAGIC.len())? != Self::MAGIC {
+            return Err(io::Error::new(
+                io::ErrorKind::InvalidData,
+                "Invalid tracing data magic",
+            ));
+        }
+        let version = cur.c_string()?;
+        cur.endian = match cur.bytes(1)?[0] {
+            0 => Endianness::LittleEndian,
+            _ => Endianness::BigEndian,
+        };
+        let long_size = cur.bytes(1)?[0];
+        let page_size = cur.u32()?;
+
+        // "header_page\0", u64 size, data; "header_event\0", u64 size, data.
+        cur.bytes(b"header_page\0".len())?;
+        let header_page = cur.u64_sized()?;
+        cur.bytes(b"header_event\0".len())?;
+        let header_event = cur.u64_sized()?;
+
+        let ftrace_count = cur.u32()?;
+        let ftrace_formats = (0..ftrace_count)
+            .map(|_| cur.u64_sized())
+            .collect::<Result<_, _>>()?;
+
+        let system_count = cur.u32()?;
+        let mut event_formats = Vec::new();
+        for _ in 0..system_count {
+            let system = cur.c_string()?;
+            let event_count = cur.u32()?;
+            for _ in 0..event_count {
+                event_formats.push((system, cur.u64_sized()?));
+            }
+        }
+
+        let kallsyms_size = cur.u32()?;
+        let kallsyms = cur.bytes(kallsyms_size as usize)?;
+        let printk_size = cur.u32()?;
+        let printk_formats = cur.bytes(printk_size as usize)?;
+        let saved_cmdlines = if version_at_least(version, (0, 6)) && !cur.data.is_empty() {
+            Some(cur.u64_sized()?)
+        } else {
+            None
+        };
+
+        Ok(Self {
+            version,
+            endian: cur.endian,
+            long_size,
+            page_size,
+            header_page,
+            header_event,
+            ftrace_formats,
+            event_formats,
+            kallsyms,
+            printk_formats,
+            saved_cmdlines,
+        })
+    }
+
+    /// Parse the formats of the recorded events. The formats get their
+    /// system name from the tracing data.
+    pub fn parse_event_formats(&self) -> Result<Vec<TracepointFormat>, TracepointFormatError> {
+        self.event_formats
+            .iter()
+            .map(|&(system, format)| {
+                let mut format = TracepointFormat::parse(&String::from_utf8_lossy(format))?;
+                format.system = Some(String::from_utf8_lossy(system).into_owned());
+                Ok(format)
+            })
+            .collect()
+    }
+}
+
+/// A cursor for data whose endianness is only known after reading a part of it.
+struct SliceCursor<'a> {
+    data: &'a [u8],
+    endian: Endianness,
+}
+
+impl<'a> SliceCursor<'a> {
+    fn bytes(&mut self, len: usize) -> Result<&'a [u8], io::Error> {
+        if self.data.len() < len {
+            return Err(io::ErrorKind::UnexpectedEof.into());
+        }
+        let (bytes, rest) = self.data.split_at(len);
+        self.data = rest;
+        Ok(bytes)
+    }
+
+    fn c_string(&mut self) -> Result<&'a [u8], io::Error> {
+        let len = memchr::memchr(0, self.data).ok_or(io::ErrorKind::UnexpectedEof)?;
+        let s = self.bytes(len)?;
+        self.bytes(1)?;
+        Ok(s)
+    }
+
+    fn u32(&mut self) -> Result<u32, io::Error> {
+        Ok(read_uint(self.bytes(4)?, self.endian) as u32)
+    }
+
+    /// A u64 size, followed by that many bytes.
+    fn u64_sized(&mut self) -> Result<&'a [u8], io::Error> {
+        let size = read_uint(self.bytes(8)?, self.endian);
+        let size = usize::try_from(size).map_err(|_| io::ErrorKind::InvalidData)?;
+        self.bytes(size)
+    }
+}
+
+/// Whether a tracing data version like `0.6` is at least `(major, minor)`.
+/// Versions which can't be parsed count as older.
+fn version_at_least(version: &[u8], (major, minor): (u32, u32)) -> bool {
+    let parsed = std::str::from_utf8(version).ok().and_then(|version| {
+        let (major, minor) = version.split_once('.')?;
+        Some((major.parse::<u32>().ok()?, minor.parse::<u32>().ok()?))
+    });
+    parsed.is_some_and(|version| version >= (major, minor))
+}
+
+/// Read an unsigned integer of 1, 2, 4 or 8 bytes.
+fn read_uint(bytes: &[u8], endian: Endianness) -> u64 {
+    let mut buf = [0; 8];
+    match endian {
+        Endianness::LittleEndian => {
+            buf[..bytes.len()].copy_from_slice(bytes);
+            u64::from_le_bytes(buf)
+        }
+        Endianness::BigEndian => {
+            buf[8 - bytes.len()..].copy_from_slice(bytes);
+            u64::from_be_bytes(buf)
+        }
+    }
+}
+
+fn sign_extend(value: u64, size: usize) -> i64 {
+    let shift = 64 - 8 * size as u32;
+    ((value << shift) as i64) >> shift
+}
+
+fn until_nul(bytes: &[u8]) -> &[u8] {
+    match memchr::memchr(0, bytes) {
+        Some(len) => &bytes[..len],
+        None => bytes,
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use super::{
+        version_at_least, TracepointField, TracepointFieldKind, TracepointFormat,
+        TracepointFormatError, TracepointValue, TracingData,
+    };
+    use crate::Endianness;
+
+    const SCHED_SWITCH: &str = include_str!("../tests/fixtures/tracepoint/sched_switch.format");
+    const SCHED_PROCESS_EXEC: &str =
+        include_str!("../tests/fixtures/tracepoint/sched_process_exec.format");
+    const FOO_REL_LOC: &str = include_str!("../tests/fixtures/tracepoint/foo_rel_loc.format");
+
+    fn common_header(id: u16, pid: i32) -> Vec<u8> {
+        let mut data = Vec::new();
+        data.extend_from_slice(&id.to_le_bytes());
+        data.extend_from_slice(&[0, 0]);
+        data.extend_from_slice(&pid.to_le_bytes());
+        data
+    }
+
+    #[test]
+    fn parse_and_decode() {
+        let format = TracepointFormat::parse(SCHED_SWITCH).unwrap();
+        assert_eq!((format.name.as_str(), format.id), ("sched_switch", 316));
+        assert_eq!(format.fields.len(), 11);
+        assert_eq!(format.event_fields().count(), 7);
+        let prev_comm = format.field("prev_comm").unwrap();
+        assert_eq!(prev_comm.type_name, "char");
+        assert_eq!(prev_comm.kind, TracepointFieldKind::Array { len: 16 });
+
+        let mut data = common_header(316, 10);
+        data.extend_from_slice(b"bash\0\0\0\0\0\0\0\0\0\0\0\0");
+        data.extend_from_slice(&10i32.to_le_bytes());
+        data.extend_from_slice(&120i32.to_le_bytes());
+        data.extend_from_slice(&(-1i64).to_le_bytes());
+        data.extend_from_slice(b"swapper/0\0\0\0\0\0\0\0");
+        data.extend_from_slice(&0i32.to_le_bytes());
+        data.extend_from_slice(&120i32.to_le_bytes());
+        let values = format.decode(&data, Endianness::LittleEndian).unwrap();
+        assert_eq!(values[4], ("prev_comm", TracepointValue::Str(b"bash")));
+        assert_eq!(values[7], ("prev_state", TracepointValue::Signed(-1)));
+        assert_eq!(values[8], ("next_comm", TracepointValue::Str(b"swapper/0")));
+        assert!(format
+            .decode(&data[..50], Endianness::LittleEndian)
+            .is_err());
+
+        let format = TracepointFormat::parse(SCHED_PROCESS_EXEC).unwrap();
+        let mut data = common_header(311, 20);
+        data.extend_from_slice(&(20u32 | (8 << 16)).to_le_bytes());
+        data.extend_from_slice(&20i32.to_le_bytes());
+        data.extend_from_slice(&20i32.to_le_bytes());
+        data.extend_from_slice(b"/bin/ls\0");
+        assert_eq!(
+            format
+                .decode_field("filename", &data, Endianness::LittleEndian)
+                .unwrap(),
+            Some(TracepointValue::Str(b"/bin/ls"))
+        );
+
+        let format = TracepointFormat::parse(FOO_REL_LOC).unwrap();
+        let mut data = common_header(1520, 30);
+        // The string starts at 40, which is 28 bytes after the end of `foo`.
+        data.extend_from_slice(&(28u32 | (4 << 16)).to_le_bytes());
+        data.extend_from_slice(&[0; 4]);
+        data.extend_from_slice(&7u64.to_le_bytes());
+        data.extend_from_slice(&1u64.to_le_bytes());
+        data.extend_from_slice(&2u64.to_le_bytes());
+        data.extend_from_slice(b"abc\0");
+        let values = format.decode(&data, Endianness::LittleEndian).unwrap();
+        assert_eq!(values[4], ("foo", TracepointValue::Str(b"abc")));
+        assert_eq!(values[5], ("bar", TracepointValue::Unsigned(7)));
+        assert_eq!(
+            values[6],
+            ("args", TracepointValue::UnsignedArray(vec![1, 2]))
+        );
+    }
+
+    #[test]
+    fn tracing_data() {
+        fn sized(data: &mut Vec<u8>, contents: &[u8]) {
+            data.extend_from_slice(&(contents.len() as u64).to_le_bytes());
+            data.extend_from_slice(contents);
+        }
+
+        let mut data = Vec::new();
+        data.extend_from_slice(b"\x17\x08\x44tracing0.6\0");
+        data.extend_from_slice(&[0, 8]);
+        data.extend_from_slice(&4096u32.to_le_bytes());
+        data.extend_from_slice(b"header_page\0");
+        sized(&mut data, b"page");
+        data.extend_from_slice(b"header_event\0");
+        sized(&mut data, b"event");
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&1u32.to_le_bytes());
+        data.extend_from_slice(b"sched\0");
+        data.extend_from_slice(&2u32.to_le_bytes());
+        sized(&mut data, SCHED_SWITCH.as_bytes());
+        sized(&mut data, SCHED_PROCESS_EXEC.as_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+        data.extend_from_slice(&0u32.to_le_bytes());
+        sized(&mut data, b"1 init\n");
+        // Padding, as in HEADER_TRACING_DATA records.
+        data.extend_from_slice(&[0; 5]);
+
+        let tracing_data = TracingData::parse(&data).unwrap();
+        assert_eq!(tracing_data.endian, Endianness::LittleEndian);
+        assert_eq!(tracing_data.page_size, 4096);
+        assert_eq!(tracing_data.saved_cmdlines, Some(&b"1 init\n"[..]));
+        let formats = tracing_data.parse_event_formats().unwrap();
+        assert_eq!(formats.len(), 2);
+        assert_eq!(formats[1].system.as_deref(), Some("sched"));
+        assert_eq!(formats[1].name, "sched_process_exec");
+    }
+
+    #[test]
+    fn versions() {
+        assert!(version_at_least(b"0.6", (0, 6)));
+        assert!(version_at_least(b"0.10", (0, 6)));
+        assert!(version_at_least(b"1.0", (0, 6)));
+        assert!(!version_at_least(b"0.5", (0, 6)));
+        assert!(!version_at_least(b"garbage", (0, 6)));
+    }
+
+    #[test]
+    fn field_offset_overflow() {
+        let field = TracepointField::parse(&format!(
+            "field:int foo; offset:{}; size:4; signed:1;",
+            usize::MAX
+        ))
+        .unwrap();
+        assert_eq!(
+            field.decode(&[0; 16], Endianness::LittleEndian),
+            Err(TracepointFormatError::FieldOutOfBounds("foo".to_owned()))
+        );
+    }
+}
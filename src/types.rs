@@ -170,6 +170,21 @@ bitflags! {
         const INVALID = Self::RW.bits() | Self::X.bits();
     }
 
+    /// The flags of a `PERF_RECORD_AUX` record.
+    #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
+    pub struct AuxFlags: u64 {
+        /// Record was truncated to fit.
+        const TRUNCATED = PERF_AUX_FLAG_TRUNCATED;
+        /// Snapshot from overwrite mode.
+        const OVERWRITE = PERF_AUX_FLAG_OVERWRITE;
+        /// Record contains gaps.
+        const PARTIAL = PERF_AUX_FLAG_PARTIAL;
+        /// Sample collided with another.
+        const COLLISION = PERF_AUX_FLAG_COLLISION;
+        /// PMU specific trace format type.
+        const PMU_FORMAT_TYPE_MASK = PERF_AUX_FLAG_PMU_FORMAT_TYPE_MASK;
+    }
+
     /// The format of the data returned by read() on a perf event fd,
     /// as specified by attr.read_format:
     ///
@@ -179,6 +194,7 @@ bitflags! {
     /// 	  { u64 time_enabled; } && PERF_FORMAT_TOTAL_TIME_ENABLED
     /// 	  { u64 time_running; } && PERF_FORMAT_TOTAL_TIME_RUNNING
     /// 	  { u64 id;           } && PERF_FORMAT_ID
+    /// 	  { u64 lost;         } && PERF_FORMAT_LOST
     /// 	} && !PERF_FORMAT_GROUP
     ///
     /// 	{ u64 nr;
@@ -186,16 +202,29 @@ bitflags! {
     /// 	  { u64 time_running; } && PERF_FORMAT_TOTAL_TIME_RUNNING
     /// 	  { u64 value;
     /// 	    { u64	id;           } && PERF_FORMAT_ID
+    /// 	    { u64	lost;         } && PERF_FORMAT_LOST
     /// 	  } cntr[nr];
     /// 	} && PERF_FORMAT_GROUP
     /// };
     /// ```
+    ///
+    /// This data can be decoded with [`ReadFormatValues::parse`](crate::ReadFormatValues::parse).
     #[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
     pub struct ReadFormat: u64 {
         const TOTAL_TIME_ENABLED = PERF_FORMAT_TOTAL_TIME_ENABLED;
         const TOTAL_TIME_RUNNING = PERF_FORMAT_TOTAL_TIME_RUNNING;
         const ID = PERF_FORMAT_ID;
         const GROUP = PERF_FORMAT_GROUP;
+        /// The number of lost samples for this event. (since Linux 6.0)
+        const LOST = PERF_FORMAT_LOST;
+    }
+}
+
+impl AuxFlags {
+    /// The PMU specific trace format type, e.g. to distinguish between
+    /// CoreSight formatted and raw trace data.
+    pub fn pmu_format_type(&self) -> u8 {
+        ((self.bits() & Self::PMU_FORMAT_TYPE_MASK.bits()) >> 8) as u8
     }
 }
 
@@ -259,6 +288,22 @@ impl ClockId {
             _ => return None,
         })
     }
+
+    /// The `clockid_t` value of this clock, as used in the `clockid` attr field.
+    pub fn to_u32(&self) -> u32 {
+        match self {
+            Self::Realtime => 0,
+            Self::Monotonic => 1,
+            Self::ProcessCputimeId => 2,
+            Self::ThreadCputimeId => 3,
+            Self::MonotonicRaw => 4,
+            Self::RealtimeCoarse => 5,
+            Self::MonotonicCoarse => 6,
+            Self::Boottime => 7,
+            Self::RealtimeAlarm => 8,
+            Self::BoottimeAlarm => 9,
+        }
+    }
 }
 
 #[derive(Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash)]
@@ -288,6 +333,27 @@ impl RecordType {
     pub const TEXT_POKE: Self = Self(PERF_RECORD_TEXT_POKE);
     pub const AUX_OUTPUT_HW_ID: Self = Self(PERF_RECORD_AUX_OUTPUT_HW_ID);
 
+    // User record types, synthesized by the perf tool
+    pub const HEADER_ATTR: Self = Self(PERF_RECORD_HEADER_ATTR);
+    pub const HEADER_EVENT_TYPE: Self = Self(PERF_RECORD_HEADER_EVENT_TYPE);
+    pub const HEADER_TRACING_DATA: Self = Self(PERF_RECORD_HEADER_TRACING_DATA);
+    pub const HEADER_BUILD_ID: Self = Self(PERF_RECORD_HEADER_BUILD_ID);
+    pub const FINISHED_ROUND: Self = Self(PERF_RECORD_FINISHED_ROUND);
+    pub const ID_INDEX: Self = Self(PERF_RECORD_ID_INDEX);
+    pub const AUXTRACE_INFO: Self = Self(PERF_RECORD_AUXTRACE_INFO);
+    pub const AUXTRACE: Self = Self(PERF_RECORD_AUXTRACE);
+    pub const AUXTRACE_ERROR: Self = Self(PERF_RECORD_AUXTRACE_ERROR);
+    pub const THREAD_MAP: Self = Self(PERF_RECORD_THREAD_MAP);
+    pub const CPU_MAP: Self = Self(PERF_RECORD_CPU_MAP);
+    pub const STAT_CONFIG: Self = Self(PERF_RECORD_STAT_CONFIG);
+    pub const STAT: Self = Self(PERF_RECORD_STAT);
+    pub const STAT_ROUND: Self = Self(PERF_RECORD_STAT_ROUND);
+    pub const EVENT_UPDATE: Self = Self(PERF_RECORD_EVENT_UPDATE);
+    pub const TIME_CONV: Self = Self(PERF_RECORD_TIME_CONV);
+    pub const HEADER_FEATURE: Self = Self(PERF_RECORD_HEADER_FEATURE);
+    pub const COMPRESSED: Self = Self(PERF_RECORD_COMPRESSED);
+    pub const FINISHED_INIT: Self = Self(PERF_RECORD_FINISHED_INIT);
+
     pub fn is_builtin_type(&self) -> bool {
         self.0 < PERF_RECORD_USER_TYPE_START
     }
@@ -321,6 +387,25 @@ impl std::fmt::Debug for RecordType {
             Self::CGROUP => "CGROUP",
             Self::TEXT_POKE => "TEXT_POKE",
             Self::AUX_OUTPUT_HW_ID => "AUX_OUTPUT_HW_ID",
+            Self::HEADER_ATTR => "HEADER_ATTR",
+            Self::HEADER_EVENT_TYPE => "HEADER_EVENT_TYPE",
+            Self::HEADER_TRACING_DATA => "HEADER_TRACING_DATA",
+            Self::HEADER_BUILD_ID => "HEADER_BUILD_ID",
+            Self::FINISHED_ROUND => "FINISHED_ROUND",
+            Self::ID_INDEX => "ID_INDEX",
+            Self::AUXTRACE_INFO => "AUXTRACE_INFO",
+            Self::AUXTRACE => "AUXTRACE",
+            Self::AUXTRACE_ERROR => "AUXTRACE_ERROR",
+            Self::THREAD_MAP => "THREAD_MAP",
+            Self::CPU_MAP => "CPU_MAP",
+            Self::STAT_CONFIG => "STAT_CONFIG",
+            Self::STAT => "STAT",
+            Self::STAT_ROUND => "STAT_ROUND",
+            Self::EVENT_UPDATE => "EVENT_UPDATE",
+            Self::TIME_CONV => "TIME_CONV",
+            Self::HEADER_FEATURE => "HEADER_FEATURE",
+            Self::COMPRESSED => "COMPRESSED",
+            Self::FINISHED_INIT => "FINISHED_INIT",
             other if self.is_builtin_type() => {
                 return fmt.write_fmt(format_args!("Unknown built-in: {}", other.0));
             }
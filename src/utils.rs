@@ -6,12 +6,3 @@ impl fmt::Debug for HexValue {
         write!(fmt, "0x{:016X}", self.0)
     }
 }
-
-pub struct HexSlice<'a>(pub &'a [u64]);
-impl fmt::Debug for HexSlice<'_> {
-    fn fmt(&self, fmt: &mut fmt::Formatter) -> Result<(), fmt::Error> {
-        fmt.debug_list()
-            .entries(self.0.iter().map(|&value| HexValue(value)))
-            .finish()
-    }
-}